@@ -2,5 +2,5 @@
 
 make && \
 rustup run nightly cargo clippy && \
-RUST_TEST_THREADS=1 cargo test -- --nocapture && make test && cargo run -- Calc
+RUST_TEST_THREADS=1 cargo test -- --nocapture && make test && cargo run -- -cp ./classes Calc
 # cargo run
@@ -0,0 +1,30 @@
+// a call heavy benchmark, which prints the running time in milliseconds
+// run it with: make bench
+public class Benchmark {
+    private static int fib(int n) {
+        if (n < 2) {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+
+    private static void print(long x) {
+        if (x >= 10) {
+            print(x / 10);
+        }
+        System.out.print((char) ('0' + x % 10));
+    }
+
+    public static void main(String[] args) {
+        long start = System.currentTimeMillis();
+        int result = 0;
+        for (int i = 0; i < 5; i++) {
+            result += fib(25);
+        }
+        long time = System.currentTimeMillis() - start;
+        print(result);
+        System.out.print(' ');
+        print(time);
+        System.out.println(" ms");
+    }
+}
@@ -0,0 +1,20 @@
+package java.lang;
+
+public final class Byte {
+    public static final byte MIN_VALUE = -128;
+    public static final byte MAX_VALUE = 127;
+
+    private final byte value;
+
+    public Byte(byte value) {
+        this.value = value;
+    }
+
+    public static Byte valueOf(byte value) {
+        return new Byte(value);
+    }
+
+    public byte byteValue() {
+        return value;
+    }
+}
@@ -0,0 +1,20 @@
+package java.lang;
+
+public final class Character {
+    public static final char MIN_VALUE = '\u0000';
+    public static final char MAX_VALUE = '\uffff';
+
+    private final char value;
+
+    public Character(char value) {
+        this.value = value;
+    }
+
+    public static Character valueOf(char value) {
+        return new Character(value);
+    }
+
+    public char charValue() {
+        return value;
+    }
+}
@@ -0,0 +1,17 @@
+package java.lang;
+
+public final class Class {
+    // the instances are created by the vm, the name is cached like in the OpenJDK
+    private String name;
+
+    private Class() {}
+
+    public String getName() {
+        if (name == null) {
+            name = getName0();
+        }
+        return name;
+    }
+
+    private native String getName0();
+}
@@ -0,0 +1,35 @@
+package java.lang;
+
+public final class Double {
+    public static final double POSITIVE_INFINITY = 1.0 / 0.0;
+    public static final double NEGATIVE_INFINITY = -1.0 / 0.0;
+    public static final double NaN = 0.0d / 0.0;
+    public static final double MAX_VALUE = 0x1.fffffffffffffP+1023;
+    public static final double MIN_VALUE = 0x0.0000000000001P-1022;
+
+    private final double value;
+
+    public Double(double value) {
+        this.value = value;
+    }
+
+    public static Double valueOf(double value) {
+        return new Double(value);
+    }
+
+    public double doubleValue() {
+        return value;
+    }
+
+    public static native long doubleToRawLongBits(double value);
+
+    public static native double longBitsToDouble(long bits);
+
+    // all NaNs have the same bits
+    public static long doubleToLongBits(double value) {
+        if (value != value) {
+            return 0x7ff8000000000000L;
+        }
+        return doubleToRawLongBits(value);
+    }
+}
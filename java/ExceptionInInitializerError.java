@@ -0,0 +1,9 @@
+package java.lang;
+
+public class ExceptionInInitializerError extends LinkageError {
+    private Throwable exception;
+
+    public Throwable getException() {
+        return exception;
+    }
+}
@@ -1,10 +1,30 @@
 
 
+interface FieldsAndMethodsInterface {
+    long interfaceStatic = FieldsAndMethodsOther.otherStatic * 2;
+}
+
+class FieldsAndMethodsOther {
+    static long otherStatic = 99;
+
+    static {
+        FieldsAndMethods.dump_char4('O', 'I', '!', '\n');
+    }
+}
+
 class FieldsAndMethodsSuper {
     protected int instanceA = 1;
     protected int instanceB = 200;
     protected short superC = 300;
 
+    protected static int superStatic = 5;
+    protected static long superStaticInit;
+
+    static {
+        FieldsAndMethods.dump_char4('S', 'I', '!', '\n');
+        superStaticInit = 1234;
+    }
+
     public FieldsAndMethodsSuper(int a) {
         instanceA = a * 100;
     }
@@ -41,12 +61,16 @@ class FieldsAndMethodsSuper {
     }
 }
 
-public class FieldsAndMethods extends FieldsAndMethodsSuper {
+public class FieldsAndMethods extends FieldsAndMethodsSuper implements FieldsAndMethodsInterface {
 
     private int instanceA = 1;
     private long instanceB = 2;
     private short instanceC = 3;
 
+    private static int staticA = 7;
+    private static short staticC;
+    private static final int CONSTANT = 42;
+
     public FieldsAndMethods(int a) {
         super(a * 2);
         instanceA = a;
@@ -153,13 +177,26 @@ public class FieldsAndMethods extends FieldsAndMethodsSuper {
         m.changeArray(array);
         m.printArray(array);
 
-        // TODO test static variables
-        // of current class
-        // of super class
-        // of other class
-        // of interface
-        // with constant initialization
-        // with static initialization method
+        // test static variables
+        dump_longln(staticA);
+        staticA *= 3;
+        dump_longln(staticA);
+        staticC = 300;
+        staticC *= 2;
+        dump_longln(staticC);
+        dump_longln(CONSTANT);
+
+        dump_longln(superStatic);
+        superStatic += 1;
+        dump_longln(FieldsAndMethodsSuper.superStatic);
+        dump_longln(superStaticInit);
+
+        dump_longln(interfaceStatic);
+        dump_longln(FieldsAndMethodsOther.otherStatic);
+        FieldsAndMethodsOther.otherStatic = 1;
+        dump_longln(FieldsAndMethodsOther.otherStatic);
+        dump_longln(interfaceStatic);
+
         // TODO test interface methods
     }
 
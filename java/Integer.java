@@ -0,0 +1,20 @@
+package java.lang;
+
+public final class Integer {
+    public static final int MIN_VALUE = 0x80000000;
+    public static final int MAX_VALUE = 0x7fffffff;
+
+    private final int value;
+
+    public Integer(int value) {
+        this.value = value;
+    }
+
+    public static Integer valueOf(int value) {
+        return new Integer(value);
+    }
+
+    public int intValue() {
+        return value;
+    }
+}
@@ -0,0 +1,20 @@
+package java.lang;
+
+public final class Long {
+    public static final long MIN_VALUE = 0x8000000000000000L;
+    public static final long MAX_VALUE = 0x7fffffffffffffffL;
+
+    private final long value;
+
+    public Long(long value) {
+        this.value = value;
+    }
+
+    public static Long valueOf(long value) {
+        return new Long(value);
+    }
+
+    public long longValue() {
+        return value;
+    }
+}
@@ -1,3 +1,19 @@
 package java.lang;
 
-public class Object {}
+public class Object {
+    public native int hashCode();
+
+    public final native Class getClass();
+
+    protected native Object clone() throws CloneNotSupportedException;
+
+    public final native void notify();
+
+    public final native void notifyAll();
+
+    public final native void wait(long timeout) throws InterruptedException;
+
+    public final void wait() throws InterruptedException {
+        wait(0);
+    }
+}
@@ -0,0 +1,39 @@
+package java.util;
+
+// the properties are kept in parallel arrays, there is no Hashtable yet
+public class Properties {
+    private String[] keys = new String[16];
+    private String[] values = new String[16];
+    private int size;
+
+    public String getProperty(String key) {
+        for (int i = 0; i < size; i++) {
+            if (keys[i].equals(key)) {
+                return values[i];
+            }
+        }
+        return null;
+    }
+
+    public Object setProperty(String key, String value) {
+        for (int i = 0; i < size; i++) {
+            if (keys[i].equals(key)) {
+                String old = values[i];
+                values[i] = value;
+                return old;
+            }
+        }
+        if (size == keys.length) {
+            String[] newKeys = new String[size * 2];
+            String[] newValues = new String[size * 2];
+            System.arraycopy(keys, 0, newKeys, 0, size);
+            System.arraycopy(values, 0, newValues, 0, size);
+            keys = newKeys;
+            values = newValues;
+        }
+        keys[size] = key;
+        values[size] = value;
+        size++;
+        return null;
+    }
+}
@@ -0,0 +1,20 @@
+package java.lang;
+
+public final class Short {
+    public static final short MIN_VALUE = -32768;
+    public static final short MAX_VALUE = 32767;
+
+    private final short value;
+
+    public Short(short value) {
+        this.value = value;
+    }
+
+    public static Short valueOf(short value) {
+        return new Short(value);
+    }
+
+    public short shortValue() {
+        return value;
+    }
+}
@@ -0,0 +1,25 @@
+package java.lang;
+
+public final class StackTraceElement {
+    // the elements are created by the vm, which sets the fields directly
+    private String fileName;
+    private int lineNumber;
+    private String declaringClass;
+    private String methodName;
+
+    public String getFileName() {
+        return fileName;
+    }
+
+    public int getLineNumber() {
+        return lineNumber;
+    }
+
+    public String getClassName() {
+        return declaringClass;
+    }
+
+    public String getMethodName() {
+        return methodName;
+    }
+}
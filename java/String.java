@@ -0,0 +1,55 @@
+package java.lang;
+
+public final class String {
+    // string literals are created by the vm, which sets these fields directly
+    private char[] value;
+    private int count;
+    private int offset;
+
+    public String(char[] data) {
+        value = new char[data.length];
+        for (int i = 0; i < data.length; i++) {
+            value[i] = data[i];
+        }
+        count = data.length;
+    }
+
+    public String(String original) {
+        value = original.value;
+        count = original.count;
+        offset = original.offset;
+    }
+
+    public native String intern();
+
+    public int length() {
+        return count;
+    }
+
+    public char charAt(int index) {
+        if (index < 0 || index >= count) {
+            throw new StringIndexOutOfBoundsException();
+        }
+        return value[offset + index];
+    }
+
+    public boolean equals(Object other) {
+        if (!(other instanceof String)) {
+            return false;
+        }
+        String string = (String) other;
+        if (string.count != count) {
+            return false;
+        }
+        for (int i = 0; i < count; i++) {
+            if (charAt(i) != string.charAt(i)) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    public String toString() {
+        return this;
+    }
+}
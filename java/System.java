@@ -0,0 +1,28 @@
+package java.lang;
+
+import java.io.OutputStream;
+import java.io.PrintStream;
+import java.util.Properties;
+
+public final class System {
+    public static final PrintStream out = new PrintStream((OutputStream) null);
+
+    public static native void arraycopy(Object src, int srcPos, Object dest, int destPos, int length);
+
+    public static native long currentTimeMillis();
+
+    public static native long nanoTime();
+
+    public static native int identityHashCode(Object x);
+
+    private static Properties properties;
+
+    // the properties are set by the vm, when they are requested the first time
+    public static String getProperty(String key) {
+        if (properties == null) {
+            properties = new Properties();
+            VMSystemProperties.preInit(properties);
+        }
+        return properties.getProperty(key);
+    }
+}
@@ -1,14 +1,22 @@
 package com.mackie.rustyjvm;
 
-interface TestClassInterfaceA {}
+interface TestClassInterfaceA {
+    long IL = 7L;
+}
 interface TestClassInterfaceB extends TestClassInterfaceA {}
-interface TestClassInterfaceC extends TestClassInterfaceB {}
+interface TestClassInterfaceC extends TestClassInterfaceB {
+    int interfaceMethod();
+}
+interface TestClassInterfaceD extends TestClassInterfaceC {}
 
 class TestClassSuper implements TestClassInterfaceB {
     private int a;
     private long c;
     private byte d;
     private static long sl;
+    static final int CONST = 42;
+    static final String NAME = "super";
+    static final double DOUBLE_CONST = 1.5;
 
     public void virtualMethod() {}
 }
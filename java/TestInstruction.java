@@ -188,4 +188,32 @@ public class TestInstruction {
         Interface c = null;
         c.method();
     }
+
+    void wide() {
+        int i = 0;
+        i += 1000;
+        i -= 32768;
+    }
+
+    public int switches(int i) {
+        switch(i) {
+        case 1: i = 10; break;
+        case 2: i = 20; break;
+        case 3: i = 30; break;
+        }
+        switch(i) {
+        case -100: return 1;
+        case 1000: return 2;
+        default: return i;
+        }
+    }
+
+    public int exceptions() {
+        try {
+            field = 1;
+        } catch (RuntimeException e) {
+            field = 2;
+        }
+        return field;
+    }
 }
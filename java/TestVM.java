@@ -60,6 +60,299 @@ class TestVMOther extends TestVMOtherSuper {
     }
 }
 
+interface TestVMList {
+    int size();
+    long get(int index);
+}
+
+interface TestVMGrowableList extends TestVMList {
+    void add(long value);
+}
+
+abstract class TestVMAbstractList implements TestVMGrowableList {
+    protected int size;
+
+    public int size() {
+        return size;
+    }
+}
+
+class TestVMArrayList extends TestVMAbstractList {
+    private long[] data = new long[4];
+
+    public long get(int index) {
+        return data[index];
+    }
+
+    public void add(long value) {
+        data[size] = value;
+        size += 1;
+    }
+}
+
+class TestVMSingletonList implements TestVMList {
+    public int size() {
+        return 1;
+    }
+
+    public long get(int index) {
+        TestVM.nativeInt(index);
+        return 42;
+    }
+}
+
+class TestVMCaller {
+    static Class caller() {
+        return gnu.classpath.VMStackWalker.getCallingClass();
+    }
+}
+
+class TestVMStrings {
+    static String literal() {
+        return "Hallo";
+    }
+}
+
+class TestVMNode {
+    static TestVMNode root;
+
+    TestVMNode next;
+    int value;
+    int[] data;
+
+    TestVMNode(TestVMNode next, int value, int[] data) {
+        this.next = next;
+        this.value = value;
+        this.data = data;
+    }
+}
+
+class TestVMCounter implements Runnable {
+    static Object lock = new Object();
+    static int count;
+    static int racyCount;
+
+    private int increments;
+
+    TestVMCounter(int increments) {
+        this.increments = increments;
+    }
+
+    public void run() {
+        for (int i = 0; i < increments; i++) {
+            synchronized (lock) {
+                int c = count;
+                count = c + 1;
+            }
+            int c = racyCount;
+            racyCount = c + 1;
+        }
+    }
+}
+
+class TestVMQueue {
+    private int value;
+    private boolean full;
+
+    synchronized void put(int v) throws InterruptedException {
+        while (full) {
+            wait();
+        }
+        value = v;
+        full = true;
+        notifyAll();
+    }
+
+    synchronized int take() throws InterruptedException {
+        while (!full) {
+            wait();
+        }
+        full = false;
+        notifyAll();
+        return value;
+    }
+}
+
+class TestVMProducer extends Thread {
+    private TestVMQueue queue;
+    private int count;
+
+    TestVMProducer(TestVMQueue queue, int count) {
+        this.queue = queue;
+        this.count = count;
+    }
+
+    public void run() {
+        try {
+            for (int i = 1; i <= count; i++) {
+                queue.put(i);
+            }
+        } catch (InterruptedException e) {
+            TestVM.nativeInt(-1);
+        }
+    }
+}
+
+class TestVMLocker implements Runnable {
+    boolean locked;
+    private Object first;
+    private Object second;
+
+    TestVMLocker(Object first, Object second) {
+        this.first = first;
+        this.second = second;
+    }
+
+    public void run() {
+        synchronized (first) {
+            locked = true;
+            synchronized (second) {
+                TestVM.nativeInt(-1);
+            }
+        }
+    }
+}
+
+class TestVMStack {
+    int i;
+    long l;
+    double d;
+    static long sl;
+    static double sd;
+
+    static long longValue() {
+        TestVM.nativeInt(1);
+        return 1L;
+    }
+    static double doubleValue() {
+        TestVM.nativeInt(2);
+        return 2.0;
+    }
+}
+
+class TestVMArgs {
+    public static void main(String[] args) {
+        TestVM.nativeInt(args.length);
+        for (int i = 0; i < args.length; i++) {
+            TestVM.nativeInt(args[i].length());
+            for (int j = 0; j < args[i].length(); j++) {
+                TestVM.nativeChar(args[i].charAt(j));
+            }
+        }
+        // arguments are not interned
+        TestVM.nativeBoolean(args[0] == "ab");
+    }
+}
+
+class TestVMException extends RuntimeException {
+    public int value;
+    public TestVMException(int value) {
+        this.value = value;
+    }
+}
+
+class TestVMOtherException extends RuntimeException {}
+
+class TestVMError extends Error {}
+
+class TestVMStaticSuper {
+    static int superValue = init(1);
+    static long longValue;
+
+    static {
+        TestVM.nativeInt(2);
+        // the subclass is already being initialized, so its initializer doesn't run here
+        TestVM.nativeInt(TestVMStatic.value);
+    }
+
+    static int init(int value) {
+        TestVM.nativeInt(value);
+        return value;
+    }
+}
+
+class TestVMStatic extends TestVMStaticSuper {
+    static int value = init(3);
+    static double doubleValue = 1.5;
+}
+
+class TestVMStaticNew {
+    static {
+        TestVM.nativeInt(20);
+    }
+}
+
+class TestVMStaticInvoke {
+    static {
+        TestVM.nativeInt(30);
+    }
+
+    static void method() {
+        TestVM.nativeInt(31);
+    }
+}
+
+interface TestVMStaticInterface {
+    int value = TestVMStaticSuper.init(40);
+}
+
+class TestVMStaticImpl implements TestVMStaticInterface {
+    static {
+        TestVM.nativeInt(-1);
+    }
+}
+
+class TestVMStaticFailing {
+    static int value = fail();
+
+    static int fail() {
+        TestVM.nativeInt(50);
+        throw new TestVMException(50);
+    }
+}
+
+class TestVMStaticFailingSub extends TestVMStaticFailing {
+    static {
+        TestVM.nativeInt(-1);
+    }
+
+    static void method() {
+        TestVM.nativeInt(-1);
+    }
+}
+
+// the initializer blocks, while other threads use the class
+class TestVMStaticSlow {
+    static int value;
+
+    static {
+        TestVMStaticReader.initializing = true;
+        try {
+            Thread.sleep(20);
+        } catch (InterruptedException e) {
+            TestVM.nativeInt(-1);
+        }
+        value = 60;
+    }
+}
+
+class TestVMStaticReader implements Runnable {
+    static boolean initializing;
+    int value;
+
+    public void run() {
+        value = TestVMStaticSlow.value;
+    }
+}
+
+class TestVMStaticError {
+    static int value = fail();
+
+    static int fail() {
+        throw new TestVMError();
+    }
+}
+
 public class TestVM extends TestVMSuper implements TestVMInterfaceD {
     public static native void nativeBoolean(boolean i);
     public static native void nativeChar(char i);
@@ -100,6 +393,50 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         vm.privateMethod();
     }
 
+    public static void quickened() {
+        TestVMSuper[] objects = { new TestVMSuper(1), new TestVM(2, 2), new TestVMSuper(3) };
+        TestVMStack stack = new TestVMStack();
+        for (int i = 0; i < objects.length; i++) {
+            // the instructions are quickened by the first iteration, the invoked method depends on the object
+            nativeLong(objects[i].virtualMethod(i));
+            stack.l += objects[i].superInt;
+            TestVMStack.sd += 0.5;
+        }
+        nativeLong(stack.l);
+        nativeDouble(TestVMStack.sd);
+        TestVMSuper nothing = null;
+        for (int i = 0; i < 2; i++) {
+            try {
+                nothing.virtualMethod(0);
+            } catch (NullPointerException e) {
+                nativeInt(i);
+            }
+        }
+        TestVMList[] lists = { new TestVMArrayList(), new TestVMSingletonList() };
+        for (int i = 0; i < lists.length; i++) {
+            nativeInt(lists[i].size());
+        }
+        TestVMList none = null;
+        for (int i = 0; i < 2; i++) {
+            try {
+                none.size();
+            } catch (NullPointerException e) {
+                nativeInt(i);
+            }
+        }
+    }
+
+    public static void vtables() {
+        TestVTable sub = new TestVTableSub();
+        nativeInt(sub.a());
+        nativeInt(TestVTable.callB(sub));
+        nativeInt(com.mackie.rustyjvm.other.TestVTableOther.callB((com.mackie.rustyjvm.other.TestVTableOther) sub));
+        // the call sites are quickened, the methods are selected by the index in the vtable of the object
+        nativeInt(TestVTable.callB(new com.mackie.rustyjvm.other.TestVTableOther()));
+        nativeInt(TestVTable.callB(new TestVTable()));
+        nativeInt(sub.c());
+    }
+
     public static double staticMethod(double a) {
         nativeInt(1);
         nativeDouble(a);
@@ -269,7 +606,6 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         a = -1;
         nativeInt(0x80000000 / -1);
         nativeInt(0x80000000 / a);
-        // TODO test divide by 0
         long l = 4L;
         nativeLong(6L / 4L);
         nativeLong(6L / l);
@@ -278,7 +614,6 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         l = -1;
         nativeLong(0x8000000000000000L / -1L);
         nativeLong(0x8000000000000000L / l);
-        // TODO test divide by 0
 
         float f = 0.1f;
         nativeFloat(0.1f / 2f);
@@ -298,7 +633,6 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         a = -1;
         nativeInt(0x80000000 % -1);
         nativeInt(0x80000000 % a);
-        // TODO test divide by 0
         long l = 4L;
         nativeLong(6L % 4L);
         nativeLong(6L % l);
@@ -307,7 +641,6 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         l = -1;
         nativeLong(0x8000000000000000L % -1L);
         nativeLong(0x8000000000000000L % l);
-        // TODO test divide by 0
 
         float f = 2.1f;
         nativeFloat(2.1f % 2f);
@@ -634,4 +967,613 @@ public class TestVM extends TestVMSuper implements TestVMInterfaceD {
         nativeLong(a.superLong);
         nativeInt(a.superInt);
     }
+
+    private static void throwException(int value) {
+        nativeInt(value);
+        throw new TestVMException(value);
+    }
+
+    private static long throwLong() {
+        throw new TestVMException(0);
+    }
+
+    private static void exceptions() {
+        try {
+            nativeInt(1);
+            throw new TestVMException(2);
+        } catch (TestVMException e) {
+            nativeInt(e.value);
+        }
+
+        // thrown in another method
+        try {
+            throwException(3);
+            nativeInt(-1);
+        } catch (TestVMException e) {
+            nativeInt(e.value + 1);
+        }
+
+        // the inner handler doesn't match, caught by superclass
+        try {
+            try {
+                throwException(5);
+            } catch (TestVMOtherException e) {
+                nativeInt(-1);
+            }
+            nativeInt(-1);
+        } catch (RuntimeException e) {
+            nativeInt(6);
+        }
+
+        try {
+            try {
+                throwException(7);
+            } finally {
+                nativeInt(8);
+            }
+        } catch (Throwable e) {
+            nativeInt(9);
+        }
+
+        // throw inside of a handler
+        try {
+            try {
+                throwException(10);
+            } catch (TestVMException e) {
+                throw new TestVMOtherException();
+            }
+        } catch (TestVMOtherException e) {
+            nativeInt(11);
+        }
+
+        // the stack must be cleared before entering the handler
+        long l = 12;
+        try {
+            nativeLong(l + throwLong());
+        } catch (TestVMException e) {
+            nativeLong(l);
+        }
+    }
+
+    private static int divide(int a, int b) {
+        return a / b;
+    }
+
+    private static void vmExceptions() {
+        int[] array = null;
+        TestVM vm = null;
+        try { array[0] = 1; } catch (NullPointerException e) { nativeInt(1); }
+        try { nativeInt(array[0]); } catch (NullPointerException e) { nativeInt(2); }
+        try { nativeInt(array.length); } catch (NullPointerException e) { nativeInt(3); }
+        try { nativeInt(vm.intField); } catch (NullPointerException e) { nativeInt(4); }
+        try { vm.intField = 1; } catch (NullPointerException e) { nativeInt(5); }
+        try { vm.virtualMethod(1); } catch (NullPointerException e) { nativeInt(6); }
+        try { vm.privateMethod(); } catch (NullPointerException e) { nativeInt(7); }
+        try { throw null; } catch (NullPointerException e) { nativeInt(8); }
+
+        array = new int[2];
+        try { array[2] = 1; } catch (ArrayIndexOutOfBoundsException e) { nativeInt(9); }
+        try { nativeInt(array[-1]); } catch (ArrayIndexOutOfBoundsException e) { nativeInt(10); }
+        long[] longArray = new long[1];
+        try { longArray[1] = 1; } catch (ArrayIndexOutOfBoundsException e) { nativeInt(11); }
+        try { nativeLong(longArray[1]); } catch (ArrayIndexOutOfBoundsException e) { nativeInt(12); }
+
+        int zero = 0;
+        long zeroLong = 0;
+        try { nativeInt(1 / zero); } catch (ArithmeticException e) { nativeInt(13); }
+        try { nativeInt(1 % zero); } catch (ArithmeticException e) { nativeInt(14); }
+        try { nativeLong(1L / zeroLong); } catch (ArithmeticException e) { nativeInt(15); }
+        try { nativeLong(1L % zeroLong); } catch (ArithmeticException e) { nativeInt(16); }
+        // only the lower word is zero
+        nativeLong(0x100000000L / (zeroLong + 0x100000000L));
+
+        Object o = new TestVMSuper(1);
+        try { vm = (TestVM) o; } catch (ClassCastException e) { nativeInt(17); }
+
+        int negative = -1;
+        try { array = new int[negative]; } catch (NegativeArraySizeException e) { nativeInt(18); }
+        try { o = new Object[negative]; } catch (NegativeArraySizeException e) { nativeInt(19); }
+        try { o = new int[1][negative]; } catch (NegativeArraySizeException e) { nativeInt(20); }
+
+        try { divide(1, zero); } catch (RuntimeException e) { nativeInt(21); }
+
+        // arrays are covariant, so the component type is checked, when an element is stored
+        TestVMNode[] nodes = new TestVMNode[1];
+        Object[] objects = nodes;
+        try { objects[0] = "node"; } catch (ArrayStoreException e) { nativeInt(22); }
+        objects[0] = null;
+        nativeBoolean(nodes[0] == null);
+        try { objects[1] = "node"; } catch (ArrayIndexOutOfBoundsException e) { nativeInt(23); }
+    }
+
+    private static void statics() {
+        nativeInt(0);
+        // initializes the superclass first
+        nativeInt(TestVMStatic.value);
+        nativeInt(TestVMStatic.superValue);
+        TestVMStatic.superValue = 10;
+        nativeInt(TestVMStaticSuper.superValue);
+        TestVMStaticSuper.longValue = 0x100000001L;
+        nativeLong(TestVMStatic.longValue);
+        nativeDouble(TestVMStatic.doubleValue);
+        TestVMStatic.doubleValue *= 2;
+        nativeDouble(TestVMStatic.doubleValue);
+
+        new TestVMStaticNew();
+        new TestVMStaticNew();
+        nativeInt(21);
+
+        TestVMStaticInvoke.method();
+        TestVMStaticInvoke.method();
+
+        // only the interface, which declares the field, is initialized
+        nativeInt(TestVMStaticImpl.value);
+        nativeInt(TestVMStaticImpl.value);
+
+        try {
+            nativeInt(TestVMStaticFailing.value);
+        } catch (ExceptionInInitializerError e) {
+            nativeBoolean(e.getException() instanceof TestVMException);
+        }
+        try {
+            nativeInt(TestVMStaticFailing.value);
+        } catch (NoClassDefFoundError e) {
+            nativeInt(51);
+        }
+        try {
+            TestVMStaticFailingSub.method();
+        } catch (NoClassDefFoundError e) {
+            nativeInt(52);
+        }
+
+        // errors are not wrapped
+        try {
+            nativeInt(TestVMStaticError.value);
+        } catch (TestVMError e) {
+            nativeInt(60);
+        }
+        try {
+            nativeInt(TestVMStaticError.value);
+        } catch (NoClassDefFoundError e) {
+            nativeInt(61);
+        }
+    }
+
+    private static long sum(TestVMList list) {
+        long sum = 0;
+        for (int i = 0; i < list.size(); i++) {
+            sum += list.get(i);
+        }
+        return sum;
+    }
+
+    private static void interfaces() {
+        TestVMGrowableList list = new TestVMArrayList();
+        list.add(1);
+        list.add(2);
+        list.add(3);
+        nativeInt(list.size());
+        nativeLong(sum(list));
+        list.add(4);
+        nativeLong(sum(list));
+        nativeLong(sum(new TestVMSingletonList()));
+
+        TestVMList nullList = null;
+        try {
+            nullList.size();
+        } catch (NullPointerException e) {
+            nativeInt(1);
+        }
+    }
+
+    private static void strings() {
+        String a = "Hallo";
+        String b = "Hallo";
+        // literals are interned
+        nativeBoolean(a == b);
+        nativeBoolean(a == TestVMStrings.literal());
+        nativeBoolean(a == "Hallo!");
+        String c = new String(new char[] {'H', 'a', 'l', 'l', 'o'});
+        nativeBoolean(a == c);
+        nativeBoolean(a.equals(c));
+        nativeBoolean(a.equals("Hallo!"));
+        nativeInt(a.length());
+        nativeChar(a.charAt(1));
+
+        // embedded null, surrogate pair, unpaired surrogate and umlaut
+        String special = "a\0\uD83D\uDE00\uD800\u00E4";
+        nativeInt(special.length());
+        for (int i = 0; i < special.length(); i++) {
+            nativeChar(special.charAt(i));
+        }
+
+        try {
+            a.charAt(5);
+        } catch (StringIndexOutOfBoundsException e) {
+            nativeInt(1);
+        }
+    }
+
+    private static final int RED = 0;
+    private static final int GREEN = 1;
+    private static final int BLUE = 2;
+
+    // dense cases are compiled to a tableswitch
+    private static int dense(int i) {
+        switch(i) {
+        case -1: return 10;
+        case 0: return 11;
+        case 1: return 12;
+        case 3: return 13;
+        default: return 14;
+        }
+    }
+
+    // sparse cases are compiled to a lookupswitch
+    private static int sparse(int i) {
+        switch(i) {
+        case Integer.MIN_VALUE: return 20;
+        case -1000: return 21;
+        case 0: return 22;
+        case 1000: return 23;
+        case Integer.MAX_VALUE: return 24;
+        default: return 25;
+        }
+    }
+
+    private static int character(char c) {
+        int result = 0;
+        switch(c) {
+        case 'a':
+        case 'e':
+        case 'i':
+        case 'o':
+        case 'u':
+            result = 1;
+            break;
+        case 'z':
+            result = 2;
+            // fall through
+        case 'y':
+            result += 3;
+            break;
+        }
+        return result;
+    }
+
+    private static int color(int color) {
+        switch(color) {
+        case RED: return 0xFF0000;
+        case GREEN: return 0x00FF00;
+        case BLUE: return 0x0000FF;
+        }
+        return -1;
+    }
+
+    private static void switches() {
+        for (int i = -2; i < 5; i++) {
+            nativeInt(dense(i));
+        }
+        nativeInt(dense(Integer.MIN_VALUE));
+        nativeInt(dense(Integer.MAX_VALUE));
+
+        nativeInt(sparse(Integer.MIN_VALUE));
+        nativeInt(sparse(-1000));
+        nativeInt(sparse(-999));
+        nativeInt(sparse(0));
+        nativeInt(sparse(1000));
+        nativeInt(sparse(Integer.MAX_VALUE));
+        nativeInt(sparse(Integer.MAX_VALUE - 1));
+
+        nativeInt(character('a'));
+        nativeInt(character('u'));
+        nativeInt(character('b'));
+        nativeInt(character('y'));
+        nativeInt(character('z'));
+
+        nativeInt(color(RED));
+        nativeInt(color(GREEN));
+        nativeInt(color(BLUE));
+        nativeInt(color(3));
+    }
+
+    private static void stack() {
+        TestVMStack s = new TestVMStack();
+        int[] ia = new int[1];
+        long[] la = new long[1];
+        double[] da = new double[1];
+        // keeps a value below the manipulated words
+        long below = 1000L;
+
+        // DUP_X1
+        nativeInt(s.i = 3);
+        // DUP_X2
+        nativeInt(ia[0] = 4);
+        // DUP2 with a long or double
+        nativeLong(below + (TestVMStack.sl = 5L));
+        nativeDouble(TestVMStack.sd = 6.5);
+        // DUP2 with two ints
+        ia[0] += 1;
+        la[0] += 7L;
+        da[0] += 2.5;
+        nativeInt(ia[0]);
+        nativeLong(la[0]);
+        nativeDouble(da[0]);
+        // DUP2_X1
+        nativeLong(below + (s.l = 8L));
+        nativeDouble(s.d = 9.5);
+        // DUP2_X2
+        nativeLong(below + (la[0] = 10L));
+        nativeDouble(da[0] = 11.5);
+
+        // POP2
+        TestVMStack.longValue();
+        TestVMStack.doubleValue();
+
+        // chains
+        long a, b;
+        a = b = s.l = TestVMStack.sl = -1L;
+        nativeLong(a + b + s.l + TestVMStack.sl);
+        double c, d;
+        c = d = da[0] = -0.5;
+        nativeDouble(c + d + da[0]);
+
+        // post increments
+        nativeInt(ia[0]++);
+        nativeInt(ia[0]);
+        nativeLong(la[0]++);
+        nativeLong(la[0]);
+        nativeLong(s.l++);
+        nativeLong(s.l);
+        nativeDouble(da[0]--);
+        nativeDouble(da[0]);
+        nativeLong(below);
+    }
+
+    private static void garbageCollection() {
+        TestVMNode.root = new TestVMNode(null, 1, new int[1]);
+        TestVMNode list = null;
+        for (int i = 0; i < 1000; i++) {
+            list = new TestVMNode(list, i, new int[1000]);
+            // only every tenth node stays reachable
+            if (i % 10 != 0) {
+                list = list.next;
+            }
+        }
+        int sum = 0;
+        for (TestVMNode node = list; node != null; node = node.next) {
+            sum += node.value + node.data.length;
+        }
+        nativeInt(sum);
+        nativeInt(TestVMNode.root.value);
+        nativeBoolean("Hallo" == TestVMStrings.literal());
+    }
+
+    private static void outOfMemory() {
+        try {
+            int[] huge = new int[100000000];
+            nativeInt(huge.length);
+        } catch (OutOfMemoryError e) {
+            nativeInt(1);
+        }
+        TestVMNode list = null;
+        try {
+            while (true) {
+                list = new TestVMNode(list, 0, new int[1000]);
+            }
+        } catch (OutOfMemoryError e) {
+            nativeBoolean(list != null);
+            list = null;
+        }
+        // the memory of the list is freed
+        nativeInt(new int[1000].length);
+    }
+
+    private static void threads() throws InterruptedException {
+        Thread[] threads = new Thread[3];
+        for (int i = 0; i < threads.length; i++) {
+            threads[i] = new Thread(new TestVMCounter(1000));
+            threads[i].start();
+        }
+        for (int i = 0; i < threads.length; i++) {
+            threads[i].join();
+        }
+        nativeInt(TestVMCounter.count);
+        nativeBoolean(threads[0].isAlive());
+        try {
+            threads[0].start();
+        } catch (IllegalThreadStateException e) {
+            nativeInt(1);
+        }
+
+        TestVMQueue queue = new TestVMQueue();
+        new TestVMProducer(queue, 10).start();
+        int sum = 0;
+        for (int i = 0; i < 10; i++) {
+            sum += queue.take();
+        }
+        nativeInt(sum);
+
+        try {
+            queue.notify();
+        } catch (IllegalMonitorStateException e) {
+            nativeInt(2);
+        }
+        Object lock = new Object();
+        synchronized (lock) {
+            synchronized (lock) {
+                lock.wait(1);
+            }
+            lock.notifyAll();
+        }
+        long start = System.currentTimeMillis();
+        Thread.sleep(20);
+        nativeBoolean(System.currentTimeMillis() - start >= 20);
+        nativeBoolean(Thread.currentThread() == Thread.currentThread());
+    }
+
+    // the main thread waits, until the other thread initialized the class
+    private static void blockingInitialization() throws InterruptedException {
+        TestVMStaticReader reader = new TestVMStaticReader();
+        Thread thread = new Thread(reader);
+        thread.start();
+        while (!TestVMStaticReader.initializing) {
+            Thread.yield();
+        }
+        nativeInt(TestVMStaticSlow.value);
+        thread.join();
+        nativeInt(reader.value);
+    }
+
+    // the result depends on the schedule
+    private static void races() throws InterruptedException {
+        Thread first = new Thread(new TestVMCounter(100));
+        Thread second = new Thread(new TestVMCounter(100));
+        first.start();
+        second.start();
+        first.join();
+        second.join();
+        nativeInt(TestVMCounter.count);
+        nativeInt(TestVMCounter.racyCount);
+    }
+
+    // the threads lock the objects in different order and never terminate
+    private static void deadlock() {
+        Object a = new Object();
+        Object b = new Object();
+        TestVMLocker locker = new TestVMLocker(b, a);
+        new Thread(locker).start();
+        synchronized (a) {
+            while (!locker.locked) {
+                Thread.yield();
+            }
+            synchronized (b) {
+                nativeInt(-1);
+            }
+        }
+    }
+
+    private static void waitForever() throws InterruptedException {
+        Object o = new Object();
+        synchronized (o) {
+            o.wait();
+        }
+    }
+
+    private static void throwing() {
+        throw new RuntimeException("thrown");
+    }
+
+    private static void stackTraces() {
+        int line = new Throwable().getStackTrace()[0].getLineNumber();
+        try {
+            throwing();
+        } catch (RuntimeException e) {
+            StackTraceElement[] trace = e.getStackTrace();
+            nativeInt(trace.length);
+            nativeBoolean(trace[0].getMethodName() == "throwing");
+            nativeBoolean(trace[1].getMethodName() == "stackTraces");
+            nativeInt(trace[1].getLineNumber() - line);
+            nativeBoolean(trace[1].getClassName() == "com.mackie.rustyjvm.TestVM");
+            nativeBoolean(trace[1].getFileName() == "TestVM.java");
+            nativeBoolean(e.getMessage() == "thrown");
+        }
+        int zero = 0;
+        try {
+            nativeInt(1 / zero);
+        } catch (ArithmeticException e) {
+            nativeInt(e.getStackTrace().length);
+            nativeInt(e.getStackTrace()[0].getLineNumber() - line);
+        }
+    }
+
+    private static void uncaught() {
+        throwing();
+    }
+
+    private static void wrapping() {
+        try {
+            throwing();
+        } catch (RuntimeException e) {
+            throw new RuntimeException("wrapped", e);
+        }
+    }
+
+    private static void causes() {
+        wrapping();
+    }
+
+    private static void stackWalker() {
+        nativeBoolean(TestVMCaller.caller() == TestVM.class);
+    }
+
+    // the native method of the system properties calls Properties.setProperty back
+    private static void callbacks() {
+        nativeBoolean(System.getProperty("line.separator").equals("\n"));
+        nativeBoolean(System.getProperty("file.separator").equals("/"));
+        nativeBoolean(System.getProperty("unknown") == null);
+        nativeInt(System.getProperty("java.class.version").length());
+    }
+
+    private static void natives() {
+        Object o = new Object();
+        nativeBoolean(o.hashCode() == System.identityHashCode(o));
+        nativeInt(System.identityHashCode(null));
+        String s = "abc";
+        nativeBoolean(s.getClass() == "d".getClass());
+        nativeBoolean(s.getClass() != o.getClass());
+        String name = s.getClass().getName();
+        nativeInt(name.length());
+        nativeChar(name.charAt(4));
+        nativeInt(new int[0].getClass().getName().length());
+        nativeBoolean(new String(s).intern() == s);
+
+        int[] ints = {1, 2, 3, 4, 5};
+        System.arraycopy(ints, 0, ints, 1, 3);
+        nativeInt(ints[1] * 1000 + ints[2] * 100 + ints[3] * 10 + ints[4]);
+        int[] copy = (int[]) ints.clone();
+        nativeBoolean(copy != ints && copy[4] == ints[4]);
+        long[] longs = new long[2];
+        System.arraycopy(new long[] {7L, 8L}, 1, longs, 0, 1);
+        nativeLong(longs[0]);
+        Object[] objects = {"a", o, "b"};
+        String[] strings = new String[3];
+        try {
+            System.arraycopy(objects, 0, strings, 0, 3);
+        } catch (ArrayStoreException e) {
+            nativeBoolean(strings[0] == objects[0]);
+            nativeBoolean(strings[1] == null);
+        }
+        try {
+            System.arraycopy(ints, 3, ints, 0, 3);
+        } catch (ArrayIndexOutOfBoundsException e) {
+            nativeInt(ints[0]);
+        }
+        try {
+            System.arraycopy(ints, 0, longs, 0, 1);
+        } catch (ArrayStoreException e) {
+            nativeInt(2);
+        }
+        try {
+            System.arraycopy(null, 0, ints, 0, 0);
+        } catch (NullPointerException e) {
+            nativeInt(3);
+        }
+
+        nativeBoolean(System.currentTimeMillis() > 1000000000000L);
+        long start = System.nanoTime();
+        nativeBoolean(System.nanoTime() >= start);
+
+        nativeInt(Float.floatToRawIntBits(1.0f));
+        nativeFloat(Float.intBitsToFloat(0x40000000));
+        nativeInt(Float.floatToIntBits(0.0f / 0.0f));
+        nativeLong(Double.doubleToLongBits(-2.0));
+        nativeDouble(Double.longBitsToDouble(0x3ff0000000000000L));
+
+        nativeDouble(Math.sqrt(16));
+        nativeDouble(StrictMath.pow(2, 10));
+        nativeDouble(Math.IEEEremainder(5, 2));
+        nativeDouble(Math.IEEEremainder(7, 2));
+        nativeDouble(Math.atan2(1, 1) * 4);
+    }
 }
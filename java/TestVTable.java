@@ -0,0 +1,20 @@
+package com.mackie.rustyjvm;
+
+import com.mackie.rustyjvm.other.TestVTableOther;
+
+public class TestVTable {
+    public int a() { return 1; }
+    int b() { return 2; }
+    protected int c() { return 3; }
+    private int d() { return 4; }
+    public static int e() { return 5; }
+
+    public static int callB(TestVTable object) { return object.b(); }
+}
+
+// TestVTableOther is in another package
+class TestVTableSub extends TestVTableOther {
+    public int a() { return 11; }
+    // overrides TestVTable.b, but not TestVTableOther.b
+    int b() { return 12; }
+}
@@ -0,0 +1,13 @@
+package com.mackie.rustyjvm.other;
+
+import com.mackie.rustyjvm.TestVTable;
+
+public class TestVTableOther extends TestVTable {
+    // doesn't override the package-private TestVTable.b of another package
+    int b() { return 22; }
+    protected int c() { return 23; }
+    // doesn't override the private TestVTable.d
+    public int d() { return 24; }
+
+    public static int callB(TestVTableOther object) { return object.b(); }
+}
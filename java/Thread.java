@@ -0,0 +1,43 @@
+package java.lang;
+
+public class Thread implements Runnable {
+    private Runnable target;
+    private boolean started;
+
+    public Thread() {}
+
+    public Thread(Runnable target) {
+        this.target = target;
+    }
+
+    public void run() {
+        if (target != null) {
+            target.run();
+        }
+    }
+
+    public synchronized void start() {
+        if (started) {
+            throw new IllegalThreadStateException();
+        }
+        started = true;
+        start0();
+    }
+
+    private native void start0();
+
+    public final native boolean isAlive();
+
+    // the vm notifies the threads waiting on a thread, when it terminates
+    public final synchronized void join() throws InterruptedException {
+        while (isAlive()) {
+            wait();
+        }
+    }
+
+    public static native Thread currentThread();
+
+    public static native void yield();
+
+    public static native void sleep(long millis) throws InterruptedException;
+}
@@ -0,0 +1,38 @@
+package java.lang;
+
+public class Throwable {
+    // the field names are the same as in GNU Classpath, so the vm can set them directly
+    private String detailMessage;
+    private StackTraceElement[] stackTrace;
+    // the throwable itself, until the cause is initialized
+    private Throwable cause = this;
+
+    public Throwable() {
+        fillInStackTrace();
+    }
+
+    public Throwable(String message) {
+        detailMessage = message;
+        fillInStackTrace();
+    }
+
+    public Throwable(String message, Throwable cause) {
+        detailMessage = message;
+        this.cause = cause;
+        fillInStackTrace();
+    }
+
+    public String getMessage() {
+        return detailMessage;
+    }
+
+    public Throwable getCause() {
+        return cause == this ? null : cause;
+    }
+
+    public native Throwable fillInStackTrace();
+
+    public StackTraceElement[] getStackTrace() {
+        return stackTrace;
+    }
+}
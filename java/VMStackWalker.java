@@ -0,0 +1,11 @@
+package gnu.classpath;
+
+// the vm interface of GNU Classpath for the classes on the stack
+public final class VMStackWalker {
+    public static native Class[] getClassContext();
+
+    public static Class getCallingClass() {
+        Class[] context = getClassContext();
+        return context.length < 3 ? null : context[2];
+    }
+}
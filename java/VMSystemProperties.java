@@ -0,0 +1,8 @@
+package java.lang;
+
+import java.util.Properties;
+
+// the vm interface of GNU Classpath for the system properties
+final class VMSystemProperties {
+    static native void preInit(Properties properties);
+}
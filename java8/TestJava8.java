@@ -0,0 +1,239 @@
+package com.mackie.rustyjvm;
+
+interface TestJava8Interface {
+    int INITIALIZED = TestJava8Interface.initialize();
+
+    static int initialize() {
+        TestVM.nativeInt(100);
+        return 5;
+    }
+
+    static int twice(int value) {
+        if (value < 0) {
+            return 0;
+        }
+        return value * 2 + INITIALIZED;
+    }
+
+    int value();
+
+    default int defaultMethod() {
+        return 10 + value();
+    }
+}
+
+interface TestJava8SubInterface extends TestJava8Interface {
+    default int defaultMethod() {
+        return 20 + TestJava8Interface.super.defaultMethod();
+    }
+}
+
+interface TestJava8OtherInterface {
+    default int otherMethod() {
+        return 40;
+    }
+}
+
+class TestJava8Impl implements TestJava8Interface {
+    public int value() {
+        return 1;
+    }
+}
+
+// the default method of the subinterface is more specific
+class TestJava8SubImpl extends TestJava8Impl implements TestJava8SubInterface, TestJava8OtherInterface {}
+
+class TestJava8Override implements TestJava8SubInterface {
+    public int value() {
+        return 3;
+    }
+
+    public int defaultMethod() {
+        return 30 + TestJava8SubInterface.super.defaultMethod();
+    }
+}
+
+interface TestJava8Function {
+    int apply(int value);
+}
+
+interface TestJava8LongFunction {
+    long apply(int value);
+}
+
+interface TestJava8Supplier<T> {
+    T get();
+}
+
+interface TestJava8CounterSupplier extends TestJava8Supplier<TestJava8Counter> {
+    TestJava8Counter get();
+}
+
+interface TestJava8Consumer<T> {
+    void accept(T value);
+}
+
+interface TestJava8IntMapper<T> {
+    int map(T value);
+}
+
+interface TestJava8Predicate<T> {
+    boolean test(T value);
+}
+
+interface TestJava8Marker {}
+
+class TestJava8Lambda {
+    static TestJava8Function create(int offset) {
+        return value -> value + offset;
+    }
+}
+
+class TestJava8Counter {
+    int count;
+
+    TestJava8Counter() {
+        count = 7;
+    }
+
+    TestJava8Function adder() {
+        return value -> count += value;
+    }
+
+    int add(int value) {
+        count += value;
+        return count;
+    }
+
+    int get() {
+        return count;
+    }
+
+    int increment() {
+        return ++count;
+    }
+}
+
+public class TestJava8 {
+    public static int parameters(int first, final long second) {
+        return first;
+    }
+
+    public static void defaultMethods() {
+        TestJava8Interface impl = new TestJava8Impl();
+        TestVM.nativeInt(impl.defaultMethod());
+        TestVM.nativeInt(new TestJava8Impl().defaultMethod());
+        TestJava8SubImpl subImpl = new TestJava8SubImpl();
+        TestVM.nativeInt(subImpl.defaultMethod());
+        TestVM.nativeInt(((TestJava8Interface) subImpl).defaultMethod());
+        TestVM.nativeInt(subImpl.otherMethod());
+        TestVM.nativeInt(new TestJava8Override().defaultMethod());
+    }
+
+    static int triple(int value) {
+        return value * 3;
+    }
+
+    public static void lambdas() {
+        TestJava8Function twice = value -> value * 2;
+        TestVM.nativeInt(twice.apply(21));
+        TestVM.nativeInt(TestJava8Lambda.create(10).apply(5));
+        for (int i = 0; i < 2; i++) {
+            TestVM.nativeInt(TestJava8Lambda.create(i).apply(1));
+        }
+        long big = 1L << 40;
+        byte small = 3;
+        TestJava8LongFunction wide = value -> value + big + small;
+        TestVM.nativeInt((int) (wide.apply(4) >> 40));
+        TestVM.nativeInt((int) wide.apply(4));
+
+        TestJava8Counter counter = new TestJava8Counter();
+        TestJava8Function adder = counter.adder();
+        adder.apply(3);
+        TestVM.nativeInt(adder.apply(5));
+        TestVM.nativeInt(counter.count);
+    }
+
+    public static void methodReferences() {
+        TestJava8Function triple = TestJava8::triple;
+        TestVM.nativeInt(triple.apply(5));
+
+        TestJava8Counter counter = new TestJava8Counter();
+        TestJava8Function bound = counter::add;
+        bound.apply(1);
+        TestVM.nativeInt(counter.count);
+
+        TestJava8IntMapper<TestJava8Counter> unbound = TestJava8Counter::get;
+        TestVM.nativeInt(unbound.map(counter));
+
+        TestJava8Supplier<TestJava8Counter> constructor = TestJava8Counter::new;
+        TestVM.nativeInt(constructor.get().count);
+
+        TestJava8IntMapper<TestJava8Interface> interfaceMethod = TestJava8Interface::defaultMethod;
+        TestVM.nativeInt(interfaceMethod.map(new TestJava8Override()));
+
+        // the result of increment is discarded
+        TestJava8Consumer<TestJava8Counter> consumer = TestJava8Counter::increment;
+        consumer.accept(counter);
+        TestVM.nativeInt(counter.count);
+
+        // the argument is checked against the type of the method reference
+        TestJava8IntMapper raw = unbound;
+        try {
+            raw.map(new Object());
+        } catch (ClassCastException e) {
+            TestVM.nativeInt(-1);
+        }
+    }
+
+    public static void alternativeMetafactory() {
+        // a marker interface and a bridge for TestJava8Supplier
+        TestJava8CounterSupplier supplier = (TestJava8CounterSupplier & TestJava8Marker) TestJava8Counter::new;
+        TestVM.nativeInt(supplier instanceof TestJava8Marker ? 1 : 0);
+        TestVM.nativeInt(supplier.get().count);
+        TestJava8Supplier<TestJava8Counter> generic = supplier;
+        TestVM.nativeInt(generic.get().count);
+    }
+
+    static long big() {
+        return 1L << 40;
+    }
+
+    static char letter() {
+        return 'x';
+    }
+
+    static boolean negative(int value) {
+        return value < 0;
+    }
+
+    // the lambda classes box the results and unbox the arguments
+    public static void boxing() {
+        TestJava8Counter counter = new TestJava8Counter();
+        TestJava8Supplier<Integer> boxed = counter::get;
+        TestVM.nativeInt(boxed.get().intValue());
+        TestJava8IntMapper<Integer> unboxed = TestJava8::triple;
+        TestVM.nativeInt(unboxed.map(Integer.valueOf(5)));
+        TestJava8Supplier<Long> big = TestJava8::big;
+        TestVM.nativeInt((int) (big.get().longValue() >> 40));
+        TestJava8Supplier<Character> letter = TestJava8::letter;
+        TestVM.nativeInt(letter.get().charValue());
+        TestJava8Predicate<Integer> negative = TestJava8::negative;
+        TestVM.nativeInt(negative.test(Integer.valueOf(-3)) ? 1 : 0);
+    }
+
+    public static void staticInterfaceMethods() {
+        TestVM.nativeInt(TestJava8Interface.twice(21));
+        TestVM.nativeInt(TestJava8Interface.twice(-1));
+    }
+
+    // class literals are loaded with ldc since version 49
+    public static void classLiterals() {
+        Class counter = TestJava8Counter.class;
+        TestVM.nativeBoolean(counter == new TestJava8Counter().getClass());
+        TestVM.nativeBoolean(counter != TestJava8Lambda.class);
+        TestVM.nativeInt(counter.getName().length());
+        TestVM.nativeBoolean(int[].class == new int[0].getClass());
+        TestVM.nativeInt(String[].class.getName().length());
+    }
+}
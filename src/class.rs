@@ -3,11 +3,15 @@ use classfile_parser::{ClassFile, method_info, field_info, ClassAccessFlags};
 use classfile_parser::method_info::*;
 use classfile_parser::field_info::*;
 use classfile_parser::attribute_info::*;
-use instruction::{Instruction, Type};
-use parsed_class::{ParsedClass, FieldRef};
+use classfile_parser::constant_info::ConstantInfo;
+use instruction::{Instruction, Type, CodeAddress};
+use parsed_class::{ParsedClass, FieldRef, MethodRef, MethodHandle};
 use descriptor::{MethodDescriptor, FieldDescriptor};
-use class_loader::ClassLoader;
+use class_loader::{ClassLoader, MAX_MAJOR_VERSION};
 use errors::ClassLoadingError;
+use verifier::VerificationType;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 // see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#40222
 pub const MAX_INSTRUCTIONS_PER_METHOD: usize = 65536;
@@ -23,9 +27,16 @@ pub struct Class {
     super_class: Option<String>,
     interfaces: Vec<String>,
     access_flags: ClassAccessFlags,
+    // the major version of the class file, which decides how it is verified
+    version: u16,
     methods: Vec<Method>,
     static_fields: Vec<Field>,
     instance_fields: Vec<Field>,
+    bootstrap_methods: Vec<BootstrapMethod>,
+    // given by the SourceFile attribute
+    source_file: Option<String>,
+    // the virtual methods of the class and its superclasses, it is built by link
+    vtable: Vec<MethodRef>,
 }
 
 #[derive(Debug)]
@@ -35,24 +46,91 @@ pub struct Method {
     descriptor: String,
     code: Option<Code>,
     words_for_params: usize,
+    // given by the MethodParameters attribute, None for parameters without name
+    parameter_names: Vec<Option<String>>,
 }
 
 #[derive(Debug)]
 pub struct Field {
     access_flags: FieldAccessFlags,
-    // TODO: think of a better type
-    constant_value: Option<Result<[i32; 2], String>>,
+    constant_value: Option<ConstantValue>,
     name: String,
     descriptor: String,
     size: usize,
 }
 
+/// the initial value of a static field, given by its ConstantValue attribute
+/// see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#1405
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValue {
+    Int(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    // utf16 like java strings
+    String(Vec<u16>),
+}
+
+/// an entry of the BootstrapMethods attribute, which is referenced by invokedynamic
+/// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.23
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapMethod {
+    handle: MethodHandle,
+    arguments: Vec<BootstrapArgument>,
+}
+
+/// the static arguments of a bootstrap method can be any loadable constant
+#[derive(Debug, Clone, PartialEq)]
+pub enum BootstrapArgument {
+    Constant(ConstantValue),
+    Class(String),
+    MethodHandle(MethodHandle),
+    MethodType(String),
+}
+
+/// the decoded code of a method, the instructions and the exception table are shared with the frames
 #[derive(Debug)]
 pub struct Code {
-    // TODO exception table
     max_stack: usize,
     max_locals: usize,
-    code: Vec<Instruction>,
+    code: Rc<[Instruction]>,
+    exception_table: Rc<[ExceptionHandler]>,
+    // the byte offsets of the instructions in the class file, empty for code generated by the vm
+    byte_offsets: Vec<usize>,
+    // the first instruction of each line and the line number, sorted by the instructions
+    line_numbers: Vec<(CodeAddress, u32)>,
+    // given by the StackMapTable attribute, which class files before version 50 don't have
+    stack_map: Option<Vec<StackMapFrame>>,
+}
+
+/// an entry of the StackMapTable attribute, the frame describes the types before the instruction at the address
+/// the frames are compressed, so the locals are relative to the previous frame, see
+/// https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.4
+/// longs and doubles take only one entry of the locals and the stack, uninitialized objects are given by the
+/// index of their new instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackMapFrame {
+    // the same locals and an empty stack
+    Same(CodeAddress),
+    SameLocals1StackItem(CodeAddress, VerificationType),
+    // the number of removed locals and an empty stack
+    Chop(CodeAddress, usize),
+    // the additional locals and an empty stack
+    Append(CodeAddress, Vec<VerificationType>),
+    // the locals and the stack
+    Full(CodeAddress, Vec<VerificationType>, Vec<VerificationType>),
+}
+
+/// an entry of the exception table of a method
+/// all addresses are indices into the decoded instructions, not byte offsets
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExceptionHandler {
+    start: CodeAddress,
+    // exclusive
+    end: CodeAddress,
+    handler: CodeAddress,
+    // None catches everything (used for finally)
+    catch_type: Option<String>,
 }
 
 impl Class {
@@ -67,7 +145,7 @@ impl Class {
             if parsed.super_class == 0 {
                 return Err("Non-Object-Class must have a superclass".to_owned());
             }
-            let super_class_name = parsed.constant_class(parsed.super_class)?.to_owned();
+            let super_class_name = parsed.constant_class(parsed.super_class)?;
             if parsed.access_flags.contains(classfile_parser::INTERFACE) && super_class_name != OBJECT_NAME {
                 return Err("Interfaces must have Object as Superclass".to_owned());
             }
@@ -76,7 +154,7 @@ impl Class {
 
         let interfaces = parsed.interfaces
             .iter()
-            .map(|index| parsed.constant_class(*index).map(|s| s.to_owned()))
+            .map(|index| parsed.constant_class(*index))
             .collect::<Result<Vec<String>, String>>()?;
 
         let methods = parsed.methods
@@ -91,17 +169,130 @@ impl Class {
             .into_iter()
             .partition(|f| f.is_static());
 
+        let mut bootstrap_methods = Vec::new();
+        let mut source_file = None;
+        for attr in &parsed.attributes {
+            match parsed.constant_utf8(attr.attribute_name_index)?.as_str() {
+                "BootstrapMethods" => bootstrap_methods = BootstrapMethod::from_attribute(&attr.info, parsed)?,
+                // see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#79868
+                "SourceFile" => {
+                    if attr.info.len() != 2 {
+                        return Err("invalid SourceFile attribute".to_owned());
+                    }
+                    source_file = Some(parsed.constant_utf8(attribute_u16(&attr.info, 0)?)?);
+                }
+                // ignore unknown attributes, see spec
+                _ => {}
+            };
+        }
+        // the code is decoded before the bootstrap methods, so their indices are checked here
+        for method in &methods {
+            for instruction in method.code().map(|c| &c.code()[..]).unwrap_or(&[]) {
+                if let Instruction::INVOKEDYNAMIC(ref call_site) = *instruction {
+                    if call_site.bootstrap_method() as usize >= bootstrap_methods.len() {
+                        return Err(format!("invalid bootstrap method for invokedynamic in method {}",
+                                           method.name()));
+                    }
+                }
+            }
+        }
+
         Ok(Class {
-            name: name.to_owned(),
+            name: name,
             super_class: super_class,
             interfaces: interfaces,
             access_flags: parsed.access_flags,
+            version: parsed.major_version,
             methods: methods,
             instance_fields: instance_fields,
             static_fields: static_fields,
+            bootstrap_methods: bootstrap_methods,
+            source_file: source_file,
+            vtable: Vec::new(),
         })
     }
 
+    /// a final class generated by the vm, e.g. for lambdas, with Object as superclass
+    pub fn synthetic(name: &str, interfaces: Vec<String>, methods: Vec<Method>, instance_fields: Vec<Field>) -> Class {
+        Class {
+            name: name.to_owned(),
+            super_class: Some(OBJECT_NAME.to_owned()),
+            interfaces: interfaces,
+            access_flags: classfile_parser::FINAL | classfile_parser::SYNTHETIC,
+            version: MAX_MAJOR_VERSION,
+            methods: methods,
+            static_fields: Vec::new(),
+            instance_fields: instance_fields,
+            bootstrap_methods: Vec::new(),
+            source_file: None,
+            vtable: Vec::new(),
+        }
+    }
+
+    /// builds the vtable of the class from the vtable of its superclass, which gets loaded
+    /// a method overrides the entries with the same name and descriptor, which it can override, see
+    /// https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.4.5
+    /// all other methods get new entries. Static and private methods and constructors aren't virtual.
+    /// An entry is replaced by its overriding method, so a method, which overrides a package-private method
+    /// of another package through a public method in between, replaces it too.
+    /// interfaces have no vtable, their methods are selected by invokeinterface
+    pub fn link(&mut self, classloader: &mut ClassLoader) -> Result<(), ClassLoadingError> {
+        let mut vtable = match self.super_class {
+            Some(ref super_class) => classloader.load_class(super_class)?.vtable.clone(),
+            None => Vec::new(),
+        };
+        if self.is_interface() {
+            return Ok(());
+        }
+        for method in &self.methods {
+            if method.is_static() || method.is_private() || method.name() == "<init>" {
+                continue;
+            }
+            let mut overrides = false;
+            for entry in &mut vtable {
+                if entry.name() == method.name() && entry.descriptor() == method.descriptor() &&
+                   Class::can_be_overridden(entry, &self.name, classloader)? {
+                    *entry = MethodRef::new(method.name(), &self.name, method.descriptor());
+                    overrides = true;
+                }
+            }
+            if !overrides {
+                vtable.push(MethodRef::new(method.name(), &self.name, method.descriptor()));
+            }
+        }
+        self.vtable = vtable;
+        Ok(())
+    }
+
+    /// public and protected methods can be overridden by all subclasses,
+    /// package-private methods only by the subclasses in the same runtime package
+    fn can_be_overridden(method: &MethodRef,
+                         class: &str,
+                         classloader: &mut ClassLoader)
+                         -> Result<bool, ClassLoadingError> {
+        let flags = classloader.load_class(method.class())?
+            .method_by_signature(method.name(), method.descriptor())
+            .expect("the vtable contains only methods of its class")
+            .access_flags();
+        // there is only one class loader, so the runtime package is given by the name
+        Ok(flags.intersects(method_info::PUBLIC | method_info::PROTECTED) ||
+           Class::package(method.class()) == Class::package(class))
+    }
+
+    fn package(class: &str) -> &str { class.rfind('/').map_or("", |i| &class[..i]) }
+
+    /// the index of the entry for the method, which is declared by this class or one of its superclasses
+    pub fn vtable_index(&self, method: &MethodRef) -> Option<usize> { self.vtable.iter().position(|m| m == method) }
+
+    /// lists the methods of the vtable with their indices
+    pub fn dump_vtable(&self) -> String {
+        let mut dump = format!("vtable of {}:\n", self.name);
+        for (index, method) in self.vtable.iter().enumerate() {
+            dump.push_str(&format!("{:4} {}.{}{}\n", index, method.class(), method.name(), method.descriptor()));
+        }
+        dump
+    }
+
     pub fn method_by_signature(&self, name: &str, descriptor: &str) -> Option<&Method> {
         self.methods.iter().find(|m| m.name() == name && m.descriptor() == descriptor)
     }
@@ -163,6 +354,73 @@ impl Class {
         }
     }
 
+    /// calculates the offsets of the instance fields holding references with the layout of get_field_offset
+    pub fn get_reference_offsets(classname: &str,
+                                 classloader: &mut ClassLoader)
+                                 -> Result<Vec<usize>, ClassLoadingError> {
+        let mut offsets = Vec::new();
+        let mut cur_name = classname.to_owned();
+        // the size of the fields of the superclasses, which were not visited yet
+        let mut superclass_size = Class::get_instance_size(classname, classloader)?;
+        loop {
+            let class = classloader.load_class(&cur_name)?;
+            superclass_size -= class.instance_fields().iter().map(|f| f.size()).sum();
+            let mut offset = superclass_size;
+            for field in class.instance_fields() {
+                if field.is_reference() {
+                    offsets.push(offset);
+                }
+                offset += field.size();
+            }
+            cur_name = match class.super_class() {
+                Some(c) => c.to_owned(),
+                None => break,
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// resolves a static field like described in
+    /// https://docs.oracle.com/javase/specs/jvms/se6/html/ConstantPool.doc.html#71685
+    /// returns the name of the class, which declares the field, and the offset
+    /// of the field in the static fields of this class
+    pub fn find_static_field(fieldref: &FieldRef,
+                             classloader: &mut ClassLoader)
+                             -> Result<(String, usize), ClassLoadingError> {
+        match Class::find_static_field_in(fieldref.class(), fieldref, classloader)? {
+            Some(f) => Ok(f),
+            None => Err(ClassLoadingError::NoSuchFieldError(fieldref.clone())),
+        }
+    }
+
+    fn find_static_field_in(classname: &str,
+                            fieldref: &FieldRef,
+                            classloader: &mut ClassLoader)
+                            -> Result<Option<(String, usize)>, ClassLoadingError> {
+        let mut supers;
+        {
+            let class = classloader.load_class(classname)?;
+            let mut offset = 0;
+            for field in class.static_fields() {
+                if field.name() == fieldref.name() && field.descriptor() == fieldref.descriptor() {
+                    return Ok(Some((classname.to_owned(), offset)));
+                }
+                offset += field.size();
+            }
+            // the superinterfaces are searched before the superclass
+            supers = class.interfaces.clone();
+            if let Some(s) = class.super_class() {
+                supers.push(s.to_owned());
+            }
+        }
+        for name in &supers {
+            if let Some(f) = Class::find_static_field_in(name, fieldref, classloader)? {
+                return Ok(Some(f));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn is_real_super_class(superclass: &str,
                                class: &str,
                                classloader: &mut ClassLoader)
@@ -180,6 +438,7 @@ impl Class {
         }
     }
 
+    #[allow(dead_code)]
     pub fn find_first_real_super_class_with_method(classname: &str,
                                                    name: &str,
                                                    descriptor: &str,
@@ -213,6 +472,136 @@ impl Class {
         }
     }
 
+    /// selects the method, which gets invoked on an instance of class, like described in
+    /// https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.invokevirtual
+    /// methods of the class and its superclasses take precedence over default methods of its superinterfaces
+    /// returns the name of the class or interface, which declares the method, which may be abstract
+    pub fn select_method(class: &str,
+                         name: &str,
+                         descriptor: &str,
+                         classloader: &mut ClassLoader)
+                         -> Result<Option<String>, ClassLoadingError> {
+        if let Some(c) = Class::find_first_super_class_with_method(class, name, descriptor, classloader)? {
+            return Ok(Some(c));
+        }
+        Class::find_maximally_specific_method(class, name, descriptor, classloader)
+    }
+
+    /// searches the maximally-specific superinterface methods of class, see
+    /// https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.4.3.3
+    /// a single non-abstract one is selected. If there are several, the default methods conflict
+    fn find_maximally_specific_method(class: &str,
+                                      name: &str,
+                                      descriptor: &str,
+                                      classloader: &mut ClassLoader)
+                                      -> Result<Option<String>, ClassLoadingError> {
+        let mut interfaces = Vec::new();
+        let mut cur_name = Some(class.to_owned());
+        while let Some(c) = cur_name {
+            let loaded = classloader.load_class(&c)?;
+            interfaces.extend(loaded.interfaces.clone());
+            cur_name = loaded.super_class().cloned();
+        }
+
+        // the superinterfaces, which declare the method
+        let mut candidates = Vec::new();
+        let mut visited = HashSet::new();
+        while let Some(interface_name) = interfaces.pop() {
+            if !visited.insert(interface_name.clone()) {
+                continue;
+            }
+            let interface = classloader.load_class(&interface_name)?;
+            if let Some(method) = interface.method_by_signature(name, descriptor) {
+                if !method.is_static() && !method.is_private() {
+                    candidates.push(interface_name.clone());
+                }
+            }
+            interfaces.extend(interface.interfaces.clone());
+        }
+
+        // a method is overridden by the methods of its subinterfaces
+        let mut maximally_specific = Vec::new();
+        for candidate in &candidates {
+            let mut overridden = false;
+            for other in &candidates {
+                if other != candidate && Class::has_interface_or_superclass(other, candidate, classloader)? {
+                    overridden = true;
+                    break;
+                }
+            }
+            if !overridden {
+                maximally_specific.push(candidate.clone());
+            }
+        }
+
+        let mut non_abstract = Vec::new();
+        for candidate in &maximally_specific {
+            if !classloader.load_class(candidate)?.method_by_signature(name, descriptor).unwrap().is_abstract() {
+                non_abstract.push(candidate.clone());
+            }
+        }
+        match non_abstract.len() {
+            0 => Ok(maximally_specific.into_iter().next()),
+            1 => Ok(non_abstract.pop()),
+            _ => Err(ClassLoadingError::IncompatibleClassChange),
+        }
+    }
+
+    /// the superinterfaces of class, which declare non-abstract instance methods, in the order
+    /// in which they are initialized before class, see
+    /// https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.5
+    pub fn default_method_interfaces(class: &str,
+                                     classloader: &mut ClassLoader)
+                                     -> Result<Vec<String>, ClassLoadingError> {
+        let mut result = Vec::new();
+        let interfaces = classloader.load_class(class)?.interfaces.clone();
+        for interface in &interfaces {
+            Class::collect_default_method_interfaces(interface, &mut result, classloader)?;
+        }
+        Ok(result)
+    }
+
+    // the superinterfaces of an interface come before the interface itself
+    fn collect_default_method_interfaces(interface: &str,
+                                         result: &mut Vec<String>,
+                                         classloader: &mut ClassLoader)
+                                         -> Result<(), ClassLoadingError> {
+        let (interfaces, has_default_method) = {
+            let loaded = classloader.load_class(interface)?;
+            (loaded.interfaces.clone(), loaded.methods.iter().any(|m| !m.is_abstract() && !m.is_static()))
+        };
+        for super_interface in &interfaces {
+            Class::collect_default_method_interfaces(super_interface, result, classloader)?;
+        }
+        if has_default_method && !result.iter().any(|r| r == interface) {
+            result.push(interface.to_owned());
+        }
+        Ok(())
+    }
+
+    /// resolves an interface method like described in
+    /// https://docs.oracle.com/javase/specs/jvms/se6/html/ConstantPool.doc.html#71722
+    /// searches the interface, its superinterfaces and Object
+    /// returns the name of the interface (or Object), which declares the method
+    pub fn find_interface_method(interface: &str,
+                                 name: &str,
+                                 descriptor: &str,
+                                 classloader: &mut ClassLoader)
+                                 -> Result<Option<String>, ClassLoadingError> {
+        let mut interfaces = vec![interface.to_owned()];
+        while !interfaces.is_empty() {
+            let interface = classloader.load_class(&interfaces.pop().unwrap())?;
+            if interface.method_by_signature(name, descriptor).is_some() {
+                return Ok(Some(interface.name().to_owned()));
+            }
+            interfaces.extend(interface.interfaces.clone());
+        }
+        if classloader.load_class(OBJECT_NAME)?.method_by_signature(name, descriptor).is_some() {
+            return Ok(Some(OBJECT_NAME.to_owned()));
+        }
+        Ok(None)
+    }
+
     pub fn is_instance_of(class: &FieldDescriptor,
                           mut dest: FieldDescriptor,
                           classloader: &mut ClassLoader)
@@ -280,13 +669,16 @@ impl Class {
     }
 
     pub fn name(&self) -> &str { &self.name }
-    #[allow(dead_code)]
     pub fn methods(&self) -> &Vec<Method> { &self.methods }
     pub fn instance_fields(&self) -> &Vec<Field> { &self.instance_fields }
-    #[allow(dead_code)]
     pub fn static_fields(&self) -> &Vec<Field> { &self.static_fields }
     pub fn super_class(&self) -> Option<&String> { self.super_class.as_ref() }
+    pub fn bootstrap_methods(&self) -> &Vec<BootstrapMethod> { &self.bootstrap_methods }
+    pub fn source_file(&self) -> Option<&String> { self.source_file.as_ref() }
+    pub fn version(&self) -> u16 { self.version }
+    pub fn vtable(&self) -> &[MethodRef] { &self.vtable }
     pub fn has_acc_super_flag(&self) -> bool { self.access_flags.contains(classfile_parser::SUPER) }
+    pub fn is_interface(&self) -> bool { self.access_flags.contains(classfile_parser::INTERFACE) }
 }
 
 impl Method {
@@ -295,8 +687,9 @@ impl Method {
         let descriptor = parsed.constant_utf8(info.descriptor_index)?;
 
         let mut code: Option<Code> = None;
+        let mut parameter_names = Vec::new();
         for attr in &info.attributes {
-            match parsed.constant_utf8(attr.attribute_name_index)? {
+            match parsed.constant_utf8(attr.attribute_name_index)?.as_str() {
                 "Code" => {
                     if code.is_some() {
                         return Err("two code attributes".to_owned());
@@ -320,27 +713,52 @@ impl Method {
 
                     code = Some(Code::from_class_file(&code_attr, parsed)?)
                 }
+                // see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.24
+                "MethodParameters" => {
+                    let count = *attr.info.first().ok_or("empty MethodParameters attribute")? as usize;
+                    if attr.info.len() != 1 + count * 4 {
+                        return Err(format!("invalid MethodParameters attribute for method {}", name));
+                    }
+                    // the access flags of the parameters are not needed
+                    parameter_names = (0..count)
+                        .map(|i| match attribute_u16(&attr.info, 1 + i * 4)? {
+                            0 => Ok(None),
+                            index => parsed.constant_utf8(index).map(Some),
+                        })
+                        .collect::<Result<Vec<_>, String>>()?;
+                }
                 // ignore unknown attributes, see spec
                 _ => {}
             };
         }
 
+        let mut method = Method::new(info.access_flags, &name, &descriptor, code)?;
+        method.parameter_names = parameter_names;
+        Ok(method)
+    }
+
+    pub fn new(access_flags: MethodAccessFlags,
+               name: &str,
+               descriptor: &str,
+               code: Option<Code>)
+               -> Result<Method, String> {
         let parsed_descriptor = match MethodDescriptor::parse(descriptor) {
             Some(c) => c,
             None => return Err(format!("invalid method descriptor for method {}", name)),
         };
 
         let mut words_for_params = parsed_descriptor.words_for_params();
-        if !info.access_flags.contains(method_info::STATIC) {
+        if !access_flags.contains(method_info::STATIC) {
             words_for_params += Type::Reference.word_size()
         };
 
         Ok(Method {
-            access_flags: info.access_flags,
+            access_flags: access_flags,
             name: name.to_owned(),
             descriptor: descriptor.to_owned(),
             code: code,
             words_for_params: words_for_params,
+            parameter_names: Vec::new(),
         })
     }
 
@@ -349,38 +767,367 @@ impl Method {
     pub fn access_flags(&self) -> MethodAccessFlags { self.access_flags }
     pub fn code(&self) -> Option<&Code> { self.code.as_ref() }
     pub fn words_for_params(&self) -> usize { self.words_for_params }
+    pub fn is_abstract(&self) -> bool { self.access_flags.contains(method_info::ABSTRACT) }
+    pub fn is_static(&self) -> bool { self.access_flags.contains(method_info::STATIC) }
+    pub fn is_private(&self) -> bool { self.access_flags.contains(method_info::PRIVATE) }
+    #[allow(dead_code)]
+    pub fn parameter_names(&self) -> &Vec<Option<String>> { &self.parameter_names }
+}
+
+impl BootstrapMethod {
+    /// parses all entries of the BootstrapMethods attribute
+    pub fn from_attribute(info: &[u8], parsed: &ClassFile) -> Result<Vec<BootstrapMethod>, String> {
+        let count = attribute_u16(info, 0)?;
+        let mut index = 2;
+        let mut methods = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let handle = parsed.constant_method_handle(attribute_u16(info, index)?)?;
+            let argument_count = attribute_u16(info, index + 2)?;
+            index += 4;
+            let mut arguments = Vec::with_capacity(argument_count as usize);
+            for _ in 0..argument_count {
+                arguments.push(BootstrapArgument::from_class_file(attribute_u16(info, index)?, parsed)?);
+                index += 2;
+            }
+            methods.push(BootstrapMethod {
+                handle: handle,
+                arguments: arguments,
+            });
+        }
+        if index != info.len() {
+            return Err("invalid BootstrapMethods attribute".to_owned());
+        }
+        Ok(methods)
+    }
+
+    pub fn handle(&self) -> &MethodHandle { &self.handle }
+    pub fn arguments(&self) -> &Vec<BootstrapArgument> { &self.arguments }
+}
+
+impl BootstrapArgument {
+    pub fn from_class_file(index: u16, parsed: &ClassFile) -> Result<BootstrapArgument, String> {
+        Ok(match *parsed.constant(index)? {
+            ConstantInfo::Integer(ref c) => BootstrapArgument::Constant(ConstantValue::Int(c.value)),
+            ConstantInfo::Float(ref c) => BootstrapArgument::Constant(ConstantValue::Float(c.value)),
+            ConstantInfo::Long(ref c) => BootstrapArgument::Constant(ConstantValue::Long(c.value)),
+            ConstantInfo::Double(ref c) => BootstrapArgument::Constant(ConstantValue::Double(c.value)),
+            ConstantInfo::String(_) => {
+                BootstrapArgument::Constant(ConstantValue::String(parsed.constant_string(index)?))
+            }
+            ConstantInfo::Class(_) => BootstrapArgument::Class(parsed.constant_class(index)?),
+            ConstantInfo::MethodHandle(_) => BootstrapArgument::MethodHandle(parsed.constant_method_handle(index)?),
+            ConstantInfo::MethodType(_) => BootstrapArgument::MethodType(parsed.constant_method_type(index)?),
+            ref c => return Err(format!("Invalid bootstrap method argument {}", c.to_string())),
+        })
+    }
+}
+
+impl StackMapFrame {
+    /// parses all frames of the StackMapTable attribute, the byte offsets are resolved to instruction indices
+    pub fn from_attribute(info: &[u8],
+                          parsed: &ClassFile,
+                          old_to_new_index: &HashMap<usize, usize>)
+                          -> Result<Vec<StackMapFrame>, String> {
+        let resolve = |offset: usize| -> Result<CodeAddress, String> {
+            old_to_new_index.get(&offset)
+                .map(|v| *v as CodeAddress)
+                .ok_or(format!("Can't resolve CodeAddress {} in stack map table", offset))
+        };
+        let count = attribute_u16(info, 0)?;
+        let mut index = 2;
+        let mut frames = Vec::with_capacity(count as usize);
+        // the offset of the first frame is its delta, the others are behind the previous frame
+        let mut offset = None;
+        for _ in 0..count {
+            let frame_type = attribute_u8(info, index)?;
+            index += 1;
+            let delta = match frame_type {
+                t if t < 128 => t as usize % 64,
+                t if t >= 247 => {
+                    index += 2;
+                    attribute_u16(info, index - 2)? as usize
+                }
+                _ => return Err(format!("invalid stack map frame type {}", frame_type)),
+            };
+            let byte_offset = offset.map_or(delta, |o| o + delta + 1);
+            offset = Some(byte_offset);
+            let address = resolve(byte_offset)?;
+            let frame = match frame_type {
+                t if t < 64 || t == 251 => StackMapFrame::Same(address),
+                t if t < 128 || t == 247 => {
+                    StackMapFrame::SameLocals1StackItem(address,
+                                                        parse_verification_type(info, &mut index, parsed, &resolve)?)
+                }
+                t if t < 251 => StackMapFrame::Chop(address, 251 - t as usize),
+                t if t < 255 => {
+                    let count = t as usize - 251;
+                    StackMapFrame::Append(address,
+                                          parse_verification_types(info, &mut index, count, parsed, &resolve)?)
+                }
+                _ => {
+                    let count = attribute_u16(info, index)? as usize;
+                    index += 2;
+                    let locals = parse_verification_types(info, &mut index, count, parsed, &resolve)?;
+                    let count = attribute_u16(info, index)? as usize;
+                    index += 2;
+                    let stack = parse_verification_types(info, &mut index, count, parsed, &resolve)?;
+                    StackMapFrame::Full(address, locals, stack)
+                }
+            };
+            frames.push(frame);
+        }
+        if index != info.len() {
+            return Err("invalid StackMapTable attribute".to_owned());
+        }
+        Ok(frames)
+    }
+
+    pub fn address(&self) -> CodeAddress {
+        match *self {
+            StackMapFrame::Same(address) |
+            StackMapFrame::SameLocals1StackItem(address, _) |
+            StackMapFrame::Chop(address, _) |
+            StackMapFrame::Append(address, _) |
+            StackMapFrame::Full(address, _, _) => address,
+        }
+    }
+}
+
+// parses a verification_type_info of a stack map frame and advances the index behind it
+fn parse_verification_type<F>(info: &[u8], index: &mut usize, parsed: &ClassFile, resolve: &F)
+                              -> Result<VerificationType, String>
+    where F: Fn(usize) -> Result<CodeAddress, String>
+{
+    let tag = attribute_u8(info, *index)?;
+    *index += 1;
+    Ok(match tag {
+        0 => VerificationType::Top,
+        1 => VerificationType::Int,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 | 8 => {
+            let value = attribute_u16(info, *index)?;
+            *index += 2;
+            if tag == 7 {
+                VerificationType::from_class(&parsed.constant_class(value)?)?
+            } else {
+                VerificationType::Uninitialized(resolve(value as usize)? as usize)
+            }
+        }
+        _ => return Err(format!("invalid verification type {}", tag)),
+    })
+}
+
+fn parse_verification_types<F>(info: &[u8], index: &mut usize, count: usize, parsed: &ClassFile, resolve: &F)
+                               -> Result<Vec<VerificationType>, String>
+    where F: Fn(usize) -> Result<CodeAddress, String>
+{
+    (0..count).map(|_| parse_verification_type(info, index, parsed, resolve)).collect()
+}
+
+fn attribute_u8(info: &[u8], index: usize) -> Result<u8, String> {
+    info.get(index).cloned().ok_or("attribute is too short".to_owned())
+}
+
+// reads a big endian u16 from an attribute, which the parser doesn't know
+fn attribute_u16(info: &[u8], index: usize) -> Result<u16, String> {
+    match (info.get(index), info.get(index + 1)) {
+        (Some(&high), Some(&low)) => Ok((high as u16) << 8 | low as u16),
+        _ => Err("attribute is too short".to_owned()),
+    }
 }
 
 impl Code {
     pub fn from_class_file(attr: &CodeAttribute, parsed: &ClassFile) -> Result<Code, String> {
+        // the other attributes of the code (LocalVariableTable, ...) are ignored
+        let (code, old_to_new_index) = Instruction::decode(&attr.code, parsed)?;
+
+        // the end of a range is exclusive, so it may point directly behind the last instruction
+        let fixup_address = |address: u16| -> Result<CodeAddress, String> {
+            if address as usize == attr.code.len() {
+                return Ok(code.len() as CodeAddress);
+            }
+            old_to_new_index.get(&(address as usize))
+                .map(|v| *v as CodeAddress)
+                .ok_or(format!("Can't resolve CodeAddress {} in exception table", address))
+        };
+
+        let mut exception_table = Vec::with_capacity(attr.exception_table.len());
+        for entry in &attr.exception_table {
+            let catch_type = if entry.catch_type == 0 {
+                None
+            } else {
+                Some(parsed.constant_class(entry.catch_type)?)
+            };
+            let handler = ExceptionHandler {
+                start: fixup_address(entry.start_pc)?,
+                end: fixup_address(entry.end_pc)?,
+                handler: fixup_address(entry.handler_pc)?,
+                catch_type: catch_type,
+            };
+            if handler.start >= handler.end || handler.handler as usize >= code.len() {
+                return Err(format!("Invalid exception table entry {:?}", handler));
+            }
+            exception_table.push(handler);
+        }
+
+        let mut byte_offsets = vec![0; code.len()];
+        for (&offset, &index) in &old_to_new_index {
+            byte_offsets[index] = offset;
+        }
+
+        // there may be several LineNumberTable attributes in any order, see
+        // https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#22856
+        let mut line_numbers = Vec::new();
+        let mut stack_map = None;
+        for code_attr in &attr.attributes {
+            match parsed.constant_utf8(code_attr.attribute_name_index)?.as_str() {
+                "LineNumberTable" => {
+                    let count = attribute_u16(&code_attr.info, 0)? as usize;
+                    if code_attr.info.len() != 2 + count * 4 {
+                        return Err("invalid LineNumberTable attribute".to_owned());
+                    }
+                    for i in 0..count {
+                        let start = attribute_u16(&code_attr.info, 2 + i * 4)? as usize;
+                        let line = attribute_u16(&code_attr.info, 4 + i * 4)?;
+                        match old_to_new_index.get(&start) {
+                            Some(&index) => line_numbers.push((index as CodeAddress, line as u32)),
+                            None => return Err(format!("Can't resolve CodeAddress {} in line number table", start)),
+                        }
+                    }
+                }
+                // see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.4
+                // the attribute is ignored in class files before version 50
+                "StackMapTable" if parsed.major_version >= 50 => {
+                    if stack_map.is_some() {
+                        return Err("multiple StackMapTable attributes".to_owned());
+                    }
+                    stack_map = Some(StackMapFrame::from_attribute(&code_attr.info, parsed, &old_to_new_index)?);
+                }
+                _ => {}
+            }
+        }
+        line_numbers.sort();
+
         Ok(Code {
             max_stack: attr.max_stack as usize,
             max_locals: attr.max_locals as usize,
-            code: Instruction::decode(&attr.code, parsed)?,
+            code: code.into(),
+            exception_table: exception_table.into(),
+            byte_offsets: byte_offsets,
+            line_numbers: line_numbers,
+            stack_map: stack_map,
         })
     }
 
+    /// code without exception handlers, which is generated by the vm
+    pub fn new(max_stack: usize, max_locals: usize, code: Vec<Instruction>) -> Code {
+        Code {
+            max_stack: max_stack,
+            max_locals: max_locals,
+            code: code.into(),
+            exception_table: Vec::new().into(),
+            byte_offsets: Vec::new(),
+            line_numbers: Vec::new(),
+            stack_map: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_stack_map(&mut self, stack_map: Vec<StackMapFrame>) { self.stack_map = Some(stack_map); }
+
     pub fn max_stack(&self) -> usize { self.max_stack }
     pub fn max_locals(&self) -> usize { self.max_locals }
-    pub fn code(&self) -> &Vec<Instruction> { &self.code }
+    pub fn code(&self) -> &Rc<[Instruction]> { &self.code }
+    pub fn exception_table(&self) -> &Rc<[ExceptionHandler]> { &self.exception_table }
+    pub fn stack_map(&self) -> Option<&Vec<StackMapFrame>> { self.stack_map.as_ref() }
+
+    /// the byte offset of the instruction at index in the class file
+    /// generated code has no byte offsets, so the index is returned
+    pub fn byte_offset(&self, index: usize) -> usize { self.byte_offsets.get(index).cloned().unwrap_or(index) }
+
+    /// the source line of the instruction at index, None if the class file doesn't tell
+    pub fn line_number(&self, index: usize) -> Option<u32> {
+        self.line_numbers.iter().take_while(|&&(start, _)| start as usize <= index).last().map(|&(_, line)| line)
+    }
+}
+
+impl ExceptionHandler {
+    #[allow(dead_code)]
+    pub fn new(start: CodeAddress,
+               end: CodeAddress,
+               handler: CodeAddress,
+               catch_type: Option<&str>)
+               -> ExceptionHandler {
+        ExceptionHandler {
+            start: start,
+            end: end,
+            handler: handler,
+            catch_type: catch_type.map(|s| s.to_owned()),
+        }
+    }
+
+    /// checks, if the instruction at address is protected by this handler
+    #[inline(always)]
+    pub fn covers(&self, address: CodeAddress) -> bool { self.start <= address && address < self.end }
+    pub fn handler(&self) -> CodeAddress { self.handler }
+    pub fn catch_type(&self) -> Option<&str> { self.catch_type.as_ref().map(|s| s.as_str()) }
 }
 
 impl Field {
+    /// a field without ConstantValue attribute
+    pub fn new(access_flags: FieldAccessFlags, name: &str, descriptor: &str) -> Result<Field, String> {
+        match FieldDescriptor::parse(descriptor) {
+            Some(d) => {
+                Ok(Field {
+                    access_flags: access_flags,
+                    name: name.to_owned(),
+                    descriptor: descriptor.to_owned(),
+                    size: d.word_size(),
+                    constant_value: None,
+                })
+            }
+            None => Err(format!("invalid field descriptor for field {}", name)),
+        }
+    }
+
     pub fn from_class_file(info: &FieldInfo, parsed: &ClassFile) -> Result<Field, String> {
         let name = parsed.constant_utf8(info.name_index)?;
         let descriptor = parsed.constant_utf8(info.descriptor_index)?;
 
-        let parsed_descriptor = match FieldDescriptor::parse(descriptor) {
+        let parsed_descriptor = match FieldDescriptor::parse(&descriptor) {
             Some(c) => c,
             None => return Err(format!("invalid field descriptor for field {}", name)),
         };
 
+        let mut constant_value = None;
+        for attr in &info.attributes {
+            match parsed.constant_utf8(attr.attribute_name_index)?.as_str() {
+                // the attribute must be ignored for non static fields, see spec
+                "ConstantValue" if info.access_flags.contains(field_info::STATIC) => {
+                    if constant_value.is_some() {
+                        return Err(format!("two ConstantValue attributes for field {}", name));
+                    }
+                    if attr.info.len() != 2 {
+                        return Err(format!("invalid ConstantValue attribute for field {}", name));
+                    }
+                    let index = (attr.info[0] as u16) << 8 | attr.info[1] as u16;
+                    constant_value = Some(ConstantValue::from_class_file(index, &descriptor, parsed)?);
+                }
+                // ignore unknown attributes, see spec
+                _ => {}
+            };
+        }
+
         Ok(Field {
             access_flags: info.access_flags,
-            name: name.to_owned(),
-            descriptor: descriptor.to_owned(),
+            name: name,
+            descriptor: descriptor,
             size: parsed_descriptor.word_size(),
-            constant_value: None, // TODO
+            constant_value: constant_value,
         })
     }
 
@@ -389,17 +1136,40 @@ impl Field {
     #[allow(dead_code)]
     pub fn descriptor(&self) -> &str { &self.descriptor }
     pub fn size(&self) -> usize { self.size }
+    // references are the only fields starting with L or [
+    pub fn is_reference(&self) -> bool { self.descriptor.starts_with('L') || self.descriptor.starts_with('[') }
     pub fn is_static(&self) -> bool { self.access_flags.contains(field_info::STATIC) }
+    pub fn constant_value(&self) -> Option<&ConstantValue> { self.constant_value.as_ref() }
+}
+
+impl ConstantValue {
+    /// the type of the constant must match the descriptor of the field
+    pub fn from_class_file(index: u16, descriptor: &str, parsed: &ClassFile) -> Result<ConstantValue, String> {
+        Ok(match (parsed.constant(index)?, descriptor) {
+            (&ConstantInfo::Integer(ref c), "I") |
+            (&ConstantInfo::Integer(ref c), "S") |
+            (&ConstantInfo::Integer(ref c), "C") |
+            (&ConstantInfo::Integer(ref c), "B") |
+            (&ConstantInfo::Integer(ref c), "Z") => ConstantValue::Int(c.value),
+            (&ConstantInfo::Float(ref c), "F") => ConstantValue::Float(c.value),
+            (&ConstantInfo::Long(ref c), "J") => ConstantValue::Long(c.value),
+            (&ConstantInfo::Double(ref c), "D") => ConstantValue::Double(c.value),
+            (&ConstantInfo::String(_), "Ljava/lang/String;") => ConstantValue::String(parsed.constant_string(index)?),
+            (c, _) => return Err(format!("Invalid ConstantValue {} for descriptor {}", c.to_string(), descriptor)),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use classfile_parser::parse_class;
+    use parsed_class::MethodRef;
 
 
     fn get_class() -> Class {
-        Class::from_class_file(&parse_class(&(super::super::CLASSFILE_DIR.to_owned() + "/TestClass")).unwrap()).unwrap()
+        let path = super::super::TEST_CLASSES_DIR.to_owned() + "/com/mackie/rustyjvm/TestClass";
+        Class::from_class_file(&parse_class(&path).unwrap()).unwrap()
     }
 
     #[test]
@@ -452,6 +1222,59 @@ mod tests {
         assert_eq!(code.code().len(), 3);
     }
 
+    #[test]
+    fn exception_table() {
+        let class = Class::from_class_file(&parse_class(&(super::super::TEST_CLASSES_DIR.to_owned() +
+                                                          "/com/mackie/rustyjvm/TestInstruction"))
+                .unwrap())
+            .unwrap();
+        let code = class.method_by_signature("exceptions", "()I").unwrap().code().unwrap();
+        assert_eq!(&code.exception_table()[..],
+                   &[ExceptionHandler::new(0, 3, 4, Some("java/lang/RuntimeException"))]);
+        assert!(code.exception_table()[0].covers(2));
+        assert!(!code.exception_table()[0].covers(3));
+        assert_eq!((0..code.code().len()).map(|i| code.byte_offset(i)).collect::<Vec<_>>(),
+                   vec![0, 1, 2, 5, 8, 9, 10, 11, 14, 15, 18]);
+        assert_eq!((0..code.code().len()).map(|i| code.line_number(i).unwrap()).collect::<Vec<_>>(),
+                   vec![213, 213, 213, 216, 214, 215, 215, 215, 217, 217, 217]);
+        assert_eq!(class.source_file().unwrap(), "TestInstruction.java");
+        assert_eq!(Code::new(1, 1, vec![]).line_number(0), None);
+    }
+
+    #[test]
+    fn stack_map() {
+        let class = Class::from_class_file(&parse_class(&(super::super::TEST_CLASSES_DIR.to_owned() +
+                                                          "/com/mackie/rustyjvm/TestJava8Interface"))
+                .unwrap())
+            .unwrap();
+        assert_eq!(class.version(), 52);
+        // iload_0; ifge 6; iconst_0; ireturn; frame same; iload_0; ...
+        let code = class.method_by_signature("twice", "(I)I").unwrap().code().unwrap();
+        assert_eq!(code.stack_map(), Some(&vec![StackMapFrame::Same(4)]));
+        let mut parsed = parse_class(&(super::super::TEST_CLASSES_DIR.to_owned() +
+                                       "/com/mackie/rustyjvm/TestJava8Interface"))
+            .unwrap();
+        parsed.major_version = 49;
+        let class = Class::from_class_file(&parsed).unwrap();
+        assert_eq!(class.method_by_signature("twice", "(I)I").unwrap().code().unwrap().stack_map(), None);
+
+        // same, same_locals_1_stack_item, chop, append and full with an object and an uninitialized object
+        let parsed = parse_class(&(super::super::TEST_CLASSES_DIR.to_owned() + "/com/mackie/rustyjvm/TestClass"))
+            .unwrap();
+        let this_class = parsed.this_class;
+        let info = [0, 5, 0, 65, 1, 250, 0, 0, 252, 0, 0, 2, 255, 0, 0, 0, 1, 7, (this_class >> 8) as u8,
+                    this_class as u8, 0, 1, 8, 0, 0];
+        let offsets = (0..6).map(|i| (i, i)).collect();
+        let object = VerificationType::from_class("com/mackie/rustyjvm/TestClass").unwrap();
+        assert_eq!(StackMapFrame::from_attribute(&info, &parsed, &offsets),
+                   Ok(vec![StackMapFrame::Same(0),
+                           StackMapFrame::SameLocals1StackItem(2, VerificationType::Int),
+                           StackMapFrame::Chop(3, 1),
+                           StackMapFrame::Append(4, vec![VerificationType::Float]),
+                           StackMapFrame::Full(5, vec![object], vec![VerificationType::Uninitialized(0)])]));
+        assert!(StackMapFrame::from_attribute(&[0, 1, 200], &parsed, &offsets).is_err());
+    }
+
     #[test]
     fn fields_size() {
         let class = get_class();
@@ -463,9 +1286,41 @@ mod tests {
         assert_eq!(class.static_fields()[0].descriptor(), "S");
     }
 
+    #[test]
+    fn constant_value() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let class = classloader.load_class("com/mackie/rustyjvm/TestClassSuper").unwrap();
+        let constants = class.static_fields().iter().map(|f| f.constant_value()).collect::<Vec<_>>();
+        assert_eq!(constants,
+                   vec![None,
+                        Some(&ConstantValue::Int(42)),
+                        Some(&ConstantValue::String("super".encode_utf16().collect())),
+                        Some(&ConstantValue::Double(1.5))]);
+        assert_eq!(class.instance_fields().iter().map(|f| f.constant_value()).collect::<Vec<_>>(),
+                   vec![None, None, None]);
+    }
+
+    #[test]
+    fn static_field() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        macro_rules! check(
+            ($name: expr, $descriptor: expr, $val: expr) => {{
+                let field = FieldRef::new($name, "com/mackie/rustyjvm/TestClass", $descriptor).unwrap();
+                let found = Class::find_static_field(&field, &mut classloader).ok();
+                assert_eq!(found, $val.map(|(class, offset): (&str, usize)| (class.to_owned(), offset)));
+            }});
+        check!("c", "S", Some(("com/mackie/rustyjvm/TestClass", 0)));
+        check!("sl", "J", Some(("com/mackie/rustyjvm/TestClassSuper", 0)));
+        check!("CONST", "I", Some(("com/mackie/rustyjvm/TestClassSuper", 2)));
+        check!("DOUBLE_CONST", "D", Some(("com/mackie/rustyjvm/TestClassSuper", 4)));
+        check!("IL", "J", Some(("com/mackie/rustyjvm/TestClassInterfaceA", 0)));
+        check!("a", "I", None);
+        check!("c", "J", None);
+    }
+
     #[test]
     fn is_super_class() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         assert_eq!(Class::is_real_super_class("com/mackie/rustyjvm/TestClass",
                                               "com/mackie/rustyjvm/TestClass",
                                               &mut classloader)
@@ -490,7 +1345,7 @@ mod tests {
 
     #[test]
     fn field_offset() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         assert_eq!(Class::get_field_offset(&FieldRef::new("a", "com/mackie/rustyjvm/TestClass", "I").unwrap(),
                                            &mut classloader)
                        .unwrap(),
@@ -517,17 +1372,25 @@ mod tests {
                    true);
     }
 
+    #[test]
+    fn reference_offsets() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        assert_eq!(Class::get_reference_offsets("com/mackie/rustyjvm/TestClass", &mut classloader).unwrap(),
+                   vec![7]);
+        assert_eq!(Class::get_reference_offsets("com/mackie/rustyjvm/TestClassSuper", &mut classloader).unwrap(),
+                   vec![]);
+    }
 
     #[test]
     fn field_size() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         assert_eq!(Class::get_instance_size("com/mackie/rustyjvm/TestClass", &mut classloader).unwrap(),
                    8);
     }
 
     #[test]
     fn find_methods() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         assert_eq!(Class::find_first_super_class_with_method("com/mackie/rustyjvm/TestClass",
                                                              "virtualMethod",
                                                              "()V",
@@ -550,9 +1413,99 @@ mod tests {
 
     }
 
+    #[test]
+    fn find_interface_methods() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        assert_eq!(Class::find_interface_method("com/mackie/rustyjvm/TestClassInterfaceD",
+                                                "interfaceMethod",
+                                                "()I",
+                                                &mut classloader)
+                       .unwrap(),
+                   Some("com/mackie/rustyjvm/TestClassInterfaceC".to_owned()));
+        assert_eq!(Class::find_interface_method("com/mackie/rustyjvm/TestClassInterfaceD",
+                                                "interfaceMethod",
+                                                "()V",
+                                                &mut classloader)
+                       .unwrap(),
+                   None);
+        assert!(classloader.load_class("com/mackie/rustyjvm/TestClassInterfaceC")
+            .unwrap()
+            .method_by_signature("interfaceMethod", "()I")
+            .unwrap()
+            .is_abstract());
+    }
+
+    #[test]
+    fn select_methods() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        macro_rules! check(
+            ($class: expr, $name: expr, $val: expr) => {{
+                assert_eq!(Class::select_method(&format!("com/mackie/rustyjvm/{}", $class), $name, "()I",
+                                                &mut classloader).unwrap(),
+                           $val.map(|c: &str| format!("com/mackie/rustyjvm/{}", c)));
+            }});
+        check!("TestJava8Impl", "value", Some("TestJava8Impl"));
+        check!("TestJava8Impl", "defaultMethod", Some("TestJava8Interface"));
+        check!("TestJava8SubImpl", "defaultMethod", Some("TestJava8SubInterface"));
+        check!("TestJava8SubImpl", "otherMethod", Some("TestJava8OtherInterface"));
+        check!("TestJava8SubImpl", "value", Some("TestJava8Impl"));
+        check!("TestJava8Override", "defaultMethod", Some("TestJava8Override"));
+        check!("TestJava8SubInterface", "value", Some("TestJava8Interface"));
+        check!("TestJava8Impl", "otherMethod", None);
+        // static interface methods are not inherited
+        assert_eq!(Class::select_method("com/mackie/rustyjvm/TestJava8Impl", "twice", "(I)I", &mut classloader)
+                       .unwrap(),
+                   None);
+
+        assert_eq!(Class::default_method_interfaces("com/mackie/rustyjvm/TestJava8SubImpl", &mut classloader)
+                       .unwrap(),
+                   vec!["com/mackie/rustyjvm/TestJava8Interface".to_owned(),
+                        "com/mackie/rustyjvm/TestJava8SubInterface".to_owned(),
+                        "com/mackie/rustyjvm/TestJava8OtherInterface".to_owned()]);
+    }
+
+    #[test]
+    fn method_parameters() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let class = classloader.load_class("com/mackie/rustyjvm/TestJava8").unwrap();
+        assert_eq!(class.method_by_signature("parameters", "(IJ)I").unwrap().parameter_names(),
+                   &vec![Some("first".to_owned()), Some("second".to_owned())]);
+        let main = get_class();
+        assert!(main.method_by_signature("main", "([Ljava/lang/String;)V").unwrap().parameter_names().is_empty());
+    }
+
+    #[test]
+    fn bootstrap_methods() {
+        let parsed = parse_class(&(super::super::TEST_CLASSES_DIR.to_owned() + "/com/mackie/rustyjvm/TestJava8Lambda"))
+            .unwrap();
+        let class = Class::from_class_file(&parsed).unwrap();
+        let methods = class.bootstrap_methods();
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods[0].handle(),
+                   &MethodHandle::InvokeStatic(MethodRef::new("metafactory",
+                                                              "java/lang/invoke/LambdaMetafactory",
+                                                              "(Ljava/lang/invoke/MethodHandles$Lookup;\
+                                                               Ljava/lang/String;Ljava/lang/invoke/MethodType;\
+                                                               Ljava/lang/invoke/MethodType;\
+                                                               Ljava/lang/invoke/MethodHandle;\
+                                                               Ljava/lang/invoke/MethodType;)\
+                                                               Ljava/lang/invoke/CallSite;")));
+        assert_eq!(methods[0].arguments(),
+                   &vec![BootstrapArgument::MethodType("(I)I".to_owned()),
+                         BootstrapArgument::MethodHandle(MethodHandle::InvokeStatic(
+                             MethodRef::new("lambda$create$0", "com/mackie/rustyjvm/TestJava8Lambda", "(II)I"))),
+                         BootstrapArgument::MethodType("(I)I".to_owned())]);
+
+        let attr = parsed.attributes
+            .iter()
+            .find(|a| parsed.constant_utf8(a.attribute_name_index).unwrap() == "BootstrapMethods")
+            .unwrap();
+        assert!(BootstrapMethod::from_attribute(&attr.info[..attr.info.len() - 1], &parsed).is_err());
+    }
+
     #[test]
     fn instance_of() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         macro_rules! check(
             ($class: expr, $dest: expr, $val: expr) => {{
                 assert_eq!(Class::is_instance_of(&FieldDescriptor::parse($class).unwrap(),
@@ -622,7 +1575,7 @@ mod tests {
 
     #[test]
     fn has_interface() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         macro_rules! check(
             ($interface: expr, $class: expr, $val: expr) => {{
                 assert_eq!(Class::has_interface_or_superclass($interface, $class,
@@ -662,4 +1615,35 @@ mod tests {
                "com/mackie/rustyjvm/TestClass",
                false);
     }
+
+    #[test]
+    fn vtable() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let class = classloader.load_class("com/mackie/rustyjvm/TestVTableSub").unwrap();
+        assert_eq!(class.dump_vtable(),
+                   concat!("vtable of com/mackie/rustyjvm/TestVTableSub:\n",
+                           "   0 java/lang/Object.hashCode()I\n",
+                           "   1 java/lang/Object.getClass()Ljava/lang/Class;\n",
+                           "   2 java/lang/Object.clone()Ljava/lang/Object;\n",
+                           "   3 java/lang/Object.notify()V\n",
+                           "   4 java/lang/Object.notifyAll()V\n",
+                           "   5 java/lang/Object.wait(J)V\n",
+                           "   6 java/lang/Object.wait()V\n",
+                           "   7 com/mackie/rustyjvm/TestVTableSub.a()I\n",
+                           "   8 com/mackie/rustyjvm/TestVTableSub.b()I\n",
+                           "   9 com/mackie/rustyjvm/other/TestVTableOther.c()I\n",
+                           "  10 com/mackie/rustyjvm/other/TestVTableOther.b()I\n",
+                           "  11 com/mackie/rustyjvm/other/TestVTableOther.d()I\n"));
+        assert_eq!(class.vtable_index(&MethodRef::new("c", "com/mackie/rustyjvm/other/TestVTableOther", "()I")),
+                   Some(9));
+        assert_eq!(class.vtable_index(&MethodRef::new("c", "com/mackie/rustyjvm/TestVTable", "()I")), None);
+
+        // the superclasses were loaded
+        let class = classloader.load_class("com/mackie/rustyjvm/TestVTable").unwrap();
+        assert_eq!(class.vtable()[7..],
+                   [MethodRef::new("a", "com/mackie/rustyjvm/TestVTable", "()I"),
+                    MethodRef::new("b", "com/mackie/rustyjvm/TestVTable", "()I"),
+                    MethodRef::new("c", "com/mackie/rustyjvm/TestVTable", "()I")]);
+        assert!(classloader.load_class("com/mackie/rustyjvm/TestClassInterfaceC").unwrap().vtable().is_empty());
+    }
 }
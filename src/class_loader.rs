@@ -1,33 +1,109 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::{ErrorKind, stderr};
 use classfile_parser::class_parser_option;
 use class::Class;
+use verifier;
 use errors::ClassLoadingError;
+use jar::{Jar, MAIN_CLASS_ATTRIBUTE, CLASS_PATH_ATTRIBUTE};
 
 // see https://docs.oracle.com/javase/specs/jvms/se6/html/ConstantPool.doc.html
 
-// we implement the second edition of the jvm, so we have to support version 45.0 to 46.0
-// ttps://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#75883
+// we support class files up to java 8, which are version 45.0 to 52.0
+// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.1
 const MIN_MAJOR_VERSION: u16 = 45;
 const MIN_MINOR_VERSION: u16 = 0;
-const MAX_MAJOR_VERSION: u16 = 46;
+pub const MAX_MAJOR_VERSION: u16 = 52;
 const MAX_MINOR_VERSION: u16 = 0;
 
+// separates the entries of the classpath like the java launcher on unix
+pub const CLASSPATH_SEPARATOR: char = ':';
+
+enum ClassPathEntry {
+    Directory(PathBuf),
+    Jar(Jar),
+}
+
+impl ClassPathEntry {
+    /// files are treated as jar or zip archives
+    /// like the java launcher, archives which can't be opened are ignored
+    fn open(path: PathBuf) -> Option<ClassPathEntry> {
+        if path.is_file() {
+            Jar::open(path).ok().map(ClassPathEntry::Jar)
+        } else {
+            Some(ClassPathEntry::Directory(path))
+        }
+    }
+}
+
 pub struct ClassLoader {
-    load_dir: PathBuf,
+    // the entries are searched in order
+    class_path: Vec<ClassPathEntry>,
     loaded_classes: HashMap<String, Class>,
+    // the classes, whose superclasses are being loaded
+    linking: HashSet<String>,
+    // true, if the vtables of the linked classes are printed to stderr
+    dump_vtables: bool,
+    // the superclasses of the classes, whose class files were parsed for the verifier, and if they are interfaces
+    headers: HashMap<String, (Option<String>, bool)>,
 }
 
 impl ClassLoader {
-    pub fn new(load_dir: &str) -> ClassLoader {
+    /// class_path is a list of directories and archives separated by CLASSPATH_SEPARATOR
+    /// an empty entry means the current directory
+    pub fn new(class_path: &str) -> ClassLoader {
+        ClassLoader::with_class_path(class_path.split(CLASSPATH_SEPARATOR)
+            .map(|entry| if entry.is_empty() { "." } else { entry })
+            .filter_map(|entry| ClassPathEntry::open(entry.into()))
+            .collect())
+    }
+
+    /// creates a class loader for an executable jar like java -jar
+    /// returns the main class of the manifest too
+    pub fn for_jar(path: &Path) -> Result<(ClassLoader, String), String> {
+        let jar = Jar::open(path)?;
+        let attributes = jar.main_attributes()?;
+        let main_class = match attributes.get(MAIN_CLASS_ATTRIBUTE) {
+            Some(class) => class.replace('.', "/"),
+            None => return Err(format!("no main manifest attribute, in {}", path.display())),
+        };
+
+        let mut class_path = vec![ClassPathEntry::Jar(jar)];
+        // the entries are relative to the directory of the jar
+        if let Some(entries) = attributes.get(CLASS_PATH_ATTRIBUTE) {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            class_path.extend(entries.split_whitespace().filter_map(|entry| ClassPathEntry::open(dir.join(entry))));
+        }
+        Ok((ClassLoader::with_class_path(class_path), main_class))
+    }
+
+    fn with_class_path(class_path: Vec<ClassPathEntry>) -> ClassLoader {
         ClassLoader {
-            load_dir: load_dir.into(),
+            class_path: class_path,
             loaded_classes: HashMap::new(),
+            linking: HashSet::new(),
+            dump_vtables: false,
+            headers: HashMap::new(),
         }
     }
 
+    /// the entries of the class path separated by CLASSPATH_SEPARATOR
+    pub fn class_path(&self) -> String {
+        let entries: Vec<String> = self.class_path
+            .iter()
+            .map(|entry| match *entry {
+                ClassPathEntry::Directory(ref path) => path.display().to_string(),
+                ClassPathEntry::Jar(ref jar) => jar.path().display().to_string(),
+            })
+            .collect();
+        entries.join(&CLASSPATH_SEPARATOR.to_string())
+    }
+
+    /// prints the vtable of each class to stderr, after it was linked
+    pub fn dump_vtables(&mut self) { self.dump_vtables = true; }
+
     pub fn load_class(&mut self, name: &str) -> Result<&Class, ClassLoadingError> {
         // must check twice to make the borrow-checker happy
         // TODO change, when non-lexical-lifetimes arrive
@@ -40,18 +116,53 @@ impl ClassLoader {
         self.load_file(name)
     }
 
+    /// adds a class, which was created at runtime, e.g. for a lambda
+    pub fn define_class(&mut self, mut class: Class) -> Result<(), ClassLoadingError> {
+        if self.loaded_classes.contains_key(class.name()) {
+            return Err(ClassLoadingError::LinkageError(format!("duplicate class definition {}", class.name())));
+        }
+        self.link(&mut class)?;
+        let name = class.name().to_owned();
+        self.loaded_classes.insert(name, class);
+        Ok(())
+    }
+
+    /// the superclass of the class and whether it is an interface, as needed by the verifier
+    /// the class file of a class, which isn't loaded yet, is only parsed. So the verifier doesn't load
+    /// the classes recursively, e.g. if two classes refer to each other
+    pub fn super_class_of(&mut self, name: &str) -> Result<(Option<String>, bool), ClassLoadingError> {
+        if let Some(class) = self.loaded_classes.get(name) {
+            return Ok((class.super_class().cloned(), class.is_interface()));
+        }
+        if let Some(header) = self.headers.get(name) {
+            return Ok(header.clone());
+        }
+        let class = self.parse_file(name)?;
+        let header = (class.super_class().cloned(), class.is_interface());
+        self.headers.insert(name.to_owned(), header.clone());
+        Ok(header)
+    }
+
     fn load_file(&mut self, name: &str) -> Result<&Class, ClassLoadingError> {
         // println!("Loading class: {}", name);
-        let classfilename = format!("{}.class", name.split('/').last().unwrap_or(name));
-        let mut file = match File::open(self.load_dir.join(classfilename)) {
-            Ok(file) => file,
-            Err(err) => return Err(ClassLoadingError::NoClassDefFound(Err(err))),
-        };
-        let mut bytes = Vec::new();
-        match file.read_to_end(&mut bytes) {
-            Ok(..) => {}
-            Err(err) => return Err(ClassLoadingError::NoClassDefFound(Err(err))),
-        };
+        let mut class = self.parse_file(name)?;
+        verifier::verify(&class, self)?;
+        self.link(&mut class)?;
+
+        let class_name = class.name().to_owned();
+        assert!(self.loaded_classes.insert(class_name.clone(), class).is_none());
+        if class_name != name {
+            return Err(ClassLoadingError::NoClassDefFound(Ok(format!("Expected class {}, but found {}",
+                                                                     name,
+                                                                     class_name))));
+        }
+
+        Ok(&self.loaded_classes[&class_name])
+    }
+
+    /// parses the class file of the class, whose version must be supported, without verifying or linking it
+    fn parse_file(&self, name: &str) -> Result<Class, ClassLoadingError> {
+        let bytes = self.find_class_file(name)?;
 
         let classfile = match class_parser_option(&bytes) {
             Some(classfile) => classfile,
@@ -65,20 +176,54 @@ impl ClassLoader {
             return Err(ClassLoadingError::UnsupportedClassVersion);
         }
 
-        let class = match Class::from_class_file(&classfile) {
-            Ok(c) => c,
-            Err(s) => return Err(ClassLoadingError::ClassFormatError(s)),
-        };
+        Class::from_class_file(&classfile).map_err(ClassLoadingError::ClassFormatError)
+    }
 
-        let class_name = class.name().to_owned();
-        assert!(self.loaded_classes.insert(class_name.clone(), class).is_none());
-        if class_name != name {
-            return Err(ClassLoadingError::NoClassDefFound(Ok(format!("Expected class {}, but found {}",
-                                                                     name,
-                                                                     class_name))));
+    /// loads the superclasses of the class and builds its vtable
+    /// a class, which is its own superclass, would be loaded again during its linking
+    fn link(&mut self, class: &mut Class) -> Result<(), ClassLoadingError> {
+        if !self.linking.insert(class.name().to_owned()) {
+            return Err(ClassLoadingError::ClassCircularity);
+        }
+        let result = class.link(self);
+        self.linking.remove(class.name());
+        if result.is_ok() && self.dump_vtables {
+            write!(&mut stderr(), "{}", class.dump_vtable()).expect("stderr writing failed");
         }
+        result
+    }
 
-        Ok(&self.loaded_classes[&class_name])
+    /// reads the class file of the class from the first entry of the classpath, which contains it
+    /// the package of the class is mapped to subdirectories or directories in the archive
+    fn find_class_file(&self, name: &str) -> Result<Vec<u8>, ClassLoadingError> {
+        let classfilename = format!("{}.class", name);
+        for entry in &self.class_path {
+            let dir = match *entry {
+                ClassPathEntry::Directory(ref dir) => dir,
+                ClassPathEntry::Jar(ref jar) => {
+                    match jar.read(&classfilename) {
+                        Some(Ok(bytes)) => return Ok(bytes),
+                        Some(Err(err)) => {
+                            return Err(ClassLoadingError::NoClassDefFound(Ok(format!("{}: {}",
+                                                                                     jar.path().display(),
+                                                                                     err))))
+                        }
+                        None => continue,
+                    }
+                }
+            };
+            let mut file = match File::open(dir.join(&classfilename)) {
+                Ok(file) => file,
+                Err(ref err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(ClassLoadingError::NoClassDefFound(Err(err))),
+            };
+            let mut bytes = Vec::new();
+            return match file.read_to_end(&mut bytes) {
+                Ok(..) => Ok(bytes),
+                Err(err) => Err(ClassLoadingError::NoClassDefFound(Err(err))),
+            };
+        }
+        Err(ClassLoadingError::NoClassDefFound(Ok(format!("{} not found in classpath", name))))
     }
 }
 
@@ -86,7 +231,7 @@ impl ClassLoader {
 mod tests {
     use super::*;
 
-    fn setup() -> ClassLoader { ClassLoader::new(super::super::CLASSFILE_DIR) }
+    fn setup() -> ClassLoader { ClassLoader::new(super::super::TEST_CLASSPATH) }
 
     #[test]
     fn not_existing_class() {
@@ -115,10 +260,94 @@ mod tests {
         });
     }
 
+    #[test]
+    fn verify_error() {
+        // retInt: iconst_1; istore_0; ret 0
+        let mut classloader = setup();
+        match classloader.load_class("TestInvalidSubroutine").err() {
+            Some(ClassLoadingError::VerifyError(message)) => {
+                assert_eq!(message,
+                           "TestInvalidSubroutine.retInt()V at bytecode index 2: Expected a return address in local \
+                            variable 0, but found Int")
+            }
+            err => panic!("Expected a VerifyError, but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn duplicate_class() {
+        let mut classloader = setup();
+        classloader.load_class("com/mackie/rustyjvm/TestClass").unwrap();
+        let class = Class::synthetic("com/mackie/rustyjvm/TestClass", vec![], vec![], vec![]);
+        assert!(match classloader.define_class(class) {
+            Err(ClassLoadingError::LinkageError(..)) => true,
+            _ => false,
+        });
+        classloader.define_class(Class::synthetic("Synthetic", vec![], vec![], vec![])).unwrap();
+        assert!(classloader.define_class(Class::synthetic("Synthetic", vec![], vec![], vec![])).is_err());
+    }
+
     #[test]
     fn good_class() {
         let mut classloader = setup();
         let class = classloader.load_class("com/mackie/rustyjvm/TestClass").unwrap();
         assert_eq!(class.name(), "com/mackie/rustyjvm/TestClass");
     }
+
+    #[test]
+    fn packages() {
+        let mut classloader = setup();
+        // the class exists, but in another package
+        assert!(match classloader.load_class("TestClass").err() {
+            Some(ClassLoadingError::NoClassDefFound(..)) => true,
+            _ => false,
+        });
+        assert!(match classloader.load_class("com/mackie/TestClass").err() {
+            Some(ClassLoadingError::NoClassDefFound(..)) => true,
+            _ => false,
+        });
+        assert_eq!(classloader.load_class("java/lang/Object").unwrap().name(), "java/lang/Object");
+    }
+
+    #[test]
+    fn class_path_order() {
+        // the classes directory doesn't contain hand assembled classes
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSES_DIR);
+        assert!(classloader.load_class("TestSubroutine").is_err());
+        assert!(classloader.load_class("com/mackie/rustyjvm/TestClass").is_ok());
+
+        // entries without the class are skipped, the library contains only the superclass Object
+        let mut classloader = ClassLoader::new(&format!("./not_existing::./java:{}", super::super::TEST_LIB_JAR));
+        assert!(classloader.load_class("TestSubroutine").is_ok());
+        assert!(classloader.load_class("com/mackie/rustyjvm/TestClass").is_err());
+
+        // the first entry containing a class file wins, even if it declares another class
+        let class_path = format!("{0}/com/mackie/rustyjvm:{0}", super::super::TEST_CLASSES_DIR);
+        let mut classloader = ClassLoader::new(&class_path);
+        assert!(match classloader.load_class("TestClass").err() {
+            Some(ClassLoadingError::NoClassDefFound(Ok(..))) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn jars() {
+        let class_path = format!("{}:{}", super::super::TEST_APP_JAR, super::super::TEST_LIB_JAR);
+        let mut classloader = ClassLoader::new(&class_path);
+        assert!(classloader.load_class("com/mackie/rustyjvm/TestClass").is_ok());
+        assert!(classloader.load_class("java/lang/Object").is_ok());
+        assert!(classloader.load_class("TestSubroutine").is_err());
+    }
+
+    #[test]
+    fn executable_jar() {
+        let (mut classloader, main_class) = ClassLoader::for_jar(Path::new(super::super::TEST_APP_JAR)).unwrap();
+        assert_eq!(main_class, "Calc");
+        assert!(classloader.load_class("Calc").is_ok());
+        // from the Class-Path of the manifest
+        assert!(classloader.load_class("java/lang/Object").is_ok());
+        assert!(classloader.load_class("TestSubroutine").is_err());
+
+        assert!(ClassLoader::for_jar(Path::new(super::super::TEST_LIB_JAR)).is_err());
+    }
 }
@@ -0,0 +1,650 @@
+use vm::VM;
+use native::{self, NativeRegistry, Value};
+use parsed_class::{FieldRef, MethodRef};
+use descriptor::FieldDescriptor;
+use instruction::Type;
+use object::{ArrayObject, Object};
+use class_loader::{CLASSPATH_SEPARATOR, MAX_MAJOR_VERSION};
+use class::OBJECT_NAME;
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::process;
+use std::time::Instant;
+
+// the natives of the vm interface of GNU Classpath, see
+// https://www.gnu.org/software/classpath/docs/cp-vmintegration.html
+// the reference implementations of these classes are part of glibj.zip, only their natives are implemented here
+
+const CLASS_NOT_FOUND_EXCEPTION: &'static str = "java/lang/ClassNotFoundException";
+const IO_EXCEPTION: &'static str = "java/io/IOException";
+const ILLEGAL_ARGUMENT_EXCEPTION: &'static str = "java/lang/IllegalArgumentException";
+const SYSTEM_NAME: &'static str = "java/lang/System";
+const STRING_NAME: &'static str = "java/lang/String";
+const VM_THROWABLE_NAME: &'static str = "java/lang/VMThrowable";
+
+/// adds the natives of GNU Classpath to the registry
+pub fn register(registry: &mut NativeRegistry) {
+    registry.register("java/lang/VMObject", "getClass", "(Ljava/lang/Object;)Ljava/lang/Class;", |vm, args| {
+        let typ = vm.heap().get(args[0].as_reference()).typ().clone();
+        Ok(Some(Value::Reference(vm.class_object(&typ))))
+    });
+    registry.register("java/lang/VMObject", "clone", "(Ljava/lang/Cloneable;)Ljava/lang/Object;", |vm, args| {
+        native::clone(vm, args[0].as_reference()).map(|o| Some(Value::Reference(o)))
+    });
+    registry.register("java/lang/VMObject", "notify", "(Ljava/lang/Object;)V", |vm, args| {
+        vm.notify(args[0].as_reference(), false).map(|_| None)
+    });
+    registry.register("java/lang/VMObject", "notifyAll", "(Ljava/lang/Object;)V", |vm, args| {
+        vm.notify(args[0].as_reference(), true).map(|_| None)
+    });
+    // the nanoseconds are rounded up to the next millisecond
+    registry.register("java/lang/VMObject", "wait", "(Ljava/lang/Object;JI)V", |vm, args| {
+        let millis = args[1].as_long().saturating_add((args[2].as_int() > 0) as i64);
+        vm.wait(args[0].as_reference(), millis).map(|_| None)
+    });
+
+    // the vm thread runs VMThread.run, which calls Thread.run
+    registry.register("java/lang/VMThread", "start", "(J)V", |vm, args| {
+        let vm_thread = args[0].as_reference();
+        // TODO replace expect with exception throw
+        let field = FieldRef::new("thread", "java/lang/VMThread", "Ljava/lang/Thread;").unwrap();
+        let thread = vm.get_field(vm_thread, &field).expect("VMThread must have a field thread");
+        vm.start_thread(thread, vm_thread, MethodRef::new("run", "java/lang/VMThread", "()V"));
+        Ok(None)
+    });
+    registry.register("java/lang/VMThread", "currentThread", "()Ljava/lang/Thread;", |vm, _| {
+        Ok(Some(Value::Reference(vm.current_thread_object())))
+    });
+    registry.register("java/lang/VMThread", "yield", "()V", |vm, _| {
+        vm.yield_thread();
+        Ok(None)
+    });
+
+    registry.register("java/lang/VMSystem",
+                      "arraycopy",
+                      "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+                      |vm, args| {
+        native::arraycopy(vm,
+                          args[0].as_reference(),
+                          args[1].as_int(),
+                          args[2].as_reference(),
+                          args[3].as_int(),
+                          args[4].as_int())
+            .map(|_| None)
+    });
+    registry.register("java/lang/VMSystem", "identityHashCode", "(Ljava/lang/Object;)I", |_, args| {
+        Ok(Some(Value::Int(args[0].as_reference())))
+    });
+    let start = Instant::now();
+    registry.register("java/lang/VMSystem", "nanoTime", "()J", move |_, _| {
+        let time = start.elapsed();
+        Ok(Some(Value::Long(time.as_secs() as i64 * 1_000_000_000 + time.subsec_nanos() as i64)))
+    });
+    // the standard streams are final, so they can only be set by the vm
+    for &(name, field, descriptor) in &[("setIn", "in", "Ljava/io/InputStream;"),
+                                        ("setOut", "out", "Ljava/io/PrintStream;"),
+                                        ("setErr", "err", "Ljava/io/PrintStream;")] {
+        let field = FieldRef::new(field, SYSTEM_NAME, descriptor).unwrap();
+        registry.register("java/lang/VMSystem", name, &format!("({})V", descriptor), move |vm, args| {
+            // TODO replace expect with exception throw
+            vm.set_static_field(&field, args[0].as_reference()).expect("System must have the standard streams");
+            Ok(None)
+        });
+    }
+    registry.register("java/lang/VMSystem", "getenv", "(Ljava/lang/String;)Ljava/lang/String;", |vm, args| {
+        let name = String::from_utf16_lossy(&vm.string_chars(args[0].as_reference()));
+        match env::var(name) {
+            Ok(value) => Ok(Some(Value::Reference(create_string(vm, &value)))),
+            Err(_) => Ok(Some(Value::Reference(0))),
+        }
+    });
+    // the variables are returned as name=value
+    registry.register("java/lang/VMSystem", "environ", "()[Ljava/lang/String;", |vm, _| {
+        let variables: Vec<String> = env::vars().map(|(name, value)| format!("{}={}", name, value)).collect();
+        let mut array = ArrayObject::new(variables.len() as i32, FieldDescriptor::from_class(STRING_NAME));
+        for (index, variable) in variables.iter().enumerate() {
+            let string = create_string(vm, variable);
+            array.set(index as i32, string).expect("index is always in bounds");
+        }
+        Ok(Some(Value::Reference(vm.heap().allocate(Object::Array(array)))))
+    });
+    // the properties are set by Properties.setProperty, which is called back after the native returned
+    registry.register("java/lang/VMSystemProperties", "preInit", "(Ljava/util/Properties;)V", |vm, args| {
+        let set_property = MethodRef::new("setProperty",
+                                          "java/util/Properties",
+                                          "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/Object;");
+        let class_path = vm.classloader().class_path();
+        for (key, value) in system_properties(class_path) {
+            let key = create_string(vm, key);
+            let value = create_string(vm, &value);
+            vm.call_back(set_property.clone(), vec![args[0].as_reference(), key, value]);
+        }
+        Ok(None)
+    });
+
+    register_class(registry);
+    register_class_loader(registry);
+    register_stack_walker(registry);
+
+    registry.register("java/lang/VMString", "intern", "(Ljava/lang/String;)Ljava/lang/String;", |vm, args| {
+        Ok(Some(Value::Reference(native::intern(vm, args[0].as_reference()))))
+    });
+
+    registry.register("java/lang/VMFloat", "floatToRawIntBits", "(F)I", |_, args| {
+        Ok(Some(Value::Int(unsafe { mem::transmute(args[0].as_float()) })))
+    });
+    registry.register("java/lang/VMFloat", "floatToIntBits", "(F)I", |_, args| {
+        let value = args[0].as_float();
+        // all NaNs have the same bits
+        let bits = if value.is_nan() { 0x7fc00000 } else { unsafe { mem::transmute(value) } };
+        Ok(Some(Value::Int(bits)))
+    });
+    registry.register("java/lang/VMFloat", "intBitsToFloat", "(I)F", |_, args| {
+        Ok(Some(Value::Float(unsafe { mem::transmute(args[0].as_int()) })))
+    });
+    registry.register("java/lang/VMDouble", "initIDs", "()V", |_, _| Ok(None));
+    registry.register("java/lang/VMDouble", "doubleToRawLongBits", "(D)J", |_, args| {
+        Ok(Some(Value::Long(unsafe { mem::transmute(args[0].as_double()) })))
+    });
+    registry.register("java/lang/VMDouble", "doubleToLongBits", "(D)J", |_, args| {
+        let value = args[0].as_double();
+        let bits = if value.is_nan() { 0x7ff8000000000000 } else { unsafe { mem::transmute(value) } };
+        Ok(Some(Value::Long(bits)))
+    });
+    registry.register("java/lang/VMDouble", "longBitsToDouble", "(J)D", |_, args| {
+        Ok(Some(Value::Double(unsafe { mem::transmute(args[0].as_long()) })))
+    });
+
+    let math = "java/lang/VMMath";
+    for &(name, function) in &[("sin", f64::sin as fn(f64) -> f64),
+                               ("cos", f64::cos),
+                               ("tan", f64::tan),
+                               ("asin", f64::asin),
+                               ("acos", f64::acos),
+                               ("atan", f64::atan),
+                               ("exp", f64::exp),
+                               ("log", f64::ln),
+                               ("log10", f64::log10),
+                               ("sqrt", f64::sqrt),
+                               ("cbrt", f64::cbrt),
+                               ("sinh", f64::sinh),
+                               ("cosh", f64::cosh),
+                               ("tanh", f64::tanh),
+                               ("expm1", f64::exp_m1),
+                               ("log1p", f64::ln_1p),
+                               ("ceil", f64::ceil),
+                               ("floor", f64::floor),
+                               ("rint", rint)] {
+        registry.register_math(math, name, function);
+    }
+    registry.register_math2(math, "atan2", f64::atan2);
+    registry.register_math2(math, "pow", native::pow);
+    registry.register_math2(math, "hypot", f64::hypot);
+    registry.register_math2(math, "IEEEremainder", native::ieee_remainder);
+
+    register_runtime(registry);
+
+    // the VMThrowable keeps the stack trace in vmdata, until Throwable.getStackTrace requests it
+    registry.register("java/lang/VMThrowable",
+                      "fillInStackTrace",
+                      "(Ljava/lang/Throwable;)Ljava/lang/VMThrowable;",
+                      |vm, args| {
+        let state = match Object::new_instance(VM_THROWABLE_NAME, vm.classloader()) {
+            Ok(s) => s,
+            Err(e) => panic!("Error loading VMThrowable: {}", e),
+        };
+        let state = vm.heap().allocate(state);
+        let trace = vm.stack_trace(args[0].as_reference());
+        vm.set_field(state, &vm_data(), trace).expect("VMThrowable must have a field vmdata");
+        Ok(Some(Value::Reference(state)))
+    });
+    registry.register("java/lang/VMThrowable",
+                      "getStackTrace",
+                      "(Ljava/lang/Throwable;)[Ljava/lang/StackTraceElement;",
+                      |vm, args| {
+        let trace = vm.get_field(args[0].as_reference(), &vm_data()).expect("VMThrowable must have a field vmdata");
+        Ok(Some(Value::Reference(trace)))
+    });
+
+    // the standard streams are written directly, other files aren't supported yet
+    registry.register("gnu/java/nio/VMChannel", "initIDs", "()V", |_, _| Ok(None));
+    registry.register("gnu/java/nio/VMChannel", "stdin_fd", "()I", |_, _| Ok(Some(Value::Int(0))));
+    registry.register("gnu/java/nio/VMChannel", "stdout_fd", "()I", |_, _| Ok(Some(Value::Int(1))));
+    registry.register("gnu/java/nio/VMChannel", "stderr_fd", "()I", |_, _| Ok(Some(Value::Int(2))));
+    registry.register("gnu/java/nio/VMChannel", "write", "(II)V", |_, args| {
+        write(args[0].as_int(), &[args[1].as_int() as u8]).map(|_| None)
+    });
+    registry.register("gnu/java/nio/VMChannel", "write", "(ILjava/nio/ByteBuffer;)I", |vm, args| {
+        write_buffer(vm, args[0].as_int(), args[1].as_reference()).map(|n| Some(Value::Int(n)))
+    });
+}
+
+/// the natives of VMClass, all classes are loaded by the bootstrap class loader
+fn register_class(registry: &mut NativeRegistry) {
+    let class = "java/lang/VMClass";
+    registry.register(class, "getName", "(Ljava/lang/Class;)Ljava/lang/String;", |vm, args| {
+        Ok(Some(Value::Reference(native::class_name(vm, args[0].as_reference()))))
+    });
+    registry.register(class, "isArray", "(Ljava/lang/Class;)Z", |vm, args| {
+        Ok(Some(Value::Int(class_type(vm, args[0]).map_or(false, |t| t.is_array()) as i32)))
+    });
+    registry.register(class, "isPrimitive", "(Ljava/lang/Class;)Z", |vm, args| {
+        let is_primitive = class_type(vm, args[0]).map_or(true, |t| t.simple_typ() != Type::Reference);
+        Ok(Some(Value::Int(is_primitive as i32)))
+    });
+    registry.register(class, "isInterface", "(Ljava/lang/Class;)Z", |vm, args| {
+        let typ = class_type(vm, args[0]);
+        let is_interface = match typ.as_ref().and_then(|t| t.get_class()) {
+            // TODO replace expect with exception throw
+            Some(name) => vm.classloader().load_class(name).expect("the class was loaded before").is_interface(),
+            None => false,
+        };
+        Ok(Some(Value::Int(is_interface as i32)))
+    });
+    registry.register(class, "isInstance", "(Ljava/lang/Class;Ljava/lang/Object;)Z", |vm, args| {
+        let object = args[1].as_reference();
+        let dest = match class_type(vm, args[0]) {
+            Some(dest) => dest,
+            None => return Ok(Some(Value::Int(0))),
+        };
+        if object == 0 {
+            return Ok(Some(Value::Int(0)));
+        }
+        let typ = vm.heap().get(object).typ().clone();
+        Ok(Some(Value::Int(native::is_assignable(vm, &typ, &dest) as i32)))
+    });
+    registry.register(class, "isAssignableFrom", "(Ljava/lang/Class;Ljava/lang/Class;)Z", |vm, args| {
+        let is_assignable = match (class_type(vm, args[0]), class_type(vm, args[1])) {
+            (Some(dest), Some(typ)) => native::is_assignable(vm, &typ, &dest),
+            // void is only assignable to itself
+            (dest, typ) => dest.is_none() && typ.is_none(),
+        };
+        Ok(Some(Value::Int(is_assignable as i32)))
+    });
+    registry.register(class, "getSuperclass", "(Ljava/lang/Class;)Ljava/lang/Class;", |vm, args| {
+        let super_class = match class_type(vm, args[0]) {
+            // TODO replace expect with exception throw
+            Some(ref typ) if typ.get_class().is_some() => {
+                let name = typ.get_class().unwrap();
+                let class = vm.classloader().load_class(name).expect("the class was loaded before");
+                // interfaces have no superclass, though they have Object as super_class in the class file
+                if class.is_interface() { None } else { class.super_class().cloned() }
+            }
+            Some(ref typ) if typ.is_array() => Some(OBJECT_NAME.to_owned()),
+            // primitive types and void
+            _ => None,
+        };
+        Ok(Some(Value::Reference(match super_class {
+            Some(name) => vm.class_object(&FieldDescriptor::from_class(&name)),
+            None => 0,
+        })))
+    });
+    registry.register(class, "getComponentType", "(Ljava/lang/Class;)Ljava/lang/Class;", |vm, args| {
+        match class_type(vm, args[0]) {
+            Some(ref mut typ) if typ.is_array() => {
+                typ.remove_array();
+                Ok(Some(Value::Reference(vm.class_object(typ))))
+            }
+            _ => Ok(Some(Value::Reference(0))),
+        }
+    });
+    registry.register(class, "getClassLoader", "(Ljava/lang/Class;)Ljava/lang/ClassLoader;", |_, _| {
+        Ok(Some(Value::Reference(0)))
+    });
+    // TODO initialize the class, if it is requested
+    registry.register(class,
+                      "forName",
+                      "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+                      |vm, args| {
+        match load_class(vm, args[0].as_reference()) {
+            Some(class) => Ok(Some(Value::Reference(class))),
+            None => Err(CLASS_NOT_FOUND_EXCEPTION),
+        }
+    });
+}
+
+/// the natives of VMClassLoader, which loads the classes of the bootstrap class loader
+/// the resources are found by the class library with the property java.boot.class.path
+fn register_class_loader(registry: &mut NativeRegistry) {
+    let loader = "java/lang/VMClassLoader";
+    // the wrapper classes initialize their field TYPE with the class objects of the primitive types
+    registry.register(loader, "getPrimitiveClass", "(C)Ljava/lang/Class;", |vm, args| {
+        let descriptor = (args[0].as_int() as u8 as char).to_string();
+        let class = match FieldDescriptor::parse(&descriptor) {
+            Some(ref typ) if typ.simple_typ() != Type::Reference => vm.class_object(typ),
+            _ if descriptor == "V" => vm.void_class_object(),
+            _ => return Err(ILLEGAL_ARGUMENT_EXCEPTION),
+        };
+        Ok(Some(Value::Reference(class)))
+    });
+    // null is returned instead of throwing ClassNotFoundException, which the class library allows
+    registry.register(loader, "loadClass", "(Ljava/lang/String;Z)Ljava/lang/Class;", |vm, args| {
+        Ok(Some(Value::Reference(load_class(vm, args[0].as_reference()).unwrap_or(0))))
+    });
+    registry.register(loader, "resolveClass", "(Ljava/lang/Class;)V", |_, _| Ok(None));
+    // the classes of other class loaders can't be defined yet, so they have no loaded classes
+    registry.register(loader,
+                      "findLoadedClass",
+                      "(Ljava/lang/ClassLoader;Ljava/lang/String;)Ljava/lang/Class;",
+                      |_, _| Ok(Some(Value::Reference(0))));
+}
+
+/// the natives of VMStackWalker and VMAccessController, which look at the methods on the stack
+fn register_stack_walker(registry: &mut NativeRegistry) {
+    // the first class is the one of the method, which called getClassContext
+    registry.register("gnu/classpath/VMStackWalker", "getClassContext", "()[Ljava/lang/Class;", |vm, _| {
+        let methods = vm.stack_methods();
+        Ok(Some(Value::Reference(class_array(vm, &methods))))
+    });
+    registry.register("gnu/classpath/VMStackWalker",
+                      "getClassLoader",
+                      "(Ljava/lang/Class;)Ljava/lang/ClassLoader;",
+                      |_, _| Ok(Some(Value::Reference(0))));
+    // the classes and the names of the methods on the stack, the security manager isn't supported
+    registry.register("java/security/VMAccessController", "getStack", "()[[Ljava/lang/Object;", |vm, _| {
+        let methods = vm.stack_methods();
+        let classes = class_array(vm, &methods);
+        let mut names = ArrayObject::new(methods.len() as i32, FieldDescriptor::from_class(STRING_NAME));
+        for (index, method) in methods.iter().enumerate() {
+            let name = create_string(vm, method.name());
+            names.set(index as i32, name).expect("index is always in bounds");
+        }
+        let names = vm.heap().allocate(Object::Array(names));
+        let mut stack = ArrayObject::new(2, FieldDescriptor::parse("[Ljava/lang/Object;").unwrap());
+        stack.set(0, classes).expect("index is always in bounds");
+        stack.set(1, names).expect("index is always in bounds");
+        Ok(Some(Value::Reference(vm.heap().allocate(Object::Array(stack)))))
+    });
+}
+
+/// the natives of VMRuntime, native libraries are never loaded, because all natives are part of the vm
+fn register_runtime(registry: &mut NativeRegistry) {
+    let runtime = "java/lang/VMRuntime";
+    registry.register(runtime, "availableProcessors", "()I", |_, _| Ok(Some(Value::Int(1))));
+    registry.register(runtime, "freeMemory", "()J", |vm, _| {
+        Ok(Some(Value::Long((vm.heap().max_size() - vm.heap().size()) as i64)))
+    });
+    registry.register(runtime, "totalMemory", "()J", |vm, _| Ok(Some(Value::Long(vm.heap().max_size() as i64))));
+    registry.register(runtime, "maxMemory", "()J", |vm, _| Ok(Some(Value::Long(vm.heap().max_size() as i64))));
+    // the heap can only be collected at allocations, where all references are known
+    registry.register(runtime, "gc", "()V", |_, _| Ok(None));
+    registry.register(runtime, "runFinalization", "()V", |_, _| Ok(None));
+    registry.register(runtime, "runFinalizationForExit", "()V", |_, _| Ok(None));
+    registry.register(runtime, "traceInstructions", "(Z)V", |_, _| Ok(None));
+    registry.register(runtime, "traceMethodCalls", "(Z)V", |_, _| Ok(None));
+    registry.register(runtime, "exit", "(I)V", |_, args| {
+        io::stdout().flush().ok();
+        process::exit(args[0].as_int())
+    });
+    registry.register(runtime, "nativeLoad", "(Ljava/lang/String;Ljava/lang/ClassLoader;)I", |_, _| {
+        Ok(Some(Value::Int(1)))
+    });
+    registry.register(runtime, "mapLibraryName", "(Ljava/lang/String;)Ljava/lang/String;", |vm, args| {
+        let name = String::from_utf16_lossy(&vm.string_chars(args[0].as_reference()));
+        Ok(Some(Value::Reference(create_string(vm, &format!("lib{}.so", name)))))
+    });
+}
+
+/// the properties, which are set by the vm, see the documentation of System.getProperties
+/// the properties of unknown directories are empty
+fn system_properties(class_path: String) -> Vec<(&'static str, String)> {
+    let user_dir = env::current_dir().map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_default();
+    vec![("java.class.path", class_path.clone()),
+         ("java.boot.class.path", class_path),
+         ("java.version", "1.8".to_owned()),
+         ("java.vendor", "rusty-jvm".to_owned()),
+         ("java.vm.name", "rusty-jvm".to_owned()),
+         ("java.specification.version", "1.8".to_owned()),
+         ("java.class.version", format!("{}.0", MAX_MAJOR_VERSION)),
+         ("os.name", env::consts::OS.to_owned()),
+         ("os.arch", env::consts::ARCH.to_owned()),
+         ("file.separator", "/".to_owned()),
+         ("path.separator", CLASSPATH_SEPARATOR.to_string()),
+         ("line.separator", "\n".to_owned()),
+         ("file.encoding", "UTF-8".to_owned()),
+         ("java.io.tmpdir", env::temp_dir().to_string_lossy().into_owned()),
+         ("user.dir", user_dir),
+         ("user.home", env::var("HOME").unwrap_or_default()),
+         ("user.name", env::var("USER").unwrap_or_default())]
+}
+
+fn create_string(vm: &mut VM, value: &str) -> i32 {
+    let chars: Vec<u16> = value.encode_utf16().collect();
+    vm.create_string(&chars)
+}
+
+// the field of VMThrowable, which is reserved for the vm
+fn vm_data() -> FieldRef { FieldRef::new("vmdata", VM_THROWABLE_NAME, "Ljava/lang/Object;").unwrap() }
+
+/// the type, which is represented by the class object, None for void
+fn class_type(vm: &mut VM, class_object: Value) -> Option<FieldDescriptor> {
+    let class_object = class_object.as_reference();
+    if vm.is_void_class(class_object) {
+        return None;
+    }
+    Some(vm.class_type(class_object).expect("not a class object").clone())
+}
+
+/// the class object for the binary name of the class or array, None if the class can't be loaded
+fn load_class(vm: &mut VM, name: i32) -> Option<i32> {
+    let name = String::from_utf16_lossy(&vm.string_chars(name)).replace('.', "/");
+    let typ = FieldDescriptor::from_symbolic_reference(&name)?;
+    if let Some(class) = typ.get_class() {
+        if vm.classloader().load_class(class).is_err() {
+            return None;
+        }
+    }
+    Some(vm.class_object(&typ))
+}
+
+/// the array of the classes, which declare the methods
+fn class_array(vm: &mut VM, methods: &[MethodRef]) -> i32 {
+    let mut classes = ArrayObject::new(methods.len() as i32, FieldDescriptor::from_class("java/lang/Class"));
+    for (index, method) in methods.iter().enumerate() {
+        let class = vm.class_object(&FieldDescriptor::from_class(method.class()));
+        classes.set(index as i32, class).expect("index is always in bounds");
+    }
+    vm.heap().allocate(Object::Array(classes))
+}
+
+/// rounds to the nearest integer and to even on ties
+fn rint(x: f64) -> f64 {
+    let rounded = x.round();
+    if (rounded - x).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - x.signum()
+    } else {
+        rounded
+    }
+}
+
+fn write(fd: i32, bytes: &[u8]) -> Result<(), &'static str> {
+    let result = match fd {
+        1 => io::stdout().write_all(bytes),
+        2 => io::stderr().write_all(bytes),
+        _ => return Err(IO_EXCEPTION),
+    };
+    result.map_err(|_| IO_EXCEPTION)
+}
+
+/// writes the remaining bytes of the heap buffer and returns their number
+fn write_buffer(vm: &mut VM, fd: i32, buffer: i32) -> Result<i32, &'static str> {
+    let position_field = FieldRef::new("pos", "java/nio/Buffer", "I").unwrap();
+    // TODO replace expect with exception throw
+    let position = vm.get_field(buffer, &position_field).expect("Buffer must have a field pos");
+    let limit = vm.get_field(buffer, &FieldRef::new("limit", "java/nio/Buffer", "I").unwrap())
+        .expect("Buffer must have a field limit");
+    let array = vm.get_field(buffer, &FieldRef::new("backing_buffer", "java/nio/ByteBuffer", "[B").unwrap())
+        .expect("ByteBuffer must have a field backing_buffer");
+    let offset = vm.get_field(buffer, &FieldRef::new("array_offset", "java/nio/ByteBuffer", "I").unwrap())
+        .expect("ByteBuffer must have a field array_offset");
+    // TODO support direct buffers
+    if array == 0 {
+        return Err(IO_EXCEPTION);
+    }
+    let bytes: Vec<u8> = vm.heap().get(array).as_array().data()[(offset + position) as usize..(offset + limit) as usize]
+        .iter()
+        .map(|&b| b as u8)
+        .collect();
+    write(fd, &bytes)?;
+    vm.set_field(buffer, &position_field, limit).expect("Buffer must have a field pos");
+    Ok(limit - position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use class_loader::ClassLoader;
+    use thread::Scheduler;
+
+    fn call(vm: &mut VM, class: &str, name: &str, descriptor: &str, args: &[Value]) -> Option<Value> {
+        let registry = NativeRegistry::new();
+        let native = registry.get(&MethodRef::new(name, class, descriptor)).expect("native is registered");
+        native(vm, args).expect("native throws no exception")
+    }
+
+    fn test_vm() -> VM {
+        VM::new(ClassLoader::new(super::super::TEST_CLASSPATH),
+                super::super::DEFAULT_MAX_HEAP_SIZE,
+                Scheduler::new(super::super::DEFAULT_QUANTUM, None))
+    }
+
+    fn string(vm: &mut VM, value: &str) -> Value { Value::Reference(create_string(vm, value)) }
+
+    fn class(vm: &mut VM, name: &str) -> Value {
+        Value::Reference(vm.class_object(&FieldDescriptor::from_symbolic_reference(name).unwrap()))
+    }
+
+    fn name(vm: &mut VM, class: Value) -> String {
+        let name = call(vm, "java/lang/VMClass", "getName", "(Ljava/lang/Class;)Ljava/lang/String;", &[class]);
+        String::from_utf16_lossy(&vm.string_chars(name.unwrap().as_reference()))
+    }
+
+    #[test]
+    fn classes() {
+        let mut vm = test_vm();
+        let string_class = class(&mut vm, "java/lang/String");
+        let object_class = class(&mut vm, OBJECT_NAME);
+        let array_class = class(&mut vm, "[[Ljava/lang/String;");
+        assert_eq!(name(&mut vm, string_class), "java.lang.String");
+        assert_eq!(name(&mut vm, array_class), "[[Ljava.lang.String;");
+
+        let is_assignable = "(Ljava/lang/Class;Ljava/lang/Class;)Z";
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[object_class, array_class]),
+                   Some(Value::Int(1)));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[string_class, object_class]),
+                   Some(Value::Int(0)));
+        let get_super = "(Ljava/lang/Class;)Ljava/lang/Class;";
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "getSuperclass", get_super, &[string_class]),
+                   Some(object_class));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "getSuperclass", get_super, &[object_class]),
+                   Some(Value::Reference(0)));
+        let component = call(&mut vm, "java/lang/VMClass", "getComponentType", get_super, &[array_class]).unwrap();
+        assert_eq!(name(&mut vm, component), "[Ljava.lang.String;");
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isArray", "(Ljava/lang/Class;)Z", &[component]),
+                   Some(Value::Int(1)));
+
+        let name = string(&mut vm, "java.lang.String");
+        assert_eq!(call(&mut vm,
+                        "java/lang/VMClass",
+                        "forName",
+                        "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+                        &[name, Value::Int(1), Value::Reference(0)]),
+                   Some(string_class));
+    }
+
+    #[test]
+    fn primitive_classes() {
+        let mut vm = test_vm();
+        let primitive = |vm: &mut VM, descriptor: char| {
+            let args = [Value::Int(descriptor as i32)];
+            call(vm, "java/lang/VMClassLoader", "getPrimitiveClass", "(C)Ljava/lang/Class;", &args).unwrap()
+        };
+        let int_class = primitive(&mut vm, 'I');
+        let void_class = primitive(&mut vm, 'V');
+        assert_eq!(primitive(&mut vm, 'I'), int_class);
+        assert_eq!(name(&mut vm, int_class), "int");
+        assert_eq!(name(&mut vm, void_class), "void");
+        let is_primitive = "(Ljava/lang/Class;)Z";
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isPrimitive", is_primitive, &[int_class]), Some(Value::Int(1)));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isPrimitive", is_primitive, &[void_class]), Some(Value::Int(1)));
+        let array_class = class(&mut vm, "[I");
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isPrimitive", is_primitive, &[array_class]),
+                   Some(Value::Int(0)));
+        let get_component = "(Ljava/lang/Class;)Ljava/lang/Class;";
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "getComponentType", get_component, &[array_class]),
+                   Some(int_class));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "getSuperclass", get_component, &[int_class]),
+                   Some(Value::Reference(0)));
+
+        let is_assignable = "(Ljava/lang/Class;Ljava/lang/Class;)Z";
+        let object_class = class(&mut vm, OBJECT_NAME);
+        let long_class = primitive(&mut vm, 'J');
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[int_class, int_class]),
+                   Some(Value::Int(1)));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[long_class, int_class]),
+                   Some(Value::Int(0)));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[object_class, int_class]),
+                   Some(Value::Int(0)));
+        assert_eq!(call(&mut vm, "java/lang/VMClass", "isAssignableFrom", is_assignable, &[void_class, void_class]),
+                   Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn class_loader() {
+        let mut vm = test_vm();
+        let load_class = "(Ljava/lang/String;Z)Ljava/lang/Class;";
+        let name = string(&mut vm, "java.lang.String");
+        let string_class = class(&mut vm, "java/lang/String");
+        assert_eq!(call(&mut vm, "java/lang/VMClassLoader", "loadClass", load_class, &[name, Value::Int(0)]),
+                   Some(string_class));
+        let name = string(&mut vm, "not.Existing");
+        assert_eq!(call(&mut vm, "java/lang/VMClassLoader", "loadClass", load_class, &[name, Value::Int(0)]),
+                   Some(Value::Reference(0)));
+    }
+
+    #[test]
+    fn objects() {
+        let mut vm = test_vm();
+        let a = string(&mut vm, "a");
+        let other_a = string(&mut vm, "a");
+        let intern = "(Ljava/lang/String;)Ljava/lang/String;";
+        let interned = call(&mut vm, "java/lang/VMString", "intern", intern, &[a]);
+        assert!(interned != Some(a));
+        assert_eq!(call(&mut vm, "java/lang/VMString", "intern", intern, &[other_a]), interned);
+
+        let value = vm.get_field(a.as_reference(), &FieldRef::new("value", "java/lang/String", "[C").unwrap()).unwrap();
+        let clone = call(&mut vm,
+                         "java/lang/VMObject",
+                         "clone",
+                         "(Ljava/lang/Cloneable;)Ljava/lang/Object;",
+                         &[Value::Reference(value)])
+            .unwrap()
+            .as_reference();
+        assert!(clone != value);
+        assert_eq!(vm.heap().get(clone).as_array().data(), &['a' as i32][..]);
+        // strings are not cloneable
+        assert!(native::clone(&mut vm, a.as_reference()).is_err());
+    }
+
+    #[test]
+    fn environment() {
+        let mut vm = test_vm();
+        let environ = call(&mut vm, "java/lang/VMSystem", "environ", "()[Ljava/lang/String;", &[]).unwrap();
+        let strings = vm.heap().get(environ.as_reference()).as_array().data().to_vec();
+        let variables = strings.into_iter()
+            .map(|s| String::from_utf16_lossy(&vm.string_chars(s)))
+            .collect::<Vec<_>>();
+        assert_eq!(variables,
+                   env::vars().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(rint(2.5), 2.0);
+        assert_eq!(rint(3.5), 4.0);
+        assert_eq!(rint(-2.5), -2.0);
+        assert_eq!(rint(2.4), 2.0);
+        assert_eq!(rint(-2.6), -3.0);
+    }
+}
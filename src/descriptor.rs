@@ -1,3 +1,4 @@
+use std::fmt;
 use std::ops::Deref;
 use instruction::Type;
 
@@ -103,8 +104,6 @@ impl FieldDescriptor {
         self.update_simple_typ();
     }
 
-    // TODO remove
-    #[allow(dead_code)]
     pub fn as_type_without_arrays(&self, num_less_arrays: usize) -> Type {
         assert!(num_less_arrays <= self.num_array);
         as_type(&self.typ, self.num_array - num_less_arrays)
@@ -160,9 +159,37 @@ impl FieldDescriptor {
         }
     }
 
+    /// the inverse of from_symbolic_reference, the descriptor must be a reference
+    pub fn to_symbolic_reference(&self) -> String {
+        assert_eq!(self.simple_typ, Type::Reference);
+        match self.get_class() {
+            Some(class) => class.to_owned(),
+            None => self.to_string(),
+        }
+    }
+
     fn update_simple_typ(&mut self) { self.simple_typ = as_type(&self.typ, self.num_array); }
 }
 
+impl fmt::Display for FieldDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for _ in 0..self.num_array {
+            write!(f, "[")?;
+        }
+        match self.typ {
+            FieldDescriptorType::Byte => write!(f, "B"),
+            FieldDescriptorType::Char => write!(f, "C"),
+            FieldDescriptorType::Double => write!(f, "D"),
+            FieldDescriptorType::Float => write!(f, "F"),
+            FieldDescriptorType::Int => write!(f, "I"),
+            FieldDescriptorType::Long => write!(f, "J"),
+            FieldDescriptorType::Reference(ref s) => write!(f, "L{};", s),
+            FieldDescriptorType::Short => write!(f, "S"),
+            FieldDescriptorType::Boolean => write!(f, "Z"),
+        }
+    }
+}
+
 impl Deref for FieldDescriptor {
     type Target = Type;
 
@@ -179,6 +206,9 @@ impl MethodDescriptor {
     }
 
     pub fn words_for_params(&self) -> usize { self.params.iter().map(|e| e.word_size()).sum() }
+    pub fn params(&self) -> &Vec<FieldDescriptor> { &self.params }
+    /// None for void
+    pub fn ret_type(&self) -> Option<&FieldDescriptor> { self.ret_type.as_ref() }
 }
 
 #[cfg(test)]
@@ -292,6 +322,17 @@ mod tests {
         assert_eq!(FieldDescriptor::from_symbolic_reference("[I"), fd(Int, 1));
     }
 
+    #[test]
+    fn field_to_string() {
+        for desc in &["B", "C", "D", "F", "I", "J", "S", "Z", "Ljava/lang/Object;", "[[[I", "[Ljava/lang/Object;"] {
+            assert_eq!(&FieldDescriptor::parse(desc).unwrap().to_string(), desc);
+        }
+        assert_eq!(FieldDescriptor::parse("[Ljava/lang/Object;").unwrap().to_symbolic_reference(),
+                   "[Ljava/lang/Object;");
+        assert_eq!(FieldDescriptor::parse("Ljava/lang/Object;").unwrap().to_symbolic_reference(),
+                   "java/lang/Object");
+    }
+
     #[test]
     fn method_empty() {
         assert_eq!(MethodDescriptor::parse("()V"), md(vec![], None));
@@ -9,10 +9,10 @@ pub enum ClassLoadingError {
     ClassFormatError(String),
     UnsupportedClassVersion,
     NoSuchFieldError(FieldRef),
-    #[allow(dead_code)]
     IncompatibleClassChange,
-    #[allow(dead_code)]
     ClassCircularity,
+    VerifyError(String),
+    LinkageError(String),
 }
 
 impl fmt::Display for ClassLoadingError {
@@ -28,6 +28,8 @@ impl fmt::Display for ClassLoadingError {
             ClassLoadingError::UnsupportedClassVersion => write!(f, "class version not supported"),
             ClassLoadingError::IncompatibleClassChange => write!(f, "IncompatibleClassChange"),
             ClassLoadingError::ClassCircularity => write!(f, "ClassCircularity"),
+            ClassLoadingError::VerifyError(ref err) => write!(f, "VerifyError: {}", err),
+            ClassLoadingError::LinkageError(ref err) => write!(f, "LinkageError: {}", err),
         }
     }
 }
@@ -41,6 +43,8 @@ impl error::Error for ClassLoadingError {
             ClassLoadingError::UnsupportedClassVersion => "UnsupportedClassVersion",
             ClassLoadingError::IncompatibleClassChange => "IncompatibleClassChange",
             ClassLoadingError::ClassCircularity => "ClassCircularity",
+            ClassLoadingError::VerifyError(..) => "VerifyError",
+            ClassLoadingError::LinkageError(..) => "LinkageError",
         }
     }
 
@@ -0,0 +1,184 @@
+use object::Object;
+use class::Class;
+use class_loader::ClassLoader;
+use errors::ClassLoadingError;
+use std::collections::HashMap;
+use std::cmp::{min, max};
+
+// the estimated size of the bookkeeping of an object in bytes
+const OBJECT_HEADER_SIZE: usize = 16;
+const WORD_SIZE: usize = 4;
+// the heap is not collected, before it reaches this size
+const MIN_COLLECTION_SIZE: usize = 1 << 20;
+
+/// the objects of the vm, which are freed by a mark and sweep garbage collector
+/// a reference is the index of the object, 0 is the null reference
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    // the unused indices, which are reused before the heap grows
+    free: Vec<usize>,
+    // the estimated size of all objects in bytes
+    size: usize,
+    max_size: usize,
+    // the size, at which the next collection starts
+    next_collection: usize,
+    // caches the offsets of the fields holding references for the classes of the marked objects
+    reference_offsets: HashMap<String, Vec<usize>>,
+}
+
+impl Heap {
+    pub fn new(max_size: usize) -> Heap {
+        Heap {
+            // the dummy null object
+            objects: vec![None],
+            free: Vec::new(),
+            size: 0,
+            max_size: max_size,
+            next_collection: min(MIN_COLLECTION_SIZE, max_size),
+            reference_offsets: HashMap::new(),
+        }
+    }
+
+    /// the estimated size of an array in bytes, which saturates instead of overflowing
+    pub fn array_size(length: usize, words_per_element: usize) -> usize {
+        OBJECT_HEADER_SIZE.saturating_add(length.saturating_mul(words_per_element).saturating_mul(WORD_SIZE))
+    }
+
+    pub fn object_size(object: &Object) -> usize {
+        match *object {
+            Object::Array(ref a) => Heap::array_size(a.data().len(), 1),
+            Object::Instance(ref i) => Heap::array_size(i.data().len(), 1),
+        }
+    }
+
+    /// allocates the object, even if the heap is full
+    /// the objects created by the program must be reserved before, see VM::reserve
+    pub fn allocate(&mut self, object: Object) -> i32 {
+        self.size += Heap::object_size(&object);
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                index as i32
+            }
+            None => {
+                self.objects.push(Some(object));
+                (self.objects.len() - 1) as i32
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize { self.size }
+    pub fn max_size(&self) -> usize { self.max_size }
+
+    pub fn get(&mut self, index: i32) -> &mut Object {
+        self.objects[index as usize].as_mut().expect("Invalid Reference")
+    }
+
+    /// checks, if the value can be a reference, which is used for slots without known type
+    pub fn is_object(&self, value: i32) -> bool {
+        value > 0 && self.objects.get(value as usize).map_or(false, |o| o.is_some())
+    }
+
+    /// true, if the heap should be collected before size bytes are allocated
+    pub fn needs_collection(&self, size: usize) -> bool { self.size.saturating_add(size) > self.next_collection }
+
+    /// true, if size bytes can be allocated without exceeding the maximum size
+    pub fn has_room(&self, size: usize) -> bool { self.size.saturating_add(size) <= self.max_size }
+
+    /// frees all objects, which are not reachable from the roots
+    /// see https://en.wikipedia.org/wiki/Tracing_garbage_collection#Na.C3.AFve_mark-and-sweep
+    pub fn collect(&mut self, roots: Vec<i32>, classloader: &mut ClassLoader) -> Result<(), ClassLoadingError> {
+        let mut marked = vec![false; self.objects.len()];
+        let mut work = roots;
+        while let Some(index) = work.pop() {
+            if index == 0 || marked[index as usize] {
+                continue;
+            }
+            marked[index as usize] = true;
+            match *self.objects[index as usize].as_ref().expect("Invalid Reference") {
+                Object::Array(ref array) => {
+                    if array.contains_references() {
+                        work.extend(array.data().iter().filter(|&&r| r != 0));
+                    }
+                }
+                Object::Instance(ref instance) => {
+                    if !self.reference_offsets.contains_key(instance.class()) {
+                        let offsets = Class::get_reference_offsets(instance.class(), classloader)?;
+                        self.reference_offsets.insert(instance.class().to_owned(), offsets);
+                    }
+                    let data = instance.data();
+                    let offsets = &self.reference_offsets[instance.class()];
+                    work.extend(offsets.iter().map(|&o| data[o]).filter(|&r| r != 0));
+                }
+            }
+        }
+
+        for (index, object) in self.objects.iter_mut().enumerate().skip(1) {
+            if marked[index] {
+                continue;
+            }
+            if let Some(o) = object.take() {
+                self.size -= Heap::object_size(&o);
+                self.free.push(index);
+            }
+        }
+        self.next_collection = min(max(2 * self.size, MIN_COLLECTION_SIZE), self.max_size);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::ArrayObject;
+    use descriptor::FieldDescriptor;
+    use instruction::Type;
+    use parsed_class::FieldRef;
+
+    fn int_array() -> Object { Object::new_array(2, FieldDescriptor::from_type_without_reference(Type::Int)) }
+
+    #[test]
+    fn allocation() {
+        let mut heap = Heap::new(1000);
+        assert_eq!(Heap::object_size(&int_array()), 24);
+        assert_eq!(heap.allocate(int_array()), 1);
+        assert_eq!(heap.allocate(int_array()), 2);
+        assert!(heap.has_room(1000 - 48));
+        assert!(!heap.has_room(1000 - 47));
+        assert!(heap.needs_collection(1000 - 47));
+        assert!(heap.is_object(2));
+        assert!(!heap.is_object(0));
+        assert!(!heap.is_object(3));
+        assert!(!heap.is_object(-1));
+        assert_eq!(Heap::array_size(usize::max_value(), 2), usize::max_value());
+    }
+
+    #[test]
+    fn collection() {
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let mut heap = Heap::new(1000);
+        let garbage = heap.allocate(int_array());
+        let value = heap.allocate(int_array());
+        let mut string = Object::new_instance("java/lang/String", &mut classloader).unwrap();
+        string.as_instance()
+            .set_field(&FieldRef::new("value", "java/lang/String", "[C").unwrap(), value, &mut classloader)
+            .unwrap();
+        let string_size = Heap::object_size(&string);
+        let string = heap.allocate(string);
+        let mut array = ArrayObject::new(2, FieldDescriptor::from_class("java/lang/String"));
+        array.set(1, string).unwrap();
+        let array = heap.allocate(Object::Array(array));
+
+        heap.collect(vec![array], &mut classloader).unwrap();
+        assert!(!heap.is_object(garbage));
+        assert!(heap.is_object(value));
+        assert!(heap.is_object(string));
+        assert_eq!(heap.size, 2 * 24 + string_size);
+        // the free slot is reused
+        assert_eq!(heap.allocate(int_array()), garbage);
+
+        heap.collect(vec![], &mut classloader).unwrap();
+        assert!(!heap.is_object(array));
+        assert_eq!(heap.size, 0);
+    }
+}
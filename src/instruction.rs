@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use classfile_parser::ClassFile;
 use classfile_parser::constant_info::ConstantInfo;
-use parsed_class::{ParsedClass, MethodRef, FieldRef};
+use parsed_class::{ParsedClass, MethodRef, FieldRef, InvokeDynamicRef, MethodHandle};
 
 #[derive(Debug, Clone, PartialEq)]
 #[allow(non_camel_case_types)]
@@ -59,9 +59,12 @@ pub enum Instruction {
 
     LDC_INT(i32),
     LDC_FLOAT(f32),
-    LDC_STRING(String),
+    LDC_STRING(Vec<u16>),
     LDC_DOUBLE(f64),
     LDC_LONG(i64),
+    LDC_CLASS(String),
+    LDC_METHOD_TYPE(String),
+    LDC_METHOD_HANDLE(MethodHandle),
 
     DUP,
     DUP_X1,
@@ -85,7 +88,7 @@ pub enum Instruction {
     LCMP,
 
     GOTO(CodeAddress),
-    // TODO: find java code, which generates jsr and ret (finally doesn't)
+    // modern javac doesn't generate jsr and ret anymore, see java/TestSubroutine.class
     JSR(CodeAddress),
     RET(LocalVarRef),
 
@@ -95,18 +98,37 @@ pub enum Instruction {
     IF(Comparison, CodeAddress),
     IFNULL(ComparisonEqual, CodeAddress),
 
+    INVOKEDYNAMIC(InvokeDynamicRef),
     INVOKEINTERFACE(MethodRef, u8),
     INVOKESPECIAL(MethodRef),
     INVOKESTATIC(MethodRef),
     INVOKEVIRTUAL(MethodRef),
 
+    // default, number of pairs, (match, address) sorted by match
     LOOKUPSWITCH(i32, i32, Vec<(i32, i32)>),
+    // default, low, high, addresses for low to high
     TABLESWITCH(i32, i32, i32, Vec<i32>),
 
     MONITORENTER,
     MONITOREXIT,
 
     NOP,
+
+    // the quickened instructions replace the instructions with symbolic references above,
+    // after they were resolved by their first execution. They are never decoded from a class file.
+    // the offset of the field and whether it is double sized
+    GETFIELD_QUICK(usize, bool),
+    PUTFIELD_QUICK(usize, bool),
+    // the class, which declares the field, is initialized
+    GETSTATIC_QUICK(ClassId, usize, bool),
+    PUTSTATIC_QUICK(ClassId, usize, bool),
+    // the method, which gets invoked
+    INVOKESPECIAL_QUICK(MethodId),
+    INVOKESTATIC_QUICK(MethodId),
+    // the index of the method in the vtables and the words of the arguments without the object
+    INVOKEVIRTUAL_QUICK(usize, usize),
+    // the resolved interface method, whose implementation is selected by the class of the object
+    INVOKEINTERFACE_QUICK(MethodId),
 }
 
 // index into the local code
@@ -115,6 +137,12 @@ pub type CodeAddress = u32;
 // index into the local variables
 pub type LocalVarRef = u16;
 
+// index into the classes, which are used by the vm
+pub type ClassId = usize;
+
+// index into the methods, which were linked by the vm
+pub type MethodId = usize;
+
 // true: equals, false: not equals
 pub type ComparisonEqual = bool;
 
@@ -169,7 +197,10 @@ impl Comparison {
 }
 
 impl Instruction {
-    pub fn decode(bytes: &[u8], parsed: &ClassFile) -> Result<Vec<Instruction>, String> {
+    /// decodes the bytecode of a method
+    /// returns the instructions and a map from byte offsets to the index of the
+    /// instruction starting there, which is needed to fix up other attributes of the code
+    pub fn decode(bytes: &[u8], parsed: &ClassFile) -> Result<(Vec<Instruction>, HashMap<usize, usize>), String> {
         use self::Instruction::*;
         use self::Type::*;
         use self::Comparison::*;
@@ -190,14 +221,24 @@ impl Instruction {
             let b2 = next_u16(index, bytes)? as u32;
             Ok((b1 << 16) | b2)
         }
+        // the operands of the switches are aligned to 4 bytes
+        fn skip_padding(index: &mut usize, bytes: &[u8]) -> Result<(), String> {
+            while *index % 4 != 0 {
+                next(index, bytes)?;
+            }
+            Ok(())
+        }
         fn class_ref(index: &mut usize, bytes: &[u8], parsed: &ClassFile) -> Result<String, String> {
-            Ok(parsed.constant_class(next_u16(index, bytes)?)?.to_owned())
+            parsed.constant_class(next_u16(index, bytes)?)
         }
         fn ldc(index: u16, parsed: &ClassFile) -> Result<Instruction, String> {
             match parsed.constant(index)? {
                 &ConstantInfo::Integer(ref s) => Ok(LDC_INT(s.value)),
                 &ConstantInfo::Float(ref s) => Ok(LDC_FLOAT(s.value)),
-                &ConstantInfo::String(ref s) => Ok(LDC_STRING(parsed.constant_utf8(s.string_index)?.to_owned())),
+                &ConstantInfo::String(_) => Ok(LDC_STRING(parsed.constant_string(index)?)),
+                &ConstantInfo::Class(_) => Ok(LDC_CLASS(parsed.constant_class(index)?)),
+                &ConstantInfo::MethodType(_) => Ok(LDC_METHOD_TYPE(parsed.constant_method_type(index)?)),
+                &ConstantInfo::MethodHandle(_) => Ok(LDC_METHOD_HANDLE(parsed.constant_method_handle(index)?)),
                 c => Err(format!("Invalid Value for LDC reference: {}", c.to_string())),
             }
         }
@@ -365,14 +406,19 @@ impl Instruction {
                 0x68 => MUL(Int),
                 0x74 => NEG(Int),
                 0xc1 => INSTANCEOF(class_ref(&mut index, bytes, parsed)?),
+                0xba => {
+                    let op = INVOKEDYNAMIC(parsed.constant_invoke_dynamic(next_u16(&mut index, bytes)?)?);
+                    next_u16(&mut index, bytes)?; // discard 0
+                    op
+                }
                 0xb9 => {
                     let op = INVOKEINTERFACE(parsed.constant_interface_method_ref(next_u16(&mut index, bytes)?)?,
                                              next(&mut index, bytes)?);
                     next(&mut index, bytes)?; // discard 0
                     op
                 }
-                0xb7 => INVOKESPECIAL(parsed.constant_method_ref(next_u16(&mut index, bytes)?)?),
-                0xb8 => INVOKESTATIC(parsed.constant_method_ref(next_u16(&mut index, bytes)?)?),
+                0xb7 => INVOKESPECIAL(parsed.constant_method_or_interface_method_ref(next_u16(&mut index, bytes)?)?),
+                0xb8 => INVOKESTATIC(parsed.constant_method_or_interface_method_ref(next_u16(&mut index, bytes)?)?),
                 0xb6 => INVOKEVIRTUAL(parsed.constant_method_ref(next_u16(&mut index, bytes)?)?),
                 0x80 => OR(Int),
                 0x70 => REM(Int),
@@ -416,7 +462,29 @@ impl Instruction {
                 0x21 => LOAD(Long, 3),
                 0x69 => MUL(Long),
                 0x75 => NEG(Long),
-                // 0xab => lookupswitch, // TODO
+                0xab => {
+                    skip_padding(&mut index, bytes)?;
+                    let default = next_u32(&mut index, bytes)? as i32 + current_index as i32;
+                    let npairs = next_u32(&mut index, bytes)? as i32;
+                    if npairs < 0 {
+                        return Err(format!("lookupswitch with negative number of pairs {}", npairs));
+                    }
+                    // the table size is untrusted, so it is checked before anything is allocated for it
+                    if npairs as u64 * 8 > (bytes.len() - index) as u64 {
+                        return Err(format!("lookupswitch with {} pairs exceeds the code", npairs));
+                    }
+                    let mut pairs = Vec::with_capacity(npairs as usize);
+                    for _ in 0..npairs {
+                        let key = next_u32(&mut index, bytes)? as i32;
+                        let address = next_u32(&mut index, bytes)? as i32 + current_index as i32;
+                        pairs.push((key, address));
+                    }
+                    // needed for the binary search
+                    if pairs.windows(2).any(|w| w[0].0 >= w[1].0) {
+                        return Err("lookupswitch pairs must be sorted".to_owned());
+                    }
+                    LOOKUPSWITCH(default, npairs, pairs)
+                }
                 0x81 => OR(Long),
                 0x71 => REM(Long),
                 0xad => RETURN(Some(Long)),
@@ -461,8 +529,42 @@ impl Instruction {
                 0x56 => ASTORE(Short),
                 0x11 => SIPUSH(next_u16(&mut index, bytes)? as i16),
                 0x5f => SWAP,
-                // 0xaa => tableswitch, // TODO
-                // 0xc4 => wide, // TODO
+                0xaa => {
+                    skip_padding(&mut index, bytes)?;
+                    let default = next_u32(&mut index, bytes)? as i32 + current_index as i32;
+                    let low = next_u32(&mut index, bytes)? as i32;
+                    let high = next_u32(&mut index, bytes)? as i32;
+                    if low > high {
+                        return Err(format!("tableswitch with low {} > high {}", low, high));
+                    }
+                    let entries = high as i64 - low as i64 + 1;
+                    if entries as u64 * 4 > (bytes.len() - index) as u64 {
+                        return Err(format!("tableswitch with {} entries exceeds the code", entries));
+                    }
+                    let mut addresses = Vec::with_capacity(entries as usize);
+                    for _ in low as i64..high as i64 + 1 {
+                        addresses.push(next_u32(&mut index, bytes)? as i32 + current_index as i32);
+                    }
+                    TABLESWITCH(default, low, high, addresses)
+                }
+                // see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc15.html#wide
+                0xc4 => {
+                    match next(&mut index, bytes)? {
+                        0x15 => LOAD(Int, next_u16(&mut index, bytes)?),
+                        0x16 => LOAD(Long, next_u16(&mut index, bytes)?),
+                        0x17 => LOAD(Float, next_u16(&mut index, bytes)?),
+                        0x18 => LOAD(Double, next_u16(&mut index, bytes)?),
+                        0x19 => LOAD(Reference, next_u16(&mut index, bytes)?),
+                        0x36 => STORE(Int, next_u16(&mut index, bytes)?),
+                        0x37 => STORE(Long, next_u16(&mut index, bytes)?),
+                        0x38 => STORE(Float, next_u16(&mut index, bytes)?),
+                        0x39 => STORE(Double, next_u16(&mut index, bytes)?),
+                        0x3a => STORE(Reference, next_u16(&mut index, bytes)?),
+                        0xa9 => RET(next_u16(&mut index, bytes)?),
+                        0x84 => IINC(next_u16(&mut index, bytes)?, next_u16(&mut index, bytes)? as i16),
+                        op => return Err(format!("Invalid Instruction {:#x} after wide", op)),
+                    }
+                }
                 op => return Err(format!("Unknown Instruction {:#x}", op)),
             });
         }
@@ -483,10 +585,22 @@ impl Instruction {
                 IF_ICMP(comp, addr) => *instr = IF_ICMP(comp, fixup_address(addr, &old_to_new_index)?),
                 IF(comp, addr) => *instr = IF(comp, fixup_address(addr, &old_to_new_index)?),
                 IFNULL(comp, addr) => *instr = IFNULL(comp, fixup_address(addr, &old_to_new_index)?),
+                LOOKUPSWITCH(ref mut default, _, ref mut pairs) => {
+                    *default = fixup_address(*default as CodeAddress, &old_to_new_index)? as i32;
+                    for pair in pairs.iter_mut() {
+                        pair.1 = fixup_address(pair.1 as CodeAddress, &old_to_new_index)? as i32;
+                    }
+                }
+                TABLESWITCH(ref mut default, _, _, ref mut addresses) => {
+                    *default = fixup_address(*default as CodeAddress, &old_to_new_index)? as i32;
+                    for address in addresses.iter_mut() {
+                        *address = fixup_address(*address as CodeAddress, &old_to_new_index)? as i32;
+                    }
+                }
                 _ => continue,
             }
         }
-        Ok(vec)
+        Ok((vec, old_to_new_index))
     }
 }
 
@@ -499,10 +613,13 @@ mod tests {
     use class::Class;
     use classfile_parser::parse_class;
 
+    fn test_instruction_path() -> String {
+        super::super::TEST_CLASSES_DIR.to_owned() + "/com/mackie/rustyjvm/TestInstruction"
+    }
+
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn get_instructions_with_desc(method_name: &str, descriptor: &str) -> Vec<Instruction> {
-        Class::from_class_file(&parse_class(
-            &(super::super::CLASSFILE_DIR.to_owned() +"/TestInstruction")).unwrap()).unwrap()
+        Class::from_class_file(&parse_class(&test_instruction_path()).unwrap()).unwrap()
             .method_by_signature(method_name, descriptor).unwrap()
             .code().unwrap()
             .code().to_vec()
@@ -648,7 +765,7 @@ mod tests {
         assert_eq!(get_instructions_with_desc("ldc", "()D"),
                    vec![LDC_INT(-1234567), STORE(Int, 0),
                         LDC_FLOAT(-1.337), STORE(Float, 1),
-                        LDC_STRING("Hallo!".to_owned()), STORE(Reference, 2),
+                        LDC_STRING("Hallo!".encode_utf16().collect()), STORE(Reference, 2),
                         LDC_LONG(-1234567), STORE(Long, 3),
                         LDC_DOUBLE(-1.337), STORE(Double, 5),
                         LOAD(Double, 5), RETURN(Some(Double))]);
@@ -662,7 +779,7 @@ mod tests {
                         INVOKESPECIAL(MethodRef::new("<init>",
                                                      "java/lang/Object",
                                                      "()V")), STORE(Reference, 1),
-                        NEW("java/lang/String".to_owned()), DUP, LDC_STRING("Hallo".to_owned()),
+                        NEW("java/lang/String".to_owned()), DUP, LDC_STRING("Hallo".encode_utf16().collect()),
                         INVOKESPECIAL(MethodRef::new("<init>",
                                                      "java/lang/String",
                                                      "(Ljava/lang/String;)V")), STORE(Reference, 2),
@@ -696,6 +813,53 @@ mod tests {
                         LOAD(Reference, 2), RETURN(Some(Reference))]);
     }
 
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_wide() {
+        assert_eq!(get_instructions("wide"),
+                   vec![BIPUSH(0), STORE(Int, 1),
+                        IINC(1, 1000),
+                        IINC(1, -32768),
+                        RETURN(None)]);
+
+        // javac only uses wide loads and stores for methods with more than 256 local variables
+        let parsed = parse_class(&test_instruction_path()).unwrap();
+        let bytes = [0xc4, 0x15, 0x01, 0x00, 0xc4, 0x37, 0xff, 0xff, 0xc4, 0x19, 0x00, 0x01,
+                     0xc4, 0x39, 0x01, 0x01, 0xc4, 0xa9, 0x02, 0x00, 0xc4, 0x84, 0x01, 0x00, 0xff, 0xff];
+        assert_eq!(Instruction::decode(&bytes, &parsed).unwrap().0,
+                   vec![LOAD(Int, 256), STORE(Long, 65535), LOAD(Reference, 1),
+                        STORE(Double, 257), RET(512), IINC(256, -1)]);
+        assert!(Instruction::decode(&[0xc4, 0x10, 0x00, 0x01], &parsed).is_err());
+        assert!(Instruction::decode(&[0xc4, 0x15, 0x01], &parsed).is_err());
+    }
+
+    #[test]
+    fn test_switch_sizes() {
+        let parsed = parse_class(&test_instruction_path()).unwrap();
+        // lookupswitch with 0x7fffffff pairs, but no pair in the code
+        let lookupswitch = [0xab, 0, 0, 0, 0, 0, 0, 1, 0x7f, 0xff, 0xff, 0xff];
+        assert_eq!(Instruction::decode(&lookupswitch, &parsed).unwrap_err(),
+                   "lookupswitch with 2147483647 pairs exceeds the code");
+        // tableswitch from -2^31 to 2^31 - 1
+        let tableswitch = [0xaa, 0, 0, 0, 0, 0, 0, 1, 0x80, 0, 0, 0, 0x7f, 0xff, 0xff, 0xff, 0, 0, 0, 1];
+        assert_eq!(Instruction::decode(&tableswitch, &parsed).unwrap_err(),
+                   "tableswitch with 4294967296 entries exceeds the code");
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_switches() {
+        assert_eq!(get_instructions_with_desc("switches", "(I)I"),
+                   vec![LOAD(Int, 1), TABLESWITCH(10, 1, 3, vec![2, 5, 8]),
+                        BIPUSH(10), STORE(Int, 1), GOTO(10),
+                        BIPUSH(20), STORE(Int, 1), GOTO(10),
+                        BIPUSH(30), STORE(Int, 1),
+                        LOAD(Int, 1), LOOKUPSWITCH(16, 2, vec![(-100, 12), (1000, 14)]),
+                        BIPUSH(1), RETURN(Some(Int)),
+                        BIPUSH(2), RETURN(Some(Int)),
+                        LOAD(Int, 1), RETURN(Some(Int))]);
+    }
+
     #[test]
     #[cfg_attr(rustfmt, rustfmt_skip)]
     fn test_jumps() {
@@ -750,4 +914,26 @@ mod tests {
                         INVOKEINTERFACE(MethodRef::new("method", "com/mackie/rustyjvm/Interface", "()V"), 1),
                         RETURN(None)]);
     }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_invokedynamic() {
+        let path = super::super::TEST_CLASSES_DIR.to_owned() + "/com/mackie/rustyjvm/TestJava8Lambda";
+        let class = Class::from_class_file(&parse_class(&path).unwrap()).unwrap();
+        assert_eq!(class.method_by_signature("create", "(I)Lcom/mackie/rustyjvm/TestJava8Function;").unwrap()
+                       .code().unwrap().code()[..],
+                   [LOAD(Int, 0),
+                         INVOKEDYNAMIC(InvokeDynamicRef::new(0, "apply", "(I)Lcom/mackie/rustyjvm/TestJava8Function;")),
+                         RETURN(Some(Reference))]);
+    }
+
+    #[test]
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn test_exceptions() {
+        let field = FieldRef::new("field", "com/mackie/rustyjvm/TestInstruction", "I").unwrap();
+        assert_eq!(get_instructions_with_desc("exceptions", "()I"),
+                   vec![LOAD(Reference, 0), BIPUSH(1), PUTFIELD(field.clone()), GOTO(8),
+                        STORE(Reference, 1), LOAD(Reference, 0), BIPUSH(2), PUTFIELD(field.clone()),
+                        LOAD(Reference, 0), GETFIELD(field), RETURN(Some(Int))]);
+    }
 }
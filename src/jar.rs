@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use inflate::InflateStream;
+
+// see https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+// the end of central directory record is followed by a comment of at most this size
+const MAX_COMMENT_SIZE: usize = 0xffff;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+const ENCRYPTED_FLAG: u16 = 1;
+
+pub const MANIFEST_NAME: &'static str = "META-INF/MANIFEST.MF";
+
+// see https://docs.oracle.com/javase/6/docs/technotes/guides/jar/jar.html
+pub const MAIN_CLASS_ATTRIBUTE: &'static str = "Main-Class";
+pub const CLASS_PATH_ATTRIBUTE: &'static str = "Class-Path";
+
+#[derive(Debug)]
+struct Entry {
+    compression: u16,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    local_header_offset: u32,
+}
+
+/// a jar or zip archive
+/// only the central directory is read, when the archive is opened, entries are read on demand
+#[derive(Debug)]
+pub struct Jar {
+    path: PathBuf,
+    file: File,
+    // the size of the file, which bounds the sizes in the headers
+    len: u64,
+    // indexed by the name of the entry
+    entries: HashMap<String, Entry>,
+}
+
+fn u16_at(bytes: &[u8], index: usize) -> u16 { (bytes[index] as u16) | (bytes[index + 1] as u16) << 8 }
+fn u32_at(bytes: &[u8], index: usize) -> u32 {
+    (u16_at(bytes, index) as u32) | (u16_at(bytes, index + 2) as u32) << 16
+}
+
+impl Jar {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Jar, String> {
+        let path = path.as_ref().to_owned();
+        let mut file = File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let entries = Jar::read_central_directory(&mut file)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let len = file.metadata().map_err(|e| format!("{}: {}", path.display(), e))?.len();
+        Ok(Jar {
+            path: path,
+            file: file,
+            len: len,
+            entries: entries,
+        })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    fn read_central_directory(file: &mut File) -> Result<HashMap<String, Entry>, String> {
+        let len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        let tail_len = ::std::cmp::min(len, (END_OF_CENTRAL_DIRECTORY_SIZE + MAX_COMMENT_SIZE) as u64) as usize;
+        if tail_len < END_OF_CENTRAL_DIRECTORY_SIZE {
+            return Err("not a zip file".to_owned());
+        }
+        let mut tail = vec![0; tail_len];
+        file.seek(SeekFrom::End(-(tail_len as i64))).map_err(|e| e.to_string())?;
+        file.read_exact(&mut tail).map_err(|e| e.to_string())?;
+
+        // the record is searched from the back, because the comment may contain the signature
+        let end = match (0..tail_len - END_OF_CENTRAL_DIRECTORY_SIZE + 1)
+            .rev()
+            .find(|&i| u32_at(&tail, i) == END_OF_CENTRAL_DIRECTORY_SIGNATURE) {
+            Some(end) => end,
+            None => return Err("end of central directory not found".to_owned()),
+        };
+        let count = u16_at(&tail, end + 10) as usize;
+        let directory_size = u32_at(&tail, end + 12) as usize;
+        let directory_offset = u32_at(&tail, end + 16) as u64;
+        // TODO support zip64
+        if directory_offset == 0xffffffff {
+            return Err("zip64 archives are not supported".to_owned());
+        }
+        // the size is checked before the directory is allocated
+        if directory_offset + directory_size as u64 > len {
+            return Err("invalid central directory".to_owned());
+        }
+
+        let mut directory = vec![0; directory_size];
+        file.seek(SeekFrom::Start(directory_offset)).map_err(|e| e.to_string())?;
+        file.read_exact(&mut directory).map_err(|e| e.to_string())?;
+
+        let mut entries = HashMap::with_capacity(count);
+        let mut index = 0;
+        for _ in 0..count {
+            if index + CENTRAL_HEADER_SIZE > directory.len() ||
+               u32_at(&directory, index) != CENTRAL_HEADER_SIGNATURE {
+                return Err("invalid central directory".to_owned());
+            }
+            let name_len = u16_at(&directory, index + 28) as usize;
+            let extra_len = u16_at(&directory, index + 30) as usize;
+            let comment_len = u16_at(&directory, index + 32) as usize;
+            let name_start = index + CENTRAL_HEADER_SIZE;
+            if name_start + name_len > directory.len() {
+                return Err("invalid central directory".to_owned());
+            }
+            let name = String::from_utf8_lossy(&directory[name_start..name_start + name_len]).into_owned();
+            if u16_at(&directory, index + 8) & ENCRYPTED_FLAG == 0 {
+                entries.insert(name,
+                               Entry {
+                                   compression: u16_at(&directory, index + 10),
+                                   compressed_size: u32_at(&directory, index + 20),
+                                   uncompressed_size: u32_at(&directory, index + 24),
+                                   local_header_offset: u32_at(&directory, index + 42),
+                               });
+            }
+            index = name_start + name_len + extra_len + comment_len;
+        }
+        Ok(entries)
+    }
+
+    /// reads the entry with the given name, None if it doesn't exist
+    pub fn read(&self, name: &str) -> Option<Result<Vec<u8>, String>> {
+        self.entries.get(name).map(|entry| self.read_entry(entry).map_err(|e| format!("{}: {}", name, e)))
+    }
+
+    fn read_entry(&self, entry: &Entry) -> Result<Vec<u8>, String> {
+        // reading and seeking is possible without a mutable file
+        let mut file = &self.file;
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        file.seek(SeekFrom::Start(entry.local_header_offset as u64)).map_err(|e| e.to_string())?;
+        file.read_exact(&mut header).map_err(|e| e.to_string())?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err("invalid local header".to_owned());
+        }
+        // the local extra field may differ from the one in the central directory
+        let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+        let start = file.seek(SeekFrom::Current(skip)).map_err(|e| e.to_string())?;
+        // the size is checked before the data is allocated
+        if start + entry.compressed_size as u64 > self.len {
+            return Err(format!("the compressed size {} exceeds the file", entry.compressed_size));
+        }
+
+        let mut data = vec![0; entry.compressed_size as usize];
+        file.read_exact(&mut data).map_err(|e| e.to_string())?;
+        let data = match entry.compression {
+            STORED => data,
+            DEFLATED => inflate(&data, entry.uncompressed_size as usize)?,
+            c => return Err(format!("unsupported compression method {}", c)),
+        };
+        if data.len() != entry.uncompressed_size as usize {
+            return Err(format!("expected {} bytes, but got {}", entry.uncompressed_size, data.len()));
+        }
+        Ok(data)
+    }
+
+    /// the attributes of the main section of the manifest, empty if there is no manifest
+    pub fn main_attributes(&self) -> Result<HashMap<String, String>, String> {
+        match self.read(MANIFEST_NAME) {
+            Some(manifest) => Ok(parse_main_attributes(&String::from_utf8_lossy(&manifest?))),
+            None => Ok(HashMap::new()),
+        }
+    }
+}
+
+/// decompresses the deflated data, but stops as soon as the output exceeds the expected size
+fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut stream = InflateStream::new();
+    let mut output = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let (read, decompressed) = stream.update(&data[index..])?;
+        if read == 0 && decompressed.is_empty() {
+            break;
+        }
+        if output.len() + decompressed.len() > size {
+            return Err(format!("expected {} bytes, but got more", size));
+        }
+        output.extend_from_slice(decompressed);
+        index += read;
+    }
+    Ok(output)
+}
+
+// see https://docs.oracle.com/javase/6/docs/technotes/guides/jar/jar.html#JAR%20Manifest
+fn parse_main_attributes(manifest: &str) -> HashMap<String, String> {
+    let mut attributes: HashMap<String, String> = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in manifest.lines() {
+        // the main section ends with an empty line
+        if line.is_empty() {
+            break;
+        }
+        // long values are continued on the next line after a single space
+        if line.starts_with(' ') {
+            if let Some(value) = last.as_ref().and_then(|name| attributes.get_mut(name)) {
+                value.push_str(&line[1..]);
+            }
+            continue;
+        }
+        last = match line.find(": ") {
+            Some(index) => {
+                attributes.insert(line[..index].to_owned(), line[index + 2..].to_owned());
+                Some(line[..index].to_owned())
+            }
+            None => None,
+        };
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn class_file(name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        File::open(format!("{}/{}.class", super::super::TEST_CLASSES_DIR, name))
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn deflated() {
+        let jar = Jar::open(super::super::TEST_APP_JAR).unwrap();
+        assert_eq!(jar.read("com/mackie/rustyjvm/TestClass.class").unwrap().unwrap(),
+                   class_file("com/mackie/rustyjvm/TestClass"));
+        assert_eq!(jar.read("Calc.class").unwrap().unwrap(), class_file("Calc"));
+        assert!(jar.read("java/lang/Object.class").is_none());
+    }
+
+    #[test]
+    fn stored() {
+        let jar = Jar::open(super::super::TEST_LIB_JAR).unwrap();
+        assert_eq!(jar.read("java/lang/Object.class").unwrap().unwrap(), class_file("java/lang/Object"));
+        assert!(jar.read("Calc.class").is_none());
+        assert!(jar.main_attributes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn manifest() {
+        let attributes = Jar::open(super::super::TEST_APP_JAR).unwrap().main_attributes().unwrap();
+        assert_eq!(attributes[MAIN_CLASS_ATTRIBUTE], "Calc");
+        assert_eq!(attributes[CLASS_PATH_ATTRIBUTE], "not-existing.jar lib.jar");
+
+        let attributes = parse_main_attributes("Manifest-Version: 1.0\r\nClass-Path: a.jar\r\n  b.jar c\r\n .jar\r\n\
+                                                Main-Class: Main\r\n\r\nName: Other\r\nMain-Class: Other\r\n");
+        assert_eq!(attributes.len(), 3);
+        assert_eq!(attributes[CLASS_PATH_ATTRIBUTE], "a.jar b.jar c.jar");
+        assert_eq!(attributes[MAIN_CLASS_ATTRIBUTE], "Main");
+    }
+
+    /// copies the application jar after changing its bytes
+    fn patched_jar<F: Fn(&mut [u8])>(name: &str, patch: F) -> PathBuf {
+        let mut bytes = Vec::new();
+        File::open(super::super::TEST_APP_JAR).unwrap().read_to_end(&mut bytes).unwrap();
+        patch(&mut bytes);
+        let path = ::std::env::temp_dir().join(format!("rusty-jvm-{}.jar", name));
+        File::create(&path).unwrap().write_all(&bytes).unwrap();
+        path
+    }
+
+    fn set_u32(bytes: &mut [u8], index: usize, value: u32) {
+        for i in 0..4 {
+            bytes[index + i] = (value >> (i * 8)) as u8;
+        }
+    }
+
+    /// the index of the central directory header of Calc.class
+    fn calc_header(bytes: &[u8]) -> usize {
+        (0..bytes.len() - CENTRAL_HEADER_SIZE)
+            .find(|&i| {
+                u32_at(bytes, i) == CENTRAL_HEADER_SIGNATURE &&
+                bytes[i + CENTRAL_HEADER_SIZE..].starts_with(b"Calc.class")
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Jar::open("./not-existing.jar").is_err());
+        assert!(Jar::open("./java/malformed.class").is_err());
+
+        // the sizes in the headers are checked against the file and the decompressed data
+        let path = patched_jar("directory-size", |b| {
+            let end = b.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+            set_u32(b, end + 12, 0xfffffff0)
+        });
+        assert!(Jar::open(path).unwrap_err().ends_with("invalid central directory"));
+        let jar = Jar::open(patched_jar("compressed-size", |b| set_u32(b, calc_header(b) + 20, 0xfffffff0))).unwrap();
+        assert!(jar.read("Calc.class").unwrap().unwrap_err().contains("exceeds the file"));
+        let jar = Jar::open(patched_jar("uncompressed-size", |b| set_u32(b, calc_header(b) + 24, 10))).unwrap();
+        assert_eq!(jar.read("Calc.class").unwrap(),
+                   Err("Calc.class: expected 10 bytes, but got more".to_owned()));
+        assert!(jar.read("com/mackie/rustyjvm/TestClass.class").unwrap().is_ok());
+    }
+}
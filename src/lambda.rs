@@ -0,0 +1,314 @@
+use classfile_parser::method_info;
+use classfile_parser::field_info;
+use class::{Class, Method, Field, Code, BootstrapMethod, BootstrapArgument, ConstantValue, OBJECT_NAME,
+            SERIALIZABLE_NAME};
+use descriptor::{FieldDescriptor, MethodDescriptor};
+use instruction::{Instruction, Type, LocalVarRef};
+use instruction::Instruction::*;
+use parsed_class::{MethodHandle, MethodRef, FieldRef, InvokeDynamicRef};
+
+// lambdas and method references are linked by invokedynamic with these bootstrap methods
+// see https://docs.oracle.com/javase/8/docs/api/java/lang/invoke/LambdaMetafactory.html
+pub const LAMBDA_METAFACTORY_NAME: &'static str = "java/lang/invoke/LambdaMetafactory";
+const METAFACTORY: &'static str = "metafactory";
+const ALT_METAFACTORY: &'static str = "altMetafactory";
+
+// the flags of altMetafactory
+const FLAG_SERIALIZABLE: i32 = 1 << 0;
+const FLAG_MARKERS: i32 = 1 << 1;
+const FLAG_BRIDGES: i32 = 1 << 2;
+
+/// the static method of a lambda class, which creates an instance from the captured arguments
+/// it has the descriptor of the call site
+pub const LAMBDA_FACTORY_NAME: &'static str = "get$Lambda";
+const CAPTURED_FIELD_PREFIX: &'static str = "arg$";
+
+/// implements LambdaMetafactory natively by synthesizing a class like the one spun by the jdk
+/// the class implements the functional interface, which is returned by the call site, and stores the
+/// captured arguments in fields. Its method forwards them together with its own arguments to the target.
+/// name is the name of the new class
+pub fn spin_lambda_class(name: &str,
+                         call_site: &InvokeDynamicRef,
+                         bootstrap: &BootstrapMethod)
+                         -> Result<Class, String> {
+    let alternative = match *bootstrap.handle() {
+        MethodHandle::InvokeStatic(ref m) if m.class() == LAMBDA_METAFACTORY_NAME && m.name() == METAFACTORY => false,
+        MethodHandle::InvokeStatic(ref m) if m.class() == LAMBDA_METAFACTORY_NAME && m.name() == ALT_METAFACTORY => {
+            true
+        }
+        ref handle => return Err(format!("unsupported bootstrap method {:?}", handle)),
+    };
+
+    let site = parse_method_descriptor(call_site.descriptor())?;
+    let interface = match site.ret_type().and_then(|r| r.get_class()) {
+        Some(interface) => interface.to_owned(),
+        None => return Err(format!("call site {} must return an interface", call_site.descriptor())),
+    };
+    let captured = site.params();
+
+    let mut arguments = bootstrap.arguments().iter();
+    let sam_type = match arguments.next() {
+        Some(&BootstrapArgument::MethodType(ref t)) => t.clone(),
+        _ => return Err("the first argument of the metafactory must be a method type".to_owned()),
+    };
+    let target = match arguments.next() {
+        Some(&BootstrapArgument::MethodHandle(ref h)) => h.clone(),
+        _ => return Err("the second argument of the metafactory must be a method handle".to_owned()),
+    };
+    // the instantiated method type only restricts the types further, the casts are done for the target
+    match arguments.next() {
+        Some(&BootstrapArgument::MethodType(_)) => {}
+        _ => return Err("the third argument of the metafactory must be a method type".to_owned()),
+    };
+
+    let mut interfaces = vec![interface];
+    let mut method_types = vec![sam_type];
+    if alternative {
+        let flags = next_int(&mut arguments)?;
+        if flags & FLAG_SERIALIZABLE != 0 {
+            interfaces.push(SERIALIZABLE_NAME.to_owned());
+        }
+        if flags & FLAG_MARKERS != 0 {
+            for _ in 0..next_int(&mut arguments)? {
+                match arguments.next() {
+                    Some(&BootstrapArgument::Class(ref c)) => interfaces.push(c.clone()),
+                    _ => return Err("marker interfaces must be classes".to_owned()),
+                }
+            }
+        }
+        if flags & FLAG_BRIDGES != 0 {
+            for _ in 0..next_int(&mut arguments)? {
+                match arguments.next() {
+                    Some(&BootstrapArgument::MethodType(ref t)) => method_types.push(t.clone()),
+                    _ => return Err("bridges must be method types".to_owned()),
+                }
+            }
+        }
+    }
+    if arguments.next().is_some() {
+        return Err("too many arguments for the metafactory".to_owned());
+    }
+
+    let fields = captured.iter()
+        .enumerate()
+        .map(|(i, d)| {
+            FieldRef::new(&format!("{}{}", CAPTURED_FIELD_PREFIX, i + 1), name, &d.to_string())
+                .expect("descriptor was parsed before")
+        })
+        .collect::<Vec<_>>();
+
+    let mut methods = vec![Method::new(method_info::PUBLIC | method_info::STATIC,
+                                       LAMBDA_FACTORY_NAME,
+                                       call_site.descriptor(),
+                                       Some(factory_code(name, &fields)))?];
+    for method_type in &method_types {
+        // bridges may be equal to the method of the interface
+        if methods.iter().any(|m| m.descriptor() == method_type) {
+            continue;
+        }
+        let code = forwarding_code(&fields, &parse_method_descriptor(method_type)?, &target)?;
+        methods.push(Method::new(method_info::PUBLIC, call_site.name(), method_type, Some(code))?);
+    }
+
+    let instance_fields = fields.iter()
+        .map(|f| Field::new(field_info::PRIVATE | field_info::FINAL, f.name(), f.descriptor()))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Class::synthetic(name, interfaces, methods, instance_fields))
+}
+
+fn next_int<'a, I: Iterator<Item = &'a BootstrapArgument>>(arguments: &mut I) -> Result<i32, String> {
+    match arguments.next() {
+        Some(&BootstrapArgument::Constant(ConstantValue::Int(i))) => Ok(i),
+        _ => Err("expected an int argument for altMetafactory".to_owned()),
+    }
+}
+
+fn parse_method_descriptor(descriptor: &str) -> Result<MethodDescriptor, String> {
+    MethodDescriptor::parse(descriptor).ok_or(format!("invalid method descriptor {}", descriptor))
+}
+
+// bytes, shorts, chars and booleans are ints on the stack and in local variables
+fn stack_type(typ: Type) -> Type {
+    match typ {
+        Type::Boolean | Type::Byte | Type::Char | Type::Short => Type::Int,
+        t => t,
+    }
+}
+
+/// stores the arguments in the fields of a new instance
+fn factory_code(class: &str, fields: &[FieldRef]) -> Code {
+    let mut code = vec![NEW(class.to_owned())];
+    let mut local = 0;
+    for field in fields {
+        code.push(DUP);
+        code.push(LOAD(stack_type(*field.typ()), local as LocalVarRef));
+        code.push(PUTFIELD(field.clone()));
+        local += field.typ().word_size();
+    }
+    code.push(RETURN(Some(Type::Reference)));
+    // the instance is on the stack twice, when a value is stored
+    Code::new(4, local, code)
+}
+
+/// loads the captured fields and the arguments and invokes the target with them
+fn forwarding_code(fields: &[FieldRef], method_type: &MethodDescriptor, target: &MethodHandle) -> Result<Code, String> {
+    let (method, instruction) = match *target {
+        MethodHandle::InvokeStatic(ref m) => (m, INVOKESTATIC(m.clone())),
+        MethodHandle::InvokeVirtual(ref m) => (m, INVOKEVIRTUAL(m.clone())),
+        MethodHandle::InvokeSpecial(ref m) |
+        MethodHandle::NewInvokeSpecial(ref m) => (m, INVOKESPECIAL(m.clone())),
+        MethodHandle::InvokeInterface(ref m) => {
+            let count = parse_method_descriptor(m.descriptor())?.words_for_params() + 1;
+            (m, INVOKEINTERFACE(m.clone(), count as u8))
+        }
+        ref handle => return Err(format!("unsupported method handle {:?} for a lambda", handle)),
+    };
+    let descriptor = parse_method_descriptor(method.descriptor())?;
+
+    // the receiver of instance methods is the first captured value or argument
+    let mut target_params = Vec::new();
+    let mut target_return = descriptor.ret_type().cloned();
+    let mut code = Vec::new();
+    match *target {
+        MethodHandle::InvokeStatic(_) => {}
+        MethodHandle::NewInvokeSpecial(_) => {
+            code.push(NEW(method.class().to_owned()));
+            code.push(DUP);
+            target_return = Some(FieldDescriptor::from_class(method.class()));
+        }
+        _ => target_params.push(FieldDescriptor::from_class(method.class())),
+    }
+    target_params.extend(descriptor.params().iter().cloned());
+    if target_params.len() != fields.len() + method_type.params().len() {
+        return Err(format!("{:?} can't be called with the captured values and {:?}", method, method_type));
+    }
+
+    let mut dest = target_params.iter();
+    for field in fields {
+        code.push(LOAD(Type::Reference, 0));
+        code.push(GETFIELD(field.clone()));
+        let source = FieldDescriptor::parse(field.descriptor()).expect("descriptor was parsed before");
+        convert(&source, dest.next().unwrap(), &mut code)?;
+    }
+    // local 0 is this
+    let mut local = 1;
+    for param in method_type.params() {
+        code.push(LOAD(stack_type(param.simple_typ()), local as LocalVarRef));
+        convert(param, dest.next().unwrap(), &mut code)?;
+        local += param.word_size();
+    }
+    code.push(instruction);
+
+    match (target_return, method_type.ret_type()) {
+        (None, None) => code.push(RETURN(None)),
+        (Some(ref ret), None) => {
+            // the result is discarded
+            code.push(if ret.is_double_sized() { POP2 } else { POP });
+            code.push(RETURN(None));
+        }
+        (Some(ref ret), Some(dest_ret)) => {
+            convert(ret, dest_ret, &mut code)?;
+            code.push(RETURN(Some(stack_type(dest_ret.simple_typ()))));
+        }
+        (None, Some(_)) => return Err(format!("{:?} must return a value", method)),
+    }
+
+    let words = target_params.iter().map(|p| p.word_size()).sum::<usize>();
+    // the new instance of a constructor needs two more slots, a conversion of the result at most two
+    Ok(Code::new(words + 4, local, code))
+}
+
+/// the class of the wrapper of a primitive type and the method to unbox it
+fn wrapper(typ: Type) -> (&'static str, &'static str) {
+    match typ {
+        Type::Boolean => ("java/lang/Boolean", "booleanValue"),
+        Type::Byte => ("java/lang/Byte", "byteValue"),
+        Type::Char => ("java/lang/Character", "charValue"),
+        Type::Short => ("java/lang/Short", "shortValue"),
+        Type::Int => ("java/lang/Integer", "intValue"),
+        Type::Long => ("java/lang/Long", "longValue"),
+        Type::Float => ("java/lang/Float", "floatValue"),
+        Type::Double => ("java/lang/Double", "doubleValue"),
+        Type::Reference => panic!("references have no wrapper"),
+    }
+}
+
+/// adapts a value of type from on top of the stack to type to, like a method invocation does
+/// references are cast, primitives are widened, boxed or unboxed
+/// see https://docs.oracle.com/javase/8/docs/api/java/lang/invoke/LambdaMetafactory.html
+fn convert(from: &FieldDescriptor, to: &FieldDescriptor, code: &mut Vec<Instruction>) -> Result<(), String> {
+    if from == to {
+        return Ok(());
+    }
+    match (from.simple_typ(), to.simple_typ()) {
+        (Type::Reference, Type::Reference) => {
+            if to.get_class() != Some(OBJECT_NAME) {
+                code.push(CHECKCAST(to.to_symbolic_reference()));
+            }
+        }
+        (Type::Reference, typ) => {
+            // unboxing
+            let (class, method) = wrapper(typ);
+            if from.get_class() != Some(class) {
+                code.push(CHECKCAST(class.to_owned()));
+            }
+            code.push(INVOKEVIRTUAL(MethodRef::new(method, class, &format!("(){}", to))));
+        }
+        (typ, Type::Reference) => {
+            // boxing, the wrapper must be assignable to the reference
+            let (class, _) = wrapper(typ);
+            code.push(INVOKESTATIC(MethodRef::new("valueOf", class, &format!("({})L{};", from, class))));
+        }
+        (from_typ, to_typ) => {
+            // widening, bytes, shorts and chars are already ints
+            match (stack_type(from_typ), to_typ) {
+                (Type::Int, Type::Int) if from_typ != Type::Boolean && to_typ != Type::Boolean => {}
+                (Type::Int, Type::Long) |
+                (Type::Int, Type::Float) |
+                (Type::Int, Type::Double) |
+                (Type::Long, Type::Float) |
+                (Type::Long, Type::Double) |
+                (Type::Float, Type::Double) if from_typ != Type::Boolean => {
+                    code.push(CONVERT(stack_type(from_typ), to_typ))
+                }
+                _ => return Err(format!("can't convert {} to {}", from, to)),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(from: &str, to: &str) -> Result<Vec<Instruction>, String> {
+        let mut code = Vec::new();
+        convert(&FieldDescriptor::parse(from).unwrap(),
+                &FieldDescriptor::parse(to).unwrap(),
+                &mut code)?;
+        Ok(code)
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(check("I", "I"), Ok(vec![]));
+        assert_eq!(check("LA;", "Ljava/lang/Object;"), Ok(vec![]));
+        assert_eq!(check("Ljava/lang/Object;", "LA;"), Ok(vec![CHECKCAST("A".to_owned())]));
+        assert_eq!(check("Ljava/lang/Object;", "[I"), Ok(vec![CHECKCAST("[I".to_owned())]));
+        assert_eq!(check("S", "I"), Ok(vec![]));
+        assert_eq!(check("C", "J"), Ok(vec![CONVERT(Type::Int, Type::Long)]));
+        assert_eq!(check("J", "D"), Ok(vec![CONVERT(Type::Long, Type::Double)]));
+        assert_eq!(check("I", "Ljava/lang/Object;"),
+                   Ok(vec![INVOKESTATIC(MethodRef::new("valueOf", "java/lang/Integer", "(I)Ljava/lang/Integer;"))]));
+        assert_eq!(check("Ljava/lang/Object;", "Z"),
+                   Ok(vec![CHECKCAST("java/lang/Boolean".to_owned()),
+                           INVOKEVIRTUAL(MethodRef::new("booleanValue", "java/lang/Boolean", "()Z"))]));
+        assert_eq!(check("Ljava/lang/Double;", "D"),
+                   Ok(vec![INVOKEVIRTUAL(MethodRef::new("doubleValue", "java/lang/Double", "()D"))]));
+        assert!(check("J", "I").is_err());
+        assert!(check("Z", "I").is_err());
+        assert!(check("I", "Z").is_err());
+    }
+}
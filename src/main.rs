@@ -12,6 +12,8 @@
 extern crate classfile_parser;
 #[macro_use]
 extern crate nom;
+extern crate inflate;
+extern crate libc;
 
 mod class_loader;
 mod class;
@@ -21,32 +23,131 @@ mod errors;
 mod vm;
 mod descriptor;
 mod object;
+mod jar;
+mod lambda;
+mod gc;
+mod reference_map;
+mod verifier;
+mod native;
+mod classpath;
+mod thread;
+mod signals;
 
 use class_loader::ClassLoader;
 use vm::VM;
+use thread::Scheduler;
 use std::env;
+use std::path::Path;
 use std::process::exit;
 use std::io::{stderr, Write};
 
-pub const CLASSFILE_DIR: &'static str = "./java";
+// the classpath used by the tests, see the Makefile
+// the classes directory contains the compiled java files, java contains the hand assembled classes
+#[cfg(test)]
+pub const TEST_CLASSES_DIR: &'static str = "./classes";
+#[cfg(test)]
+pub const TEST_CLASSPATH: &'static str = "./classes:./java";
+// the application jar is deflated and references the library jar, which is stored uncompressed
+#[cfg(test)]
+pub const TEST_APP_JAR: &'static str = "./classes/app.jar";
+#[cfg(test)]
+pub const TEST_LIB_JAR: &'static str = "./classes/lib.jar";
+
+const DEFAULT_CLASSPATH: &'static str = ".";
+const DEFAULT_MAX_HEAP_SIZE: usize = 256 << 20;
+// the number of instructions, which a thread executes before the next thread is scheduled
+const DEFAULT_QUANTUM: usize = 10000;
+
+fn usage() -> ! {
+    writeln!(&mut stderr(),
+             "Usage: {0} [<options>] [-cp <classpath>] <classname> <args>\n       \
+              {0} [<options>] -jar <jarfile> <args>\n\
+              Options:\n    \
+              -Xmx<size>          the maximum size of the heap, e.g. 512m\n    \
+              -Xquantum<count>    the number of instructions, which a thread executes at once\n    \
+              -Xseed<seed>        schedules the threads randomly, the same seed gives the same schedule\n    \
+              -Xvtables           prints the vtable of each loaded class to stderr",
+             env::args().nth(0).unwrap())
+        .expect("stderr writing failed");
+    exit(1);
+}
+
+/// parses the size given to -Xmx in bytes, the suffixes k, m and g multiply it by 1024, 1024^2 and 1024^3
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, shift) = match size.chars().last() {
+        Some('k') | Some('K') => (&size[..size.len() - 1], 10),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 20),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    digits.parse::<usize>().ok().and_then(|s| s.checked_mul(1 << shift))
+}
 
 fn main() {
-    let dest = match env::args().nth(1) {
-        Some(s) => s,
-        None => {
-            writeln!(&mut stderr(),
-                     "Usage: {} <classname> <args>",
-                     env::args().nth(0).unwrap())
-                .expect("stderr writing failed");
-            exit(1);
+    let mut args = env::args().skip(1);
+    let mut class_path = DEFAULT_CLASSPATH.to_owned();
+    let mut max_heap_size = DEFAULT_MAX_HEAP_SIZE;
+    let mut quantum = DEFAULT_QUANTUM;
+    let mut seed = None;
+    let mut dump_vtables = false;
+    let (mut classloader, dest) = loop {
+        match args.next() {
+            Some(ref option) if option == "-cp" || option == "-classpath" => {
+                class_path = match args.next() {
+                    Some(c) => c,
+                    None => usage(),
+                }
+            }
+            Some(ref option) if option.starts_with("-Xmx") => {
+                max_heap_size = match parse_size(&option["-Xmx".len()..]) {
+                    Some(s) => s,
+                    None => usage(),
+                }
+            }
+            Some(ref option) if option.starts_with("-Xquantum") => {
+                quantum = match option["-Xquantum".len()..].parse() {
+                    Ok(q) if q > 0 => q,
+                    _ => usage(),
+                }
+            }
+            Some(ref option) if option.starts_with("-Xseed") => {
+                seed = match option["-Xseed".len()..].parse() {
+                    Ok(s) => Some(s),
+                    Err(..) => usage(),
+                }
+            }
+            Some(ref option) if option == "-Xvtables" => dump_vtables = true,
+            // the classpath is ignored and the main class is taken from the manifest
+            Some(ref option) if option == "-jar" => {
+                let jar = match args.next() {
+                    Some(j) => j,
+                    None => usage(),
+                };
+                match ClassLoader::for_jar(Path::new(&jar)) {
+                    Ok(loader_and_class) => break loader_and_class,
+                    Err(ref err) => {
+                        writeln!(&mut stderr(), "Error opening jar: {}", err).expect("stderr writing failed");
+                        exit(1);
+                    }
+                }
+            }
+            Some(s) => break (ClassLoader::new(&class_path), s),
+            None => usage(),
         }
     };
 
-    let classloader = ClassLoader::new(CLASSFILE_DIR);
+    let args: Vec<String> = args.collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    if dump_vtables {
+        classloader.dump_vtables();
+    }
 
-    let mut vm = VM::new(classloader);
-    match vm.start(&dest, &[]) {
-        Ok(..) => {}
+    signals::handle_sigquit();
+    let mut vm = VM::new(classloader, max_heap_size, Scheduler::new(quantum, seed));
+    match vm.start(&dest, &args) {
+        Ok(0) => {}
+        Ok(status) => exit(status),
         Err(ref err) => {
             writeln!(&mut stderr(), "Error running: {}", err).expect("stderr writing failed");
             exit(1);
@@ -0,0 +1,424 @@
+use vm::VM;
+use instruction::Type;
+use parsed_class::MethodRef;
+use descriptor::FieldDescriptor;
+use object::Object;
+use class::{Class, CLONEABLE_NAME};
+use classpath;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::mem;
+use std::char;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const NULL_POINTER_EXCEPTION: &'static str = "java/lang/NullPointerException";
+const ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION: &'static str = "java/lang/ArrayIndexOutOfBoundsException";
+const ARRAY_STORE_EXCEPTION: &'static str = "java/lang/ArrayStoreException";
+const CLONE_NOT_SUPPORTED_EXCEPTION: &'static str = "java/lang/CloneNotSupportedException";
+
+/// an argument or the result of a native method
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    // also booleans, bytes, chars and shorts
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Reference(i32),
+}
+
+/// the result of a native method, the error is the name of the exception, which is thrown
+pub type NativeResult = Result<Option<Value>, &'static str>;
+
+/// a native method gets the vm and its arguments, which start with the object for non static methods
+pub type NativeMethod = Rc<Fn(&mut VM, &[Value]) -> NativeResult>;
+
+/// the rust implementations of native methods
+pub struct NativeRegistry {
+    methods: HashMap<MethodRef, NativeMethod>,
+}
+
+impl Value {
+    /// reads a value of the type from the words on the operand stack
+    pub fn from_words(typ: Type, words: &[i32]) -> Value {
+        match typ {
+            Type::Reference => Value::Reference(words[0]),
+            Type::Float => Value::Float(unsafe { mem::transmute(words[0]) }),
+            Type::Long => Value::Long(unsafe { mem::transmute([words[0], words[1]]) }),
+            Type::Double => Value::Double(unsafe { mem::transmute([words[0], words[1]]) }),
+            Type::Boolean | Type::Byte | Type::Char | Type::Short | Type::Int => Value::Int(words[0]),
+        }
+    }
+
+    /// the words, which represent the value on the operand stack
+    pub fn to_words(&self) -> Vec<i32> {
+        match *self {
+            Value::Int(i) | Value::Reference(i) => vec![i],
+            Value::Float(f) => vec![unsafe { mem::transmute(f) }],
+            Value::Long(l) => unsafe { mem::transmute::<_, [i32; 2]>(l) }.to_vec(),
+            Value::Double(d) => unsafe { mem::transmute::<_, [i32; 2]>(d) }.to_vec(),
+        }
+    }
+
+    // the accessors panic on the wrong type, because the types are given by the descriptor of the method
+    pub fn as_int(&self) -> i32 {
+        match *self {
+            Value::Int(i) => i,
+            v => panic!("expected int, got {:?}", v),
+        }
+    }
+    pub fn as_long(&self) -> i64 {
+        match *self {
+            Value::Long(l) => l,
+            v => panic!("expected long, got {:?}", v),
+        }
+    }
+    pub fn as_float(&self) -> f32 {
+        match *self {
+            Value::Float(f) => f,
+            v => panic!("expected float, got {:?}", v),
+        }
+    }
+    pub fn as_double(&self) -> f64 {
+        match *self {
+            Value::Double(d) => d,
+            v => panic!("expected double, got {:?}", v),
+        }
+    }
+    pub fn as_reference(&self) -> i32 {
+        match *self {
+            Value::Reference(r) => r,
+            v => panic!("expected reference, got {:?}", v),
+        }
+    }
+}
+
+impl NativeRegistry {
+    /// creates a registry with the native methods of the core classes
+    /// the classes follow the OpenJDK, the vm interface of GNU Classpath is registered by the classpath module
+    pub fn new() -> NativeRegistry {
+        let mut registry = NativeRegistry { methods: HashMap::new() };
+
+        // the reference is unique as long as the object lives
+        registry.register("java/lang/Object", "hashCode", "()I", |_, args| {
+            Ok(Some(Value::Int(args[0].as_reference())))
+        });
+        registry.register("java/lang/System", "identityHashCode", "(Ljava/lang/Object;)I", |_, args| {
+            Ok(Some(Value::Int(args[0].as_reference())))
+        });
+        registry.register("java/lang/Object", "getClass", "()Ljava/lang/Class;", |vm, args| {
+            let typ = vm.heap().get(args[0].as_reference()).typ().clone();
+            Ok(Some(Value::Reference(vm.class_object(&typ))))
+        });
+        registry.register("java/lang/Object", "clone", "()Ljava/lang/Object;", |vm, args| {
+            clone(vm, args[0].as_reference()).map(|o| Some(Value::Reference(o)))
+        });
+        registry.register("java/lang/Object", "wait", "(J)V", |vm, args| {
+            vm.wait(args[0].as_reference(), args[1].as_long()).map(|_| None)
+        });
+        registry.register("java/lang/Object", "notify", "()V", |vm, args| {
+            vm.notify(args[0].as_reference(), false).map(|_| None)
+        });
+        registry.register("java/lang/Object", "notifyAll", "()V", |vm, args| {
+            vm.notify(args[0].as_reference(), true).map(|_| None)
+        });
+        registry.register("java/lang/Thread", "start0", "()V", |vm, args| {
+            let thread = args[0].as_reference();
+            vm.start_thread(thread, thread, MethodRef::new("run", "java/lang/Thread", "()V"));
+            Ok(None)
+        });
+        registry.register("java/lang/Thread", "isAlive", "()Z", |vm, args| {
+            Ok(Some(Value::Int(vm.is_alive(args[0].as_reference()) as i32)))
+        });
+        registry.register("java/lang/Thread", "currentThread", "()Ljava/lang/Thread;", |vm, _| {
+            Ok(Some(Value::Reference(vm.current_thread_object())))
+        });
+        registry.register("java/lang/Thread", "yield", "()V", |vm, _| {
+            vm.yield_thread();
+            Ok(None)
+        });
+        registry.register("java/lang/Thread", "sleep", "(J)V", |vm, args| vm.sleep(args[0].as_long()).map(|_| None));
+        registry.register("java/lang/Class", "getName0", "()Ljava/lang/String;", |vm, args| {
+            Ok(Some(Value::Reference(class_name(vm, args[0].as_reference()))))
+        });
+        registry.register("java/lang/Throwable", "fillInStackTrace", "()Ljava/lang/Throwable;", |vm, args| {
+            vm.fill_in_stack_trace(args[0].as_reference());
+            Ok(Some(args[0]))
+        });
+        registry.register("java/lang/String", "intern", "()Ljava/lang/String;", |vm, args| {
+            Ok(Some(Value::Reference(intern(vm, args[0].as_reference()))))
+        });
+        registry.register("java/lang/System",
+                          "arraycopy",
+                          "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+                          |vm, args| {
+            arraycopy(vm,
+                      args[0].as_reference(),
+                      args[1].as_int(),
+                      args[2].as_reference(),
+                      args[3].as_int(),
+                      args[4].as_int())
+                .map(|_| None)
+        });
+        registry.register("java/lang/System", "currentTimeMillis", "()J", |_, _| {
+            let time = SystemTime::now().duration_since(UNIX_EPOCH).expect("the clock is set before 1970");
+            Ok(Some(Value::Long(time.as_secs() as i64 * 1000 + time.subsec_nanos() as i64 / 1_000_000)))
+        });
+        // the origin of nanoTime is arbitrary, so the creation of the registry is used
+        let start = Instant::now();
+        registry.register("java/lang/System", "nanoTime", "()J", move |_, _| {
+            let time = start.elapsed();
+            Ok(Some(Value::Long(time.as_secs() as i64 * 1_000_000_000 + time.subsec_nanos() as i64)))
+        });
+        // TODO replace with a real output stream
+        registry.register("java/io/PrintStream", "dump_char", "(C)V", |_, args| {
+            print!("{}", char::from_u32(args[0].as_int() as u32).unwrap_or('?'));
+            Ok(None)
+        });
+
+        registry.register("java/lang/Float", "floatToRawIntBits", "(F)I", |_, args| {
+            Ok(Some(Value::Int(unsafe { mem::transmute(args[0].as_float()) })))
+        });
+        registry.register("java/lang/Float", "intBitsToFloat", "(I)F", |_, args| {
+            Ok(Some(Value::Float(unsafe { mem::transmute(args[0].as_int()) })))
+        });
+        registry.register("java/lang/Double", "doubleToRawLongBits", "(D)J", |_, args| {
+            Ok(Some(Value::Long(unsafe { mem::transmute(args[0].as_double()) })))
+        });
+        registry.register("java/lang/Double", "longBitsToDouble", "(J)D", |_, args| {
+            Ok(Some(Value::Double(unsafe { mem::transmute(args[0].as_long()) })))
+        });
+
+        // Math may be less accurate than StrictMath, so both can use the same implementation
+        for class in &["java/lang/Math", "java/lang/StrictMath"] {
+            registry.register_math(class, "sin", f64::sin);
+            registry.register_math(class, "cos", f64::cos);
+            registry.register_math(class, "tan", f64::tan);
+            registry.register_math(class, "asin", f64::asin);
+            registry.register_math(class, "acos", f64::acos);
+            registry.register_math(class, "atan", f64::atan);
+            registry.register_math(class, "exp", f64::exp);
+            registry.register_math(class, "log", f64::ln);
+            registry.register_math(class, "log10", f64::log10);
+            registry.register_math(class, "sqrt", f64::sqrt);
+            registry.register_math(class, "cbrt", f64::cbrt);
+            registry.register_math(class, "sinh", f64::sinh);
+            registry.register_math(class, "cosh", f64::cosh);
+            registry.register_math(class, "tanh", f64::tanh);
+            registry.register_math(class, "expm1", f64::exp_m1);
+            registry.register_math(class, "log1p", f64::ln_1p);
+            registry.register_math2(class, "atan2", f64::atan2);
+            registry.register_math2(class, "pow", pow);
+            registry.register_math2(class, "hypot", f64::hypot);
+            registry.register_math2(class, "IEEEremainder", ieee_remainder);
+        }
+        classpath::register(&mut registry);
+        registry
+    }
+
+    pub fn register<F>(&mut self, class: &str, name: &str, descriptor: &str, method: F)
+        where F: Fn(&mut VM, &[Value]) -> NativeResult + 'static
+    {
+        self.methods.insert(MethodRef::new(name, class, descriptor), Rc::new(method));
+    }
+
+    pub fn register_math(&mut self, class: &str, name: &str, function: fn(f64) -> f64) {
+        self.register(class, name, "(D)D", move |_, args| Ok(Some(Value::Double(function(args[0].as_double())))));
+    }
+
+    pub fn register_math2(&mut self, class: &str, name: &str, function: fn(f64, f64) -> f64) {
+        self.register(class, name, "(DD)D", move |_, args| {
+            Ok(Some(Value::Double(function(args[0].as_double(), args[1].as_double()))))
+        });
+    }
+
+    pub fn get(&self, method: &MethodRef) -> Option<NativeMethod> { self.methods.get(method).cloned() }
+}
+
+/// x raised to the power of y, unlike C the result is NaN, if y is NaN or if x is 1 or -1 and y is infinite
+/// see https://docs.oracle.com/javase/8/docs/api/java/lang/Math.html#pow-double-double-
+pub fn pow(x: f64, y: f64) -> f64 {
+    if y.is_nan() || x.abs() == 1.0 && y.is_infinite() {
+        f64::NAN
+    } else {
+        x.powf(y)
+    }
+}
+
+/// the remainder of x / y, where the quotient is rounded to the nearest integer and to even on ties
+/// see https://docs.oracle.com/javase/8/docs/api/java/lang/Math.html#IEEEremainder-double-double-
+pub fn ieee_remainder(x: f64, y: f64) -> f64 {
+    let remainder = x % y;
+    let half = y.abs() / 2.0;
+    // the truncated quotient is odd
+    let odd = (x % (2.0 * y)).abs() >= y.abs();
+    if remainder.abs() < half || (remainder.abs() == half && !odd) {
+        remainder
+    } else if remainder < 0.0 {
+        remainder + y.abs()
+    } else {
+        remainder - y.abs()
+    }
+}
+
+/// copies the elements and throws the exceptions described in
+/// https://docs.oracle.com/javase/8/docs/api/java/lang/System.html
+pub fn arraycopy(vm: &mut VM,
+                 src: i32,
+                 src_pos: i32,
+                 dest: i32,
+                 dest_pos: i32,
+                 length: i32)
+                 -> Result<(), &'static str> {
+    if src == 0 || dest == 0 {
+        return Err(NULL_POINTER_EXCEPTION);
+    }
+    let (src_typ, src_length) = array_type(vm, src)?;
+    let (dest_typ, dest_length) = array_type(vm, dest)?;
+    if (*src_typ != Type::Reference || *dest_typ != Type::Reference) && src_typ != dest_typ {
+        return Err(ARRAY_STORE_EXCEPTION);
+    }
+    if src_pos < 0 || dest_pos < 0 || length < 0 || src_pos as i64 + length as i64 > src_length as i64 ||
+       dest_pos as i64 + length as i64 > dest_length as i64 {
+        return Err(ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+    }
+
+    let words = src_typ.word_size();
+    // the source is copied first, because the arrays may be the same
+    let values = vm.heap().get(src).as_array().data()[src_pos as usize * words..(src_pos + length) as usize * words]
+        .to_vec();
+    // all elements are stored, if every element of the source type can be stored in the destination
+    let mut count = values.len();
+    if *src_typ == Type::Reference && !is_assignable(vm, &src_typ, &dest_typ) {
+        for (index, &value) in values.iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            let typ = vm.heap().get(value).typ().clone();
+            if !is_assignable(vm, &typ, &dest_typ) {
+                count = index;
+                break;
+            }
+        }
+    }
+    let start = dest_pos as usize * words;
+    vm.heap().get(dest).as_array().data_mut()[start..start + count].copy_from_slice(&values[..count]);
+    if count < values.len() {
+        return Err(ARRAY_STORE_EXCEPTION);
+    }
+    Ok(())
+}
+
+/// creates a shallow copy of an array or an instance of a class, which implements Cloneable
+pub fn clone(vm: &mut VM, object: i32) -> Result<i32, &'static str> {
+    if object == 0 {
+        return Err(NULL_POINTER_EXCEPTION);
+    }
+    let copy = vm.heap().get(object).clone();
+    if let Object::Instance(..) = copy {
+        if !is_assignable(vm, copy.typ(), &FieldDescriptor::from_class(CLONEABLE_NAME)) {
+            return Err(CLONE_NOT_SUPPORTED_EXCEPTION);
+        }
+    }
+    Ok(vm.heap().allocate(copy))
+}
+
+/// the binary name of the class, e.g. java.lang.String or [I, or the name of the primitive type, e.g. int
+pub fn class_name(vm: &mut VM, class_object: i32) -> i32 {
+    let name = if vm.is_void_class(class_object) {
+        "void".to_owned()
+    } else {
+        let typ = vm.class_type(class_object).expect("not a class object");
+        match typ.simple_typ() {
+            Type::Reference => typ.to_symbolic_reference().replace('/', "."),
+            primitive => primitive_name(primitive).to_owned(),
+        }
+    };
+    let name: Vec<u16> = name.encode_utf16().collect();
+    vm.create_string(&name)
+}
+
+fn primitive_name(typ: Type) -> &'static str {
+    match typ {
+        Type::Boolean => "boolean",
+        Type::Byte => "byte",
+        Type::Char => "char",
+        Type::Short => "short",
+        Type::Int => "int",
+        Type::Long => "long",
+        Type::Float => "float",
+        Type::Double => "double",
+        Type::Reference => panic!("a reference isn't a primitive type"),
+    }
+}
+
+/// returns the interned string with the characters of the string
+pub fn intern(vm: &mut VM, string: i32) -> i32 {
+    let chars = vm.string_chars(string);
+    vm.intern_string(&chars)
+}
+
+/// the type of the elements and the length of the array
+fn array_type(vm: &mut VM, array: i32) -> Result<(FieldDescriptor, i32), &'static str> {
+    match *vm.heap().get(array) {
+        Object::Array(ref a) => {
+            let mut typ = a.typ().clone();
+            typ.remove_array();
+            Ok((typ, a.length()))
+        }
+        Object::Instance(..) => Err(ARRAY_STORE_EXCEPTION),
+    }
+}
+
+pub fn is_assignable(vm: &mut VM, typ: &FieldDescriptor, dest: &FieldDescriptor) -> bool {
+    // primitive types are only assignable to themselves
+    if typ.simple_typ() != Type::Reference || dest.simple_typ() != Type::Reference {
+        return typ == dest;
+    }
+    // TODO replace expect with exception throw
+    Class::is_instance_of(typ, dest.clone(), vm.classloader()).expect("the classes of objects can be loaded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::INFINITY;
+
+    #[test]
+    fn words() {
+        for value in &[Value::Int(-3), Value::Long(-1 << 40), Value::Float(1.5), Value::Double(-2.5)] {
+            let typ = match *value {
+                Value::Int(..) => Type::Int,
+                Value::Long(..) => Type::Long,
+                Value::Float(..) => Type::Float,
+                Value::Double(..) => Type::Double,
+                Value::Reference(..) => Type::Reference,
+            };
+            assert_eq!(value.to_words().len(), typ.word_size());
+            assert_eq!(Value::from_words(typ, &value.to_words()), *value);
+        }
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(pow(2.0, 10.0), 1024.0);
+        assert_eq!(pow(f64::NAN, 0.0), 1.0);
+        assert_eq!(pow(-2.0, INFINITY), INFINITY);
+        assert!(pow(1.0, f64::NAN).is_nan());
+        assert!(pow(-1.0, INFINITY).is_nan());
+        assert!(pow(1.0, f64::NEG_INFINITY).is_nan());
+        assert!(pow(-8.0, 1.0 / 3.0).is_nan());
+    }
+
+    #[test]
+    fn remainder() {
+        assert_eq!(ieee_remainder(5.0, 2.0), 1.0);
+        assert_eq!(ieee_remainder(7.0, 2.0), -1.0);
+        assert_eq!(ieee_remainder(-7.0, 2.0), 1.0);
+        assert_eq!(ieee_remainder(5.0, 3.0), -1.0);
+        assert_eq!(ieee_remainder(4.0, 3.0), 1.0);
+        assert_eq!(ieee_remainder(3.0, INFINITY), 3.0);
+        assert!(ieee_remainder(3.0, 0.0).is_nan());
+        assert!(ieee_remainder(INFINITY, 2.0).is_nan());
+    }
+}
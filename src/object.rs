@@ -3,14 +3,15 @@ use errors::ClassLoadingError;
 use class::Class;
 use parsed_class::FieldRef;
 use descriptor::FieldDescriptor;
+use instruction::Type;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Object {
     Array(ArrayObject),
     Instance(InstanceObject),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ArrayObject {
     length: i32,
     data: Box<[i32]>,
@@ -18,7 +19,7 @@ pub struct ArrayObject {
     content_needs_two_words: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InstanceObject {
     typ: FieldDescriptor,
     data: Box<[i32]>,
@@ -73,24 +74,45 @@ impl ArrayObject {
 
     pub fn length(&self) -> i32 { self.length }
     pub fn typ(&self) -> &FieldDescriptor { &self.typ }
+    pub fn data(&self) -> &[i32] { &self.data }
+    pub fn data_mut(&mut self) -> &mut [i32] { &mut self.data }
+    pub fn contains_references(&self) -> bool { self.typ.as_type_without_arrays(1) == Type::Reference }
 
-    pub fn get(&self, index: i32) -> i32 {
+    #[inline(always)]
+    pub fn is_valid_index(&self, index: i32) -> bool { index >= 0 && index < self.length }
+
+    // the accessors return None, if the index is out of bounds
+    pub fn get(&self, index: i32) -> Option<i32> {
         assert!(!self.content_needs_two_words);
-        self.data[index as usize]
+        if !self.is_valid_index(index) {
+            return None;
+        }
+        Some(self.data[index as usize])
     }
-    pub fn get2(&self, index: i32) -> [i32; 2] {
+    pub fn get2(&self, index: i32) -> Option<[i32; 2]> {
         assert!(self.content_needs_two_words);
-        [self.data[2 * (index as usize)], self.data[2 * (index as usize) + 1]]
+        if !self.is_valid_index(index) {
+            return None;
+        }
+        Some([self.data[2 * (index as usize)], self.data[2 * (index as usize) + 1]])
     }
 
-    pub fn set(&mut self, index: i32, val: i32) {
+    pub fn set(&mut self, index: i32, val: i32) -> Option<()> {
         assert!(!self.content_needs_two_words);
+        if !self.is_valid_index(index) {
+            return None;
+        }
         self.data[index as usize] = val;
+        Some(())
     }
-    pub fn set2(&mut self, index: i32, val: [i32; 2]) {
+    pub fn set2(&mut self, index: i32, val: [i32; 2]) -> Option<()> {
         assert!(self.content_needs_two_words);
+        if !self.is_valid_index(index) {
+            return None;
+        }
         self.data[2 * (index as usize)] = val[0];
         self.data[2 * (index as usize) + 1] = val[1];
+        Some(())
     }
 }
 
@@ -106,19 +128,13 @@ impl InstanceObject {
     }
 
     pub fn typ(&self) -> &FieldDescriptor { &self.typ }
+    pub fn data(&self) -> &[i32] { &self.data }
+    pub fn data_mut(&mut self) -> &mut [i32] { &mut self.data }
 
     pub fn get_field(&self, fieldref: &FieldRef, classloader: &mut ClassLoader) -> Result<i32, ClassLoadingError> {
         Ok(self.data[Class::get_field_offset(fieldref, classloader)?])
     }
 
-    pub fn get_field2(&self,
-                      fieldref: &FieldRef,
-                      classloader: &mut ClassLoader)
-                      -> Result<[i32; 2], ClassLoadingError> {
-        let offset = Class::get_field_offset(fieldref, classloader)?;
-        Ok([self.data[offset], self.data[offset + 1]])
-    }
-
     pub fn set_field(&mut self,
                      fieldref: &FieldRef,
                      val: i32,
@@ -128,61 +144,47 @@ impl InstanceObject {
         Ok(())
     }
 
-    pub fn set_field2(&mut self,
-                      fieldref: &FieldRef,
-                      val: [i32; 2],
-                      classloader: &mut ClassLoader)
-                      -> Result<(), ClassLoadingError> {
-        let offset = Class::get_field_offset(fieldref, classloader)?;
-        self.data[offset] = val[0];
-        self.data[offset + 1] = val[1];
-        Ok(())
-    }
-
     pub fn class(&self) -> &str { self.typ.get_class().unwrap() }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use instruction::Type;
 
     #[test]
     fn array() {
         let mut array = ArrayObject::new(3, FieldDescriptor::from_type_without_reference(Type::Long));
-        array.set2(0, [1, 2]);
-        array.set2(1, [3, 4]);
-        assert_eq!(array.get2(0), [1, 2]);
-        assert_eq!(array.get2(1), [3, 4]);
-        assert_eq!(array.get2(2), [0, 0]);
+        assert_eq!(array.set2(0, [1, 2]), Some(()));
+        assert_eq!(array.set2(1, [3, 4]), Some(()));
+        assert_eq!(array.get2(0), Some([1, 2]));
+        assert_eq!(array.get2(1), Some([3, 4]));
+        assert_eq!(array.get2(2), Some([0, 0]));
+        assert_eq!(array.get2(3), None);
+        assert_eq!(array.get2(-1), None);
+        assert_eq!(array.set2(3, [5, 6]), None);
+        assert_eq!(array.set2(-1, [5, 6]), None);
     }
 
     #[test]
     fn instance() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         let mut instance = InstanceObject::new("com/mackie/rustyjvm/TestObject", &mut classloader).unwrap();
         assert_eq!(instance.data.len(), 8);
         assert_eq!(instance.get_field(&FieldRef::new("a", "com/mackie/rustyjvm/TestObject", "I").unwrap(),
                                   &mut classloader)
                        .unwrap(),
                    0);
-        assert_eq!(instance.get_field2(&FieldRef::new("c", "com/mackie/rustyjvm/TestObject", "J").unwrap(),
-                                   &mut classloader)
-                       .unwrap(),
-                   [0, 0]);
-        assert_eq!(instance.set_field2(&FieldRef::new("c", "com/mackie/rustyjvm/TestObject", "J").unwrap(),
-                                   [1, 2],
-                                   &mut classloader)
-                       .unwrap(),
-                   ());
+        // longs and doubles are accessed through their offset
+        let c = Class::get_field_offset(&FieldRef::new("c", "com/mackie/rustyjvm/TestObject", "J").unwrap(),
+                                        &mut classloader)
+            .unwrap();
+        assert_eq!(&instance.data()[c..c + 2], &[0, 0]);
+        instance.data_mut()[c..c + 2].copy_from_slice(&[1, 2]);
         assert_eq!(instance.get_field(&FieldRef::new("a", "com/mackie/rustyjvm/TestObject", "I").unwrap(),
                                   &mut classloader)
                        .unwrap(),
                    0);
-        assert_eq!(instance.get_field2(&FieldRef::new("c", "com/mackie/rustyjvm/TestObject", "J").unwrap(),
-                                   &mut classloader)
-                       .unwrap(),
-                   [1, 2]);
+        assert_eq!(&instance.data()[c..c + 2], &[1, 2]);
         assert_eq!(instance.set_field(&FieldRef::new("a", "com/mackie/rustyjvm/TestObject", "I").unwrap(),
                                   3,
                                   &mut classloader)
@@ -192,10 +194,7 @@ mod tests {
                                   &mut classloader)
                        .unwrap(),
                    3);
-        assert_eq!(instance.get_field2(&FieldRef::new("c", "com/mackie/rustyjvm/TestObject", "J").unwrap(),
-                                   &mut classloader)
-                       .unwrap(),
-                   [1, 2]);
+        assert_eq!(&instance.data()[c..c + 2], &[1, 2]);
     }
 
 }
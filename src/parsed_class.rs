@@ -5,12 +5,18 @@ use instruction::Type;
 
 pub trait ParsedClass {
     fn constant(&self, index: u16) -> Result<&ConstantInfo, String>;
-    fn constant_utf8(&self, index: u16) -> Result<&str, String>;
-    fn constant_class(&self, index: u16) -> Result<&str, String>;
-    fn constant_name_and_type(&self, index: u16) -> Result<(&str, &str), String>;
+    fn constant_utf16(&self, index: u16) -> Result<Vec<u16>, String>;
+    fn constant_utf8(&self, index: u16) -> Result<String, String>;
+    fn constant_string(&self, index: u16) -> Result<Vec<u16>, String>;
+    fn constant_class(&self, index: u16) -> Result<String, String>;
+    fn constant_name_and_type(&self, index: u16) -> Result<(String, String), String>;
     fn constant_field_ref(&self, index: u16) -> Result<FieldRef, String>;
     fn constant_method_ref(&self, index: u16) -> Result<MethodRef, String>;
     fn constant_interface_method_ref(&self, index: u16) -> Result<MethodRef, String>;
+    fn constant_method_or_interface_method_ref(&self, index: u16) -> Result<MethodRef, String>;
+    fn constant_method_handle(&self, index: u16) -> Result<MethodHandle, String>;
+    fn constant_method_type(&self, index: u16) -> Result<String, String>;
+    fn constant_invoke_dynamic(&self, index: u16) -> Result<InvokeDynamicRef, String>;
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -45,7 +51,7 @@ impl FieldRef {
     pub fn typ(&self) -> &Type { &self.typ }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MethodRef {
     name: String,
     class: String,
@@ -69,6 +75,47 @@ impl MethodRef {
     pub fn descriptor(&self) -> &str { &self.descriptor }
 }
 
+/// the call site of an invokedynamic instruction
+/// the bootstrap method is an index into the BootstrapMethods attribute of the class
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvokeDynamicRef {
+    bootstrap_method: u16,
+    name: String,
+    descriptor: String,
+}
+impl InvokeDynamicRef {
+    #[allow(dead_code)]
+    pub fn new(bootstrap_method: u16, name: &str, descriptor: &str) -> InvokeDynamicRef {
+        InvokeDynamicRef {
+            bootstrap_method: bootstrap_method,
+            name: name.to_owned(),
+            descriptor: descriptor.to_owned(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn bootstrap_method(&self) -> u16 { self.bootstrap_method }
+    #[inline(always)]
+    pub fn name(&self) -> &str { &self.name }
+    #[inline(always)]
+    pub fn descriptor(&self) -> &str { &self.descriptor }
+}
+
+/// the reference of a method handle constant, named after its kind
+/// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.4.3.5
+#[derive(Debug, Clone, PartialEq)]
+pub enum MethodHandle {
+    GetField(FieldRef),
+    GetStatic(FieldRef),
+    PutField(FieldRef),
+    PutStatic(FieldRef),
+    InvokeVirtual(MethodRef),
+    InvokeStatic(MethodRef),
+    InvokeSpecial(MethodRef),
+    NewInvokeSpecial(MethodRef),
+    InvokeInterface(MethodRef),
+}
+
 impl ParsedClass for ClassFile {
     fn constant(&self, index: u16) -> Result<&ConstantInfo, String> {
         if index == 0 || index as usize > self.const_pool.len() {
@@ -77,21 +124,35 @@ impl ParsedClass for ClassFile {
         Ok(&self.const_pool[(index - 1) as usize])
     }
 
-    fn constant_utf8(&self, index: u16) -> Result<&str, String> {
+    /// the characters of a utf8 constant as they are seen by java
+    fn constant_utf16(&self, index: u16) -> Result<Vec<u16>, String> {
         match *self.constant(index)? {
-            ConstantInfo::Utf8(ref s) => Ok(&s.utf8_string),
+            ConstantInfo::Utf8(ref s) => decode_modified_utf8(&s.bytes),
             _ => Err("Not a utf8 constant".to_owned()),
         }
     }
 
-    fn constant_class(&self, index: u16) -> Result<&str, String> {
+    // the utf8_string of the parser isn't used, because it doesn't know about modified UTF-8
+    fn constant_utf8(&self, index: u16) -> Result<String, String> {
+        String::from_utf16(&self.constant_utf16(index)?).map_err(|_| "Unpaired surrogate in utf8 constant".to_owned())
+    }
+
+    // strings may contain unpaired surrogates, so they are kept as utf16
+    fn constant_string(&self, index: u16) -> Result<Vec<u16>, String> {
+        match *self.constant(index)? {
+            ConstantInfo::String(ref s) => self.constant_utf16(s.string_index),
+            _ => Err("Not a string constant".to_owned()),
+        }
+    }
+
+    fn constant_class(&self, index: u16) -> Result<String, String> {
         match *self.constant(index)? {
             ConstantInfo::Class(ref s) => Ok(self.constant_utf8(s.name_index)?),
             _ => Err("Not a class constant".to_owned()),
         }
     }
 
-    fn constant_name_and_type(&self, index: u16) -> Result<(&str, &str), String> {
+    fn constant_name_and_type(&self, index: u16) -> Result<(String, String), String> {
         match *self.constant(index)? {
             ConstantInfo::NameAndType(ref s) => {
                 Ok((self.constant_utf8(s.name_index)?, self.constant_utf8(s.descriptor_index)?))
@@ -104,7 +165,7 @@ impl ParsedClass for ClassFile {
         match *self.constant(index)? {
             ConstantInfo::FieldRef(ref s) => {
                 let (name, typ) = self.constant_name_and_type(s.name_and_type_index)?;
-                Ok(FieldRef::new(name, self.constant_class(s.class_index)?, typ)?)
+                Ok(FieldRef::new(&name, &self.constant_class(s.class_index)?, &typ)?)
             }
             _ => Err("Not a class constant".to_owned()),
         }
@@ -115,9 +176,9 @@ impl ParsedClass for ClassFile {
             ConstantInfo::MethodRef(ref s) => {
                 let (name, typ) = self.constant_name_and_type(s.name_and_type_index)?;
                 Ok(MethodRef {
-                    class: self.constant_class(s.class_index)?.to_owned(),
-                    name: name.to_owned(),
-                    descriptor: typ.to_owned(),
+                    class: self.constant_class(s.class_index)?,
+                    name: name,
+                    descriptor: typ,
                 })
             }
             _ => Err("Not a class constant".to_owned()),
@@ -129,12 +190,121 @@ impl ParsedClass for ClassFile {
             ConstantInfo::InterfaceMethodRef(ref s) => {
                 let (name, typ) = self.constant_name_and_type(s.name_and_type_index)?;
                 Ok(MethodRef {
-                    class: self.constant_class(s.class_index)?.to_owned(),
-                    name: name.to_owned(),
-                    descriptor: typ.to_owned(),
+                    class: self.constant_class(s.class_index)?,
+                    name: name,
+                    descriptor: typ,
                 })
             }
             _ => Err("Not a class constant".to_owned()),
         }
     }
+
+    // since version 52.0 invokestatic, invokespecial and method handles may reference interface methods
+    fn constant_method_or_interface_method_ref(&self, index: u16) -> Result<MethodRef, String> {
+        match *self.constant(index)? {
+            ConstantInfo::InterfaceMethodRef(_) => self.constant_interface_method_ref(index),
+            _ => self.constant_method_ref(index),
+        }
+    }
+
+    // see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.4.8
+    fn constant_method_handle(&self, index: u16) -> Result<MethodHandle, String> {
+        match *self.constant(index)? {
+            ConstantInfo::MethodHandle(ref s) => {
+                let reference = s.reference_index;
+                Ok(match s.reference_kind {
+                    1 => MethodHandle::GetField(self.constant_field_ref(reference)?),
+                    2 => MethodHandle::GetStatic(self.constant_field_ref(reference)?),
+                    3 => MethodHandle::PutField(self.constant_field_ref(reference)?),
+                    4 => MethodHandle::PutStatic(self.constant_field_ref(reference)?),
+                    5 => MethodHandle::InvokeVirtual(self.constant_method_ref(reference)?),
+                    6 => MethodHandle::InvokeStatic(self.constant_method_or_interface_method_ref(reference)?),
+                    7 => MethodHandle::InvokeSpecial(self.constant_method_or_interface_method_ref(reference)?),
+                    8 => MethodHandle::NewInvokeSpecial(self.constant_method_ref(reference)?),
+                    9 => MethodHandle::InvokeInterface(self.constant_interface_method_ref(reference)?),
+                    kind => return Err(format!("Invalid method handle kind {}", kind)),
+                })
+            }
+            _ => Err("Not a method handle constant".to_owned()),
+        }
+    }
+
+    fn constant_method_type(&self, index: u16) -> Result<String, String> {
+        match *self.constant(index)? {
+            ConstantInfo::MethodType(ref s) => self.constant_utf8(s.descriptor_index),
+            _ => Err("Not a method type constant".to_owned()),
+        }
+    }
+
+    // see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.4.10
+    fn constant_invoke_dynamic(&self, index: u16) -> Result<InvokeDynamicRef, String> {
+        match *self.constant(index)? {
+            ConstantInfo::InvokeDynamic(ref s) => {
+                let (name, typ) = self.constant_name_and_type(s.name_and_type_index)?;
+                Ok(InvokeDynamicRef {
+                    bootstrap_method: s.bootstrap_method_attr_index,
+                    name: name,
+                    descriptor: typ,
+                })
+            }
+            _ => Err("Not an invokedynamic constant".to_owned()),
+        }
+    }
+}
+
+/// decodes the modified UTF-8 of class files into the UTF-16 used by java
+/// characters outside of the BMP are already stored as surrogate pairs and null is stored in two bytes
+/// see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#7963
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<Vec<u16>, String> {
+    let mut chars = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    // the mask checks for 10xxxxxx
+    let continuation = |index: usize| -> Result<u16, String> {
+        match bytes.get(index) {
+            Some(b) if b & 0xC0 == 0x80 => Ok((b & 0x3F) as u16),
+            _ => Err(format!("Invalid modified UTF-8 at byte {}", index)),
+        }
+    };
+    while index < bytes.len() {
+        let a = bytes[index] as u16;
+        if a != 0 && a & 0x80 == 0 {
+            chars.push(a);
+            index += 1;
+        } else if a & 0xE0 == 0xC0 {
+            chars.push((a & 0x1F) << 6 | continuation(index + 1)?);
+            index += 2;
+        } else if a & 0xF0 == 0xE0 {
+            chars.push((a & 0x0F) << 12 | continuation(index + 1)? << 6 | continuation(index + 2)?);
+            index += 3;
+        } else {
+            return Err(format!("Invalid modified UTF-8 at byte {}", index));
+        }
+    }
+    Ok(chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modified_utf8() {
+        assert_eq!(decode_modified_utf8(b"Hallo!").unwrap(),
+                   "Hallo!".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(decode_modified_utf8("äöü€".as_bytes()).unwrap(),
+                   "äöü€".encode_utf16().collect::<Vec<_>>());
+        // embedded null
+        assert_eq!(decode_modified_utf8(&[0x61, 0xC0, 0x80, 0x62]).unwrap(), vec![0x61, 0, 0x62]);
+        // U+1F600 as surrogate pair
+        assert_eq!(decode_modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).unwrap(),
+                   vec![0xD83D, 0xDE00]);
+        // unpaired surrogate
+        assert_eq!(decode_modified_utf8(&[0xED, 0xA0, 0x80]).unwrap(), vec![0xD800]);
+
+        assert!(decode_modified_utf8(&[0]).is_err());
+        assert!(decode_modified_utf8(&[0xC0]).is_err());
+        assert!(decode_modified_utf8(&[0xE0, 0x80, 0x61]).is_err());
+        // standard UTF-8 for U+1F600 is not allowed
+        assert!(decode_modified_utf8(&[0xF0, 0x9F, 0x98, 0x80]).is_err());
+    }
 }
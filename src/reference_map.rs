@@ -0,0 +1,404 @@
+use instruction::{Instruction, CodeAddress, Type};
+use instruction::Instruction::*;
+use descriptor::MethodDescriptor;
+use class::Method;
+use std::iter;
+
+/// what the garbage collector knows about the value in a local variable or on the operand stack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+    // an int, float, half of a long or double or a return address
+    Value,
+    Reference,
+    // the slot holds a reference on some paths to the instruction and a value on others,
+    // so it must be checked at runtime
+    Conflict,
+    // the value can't be used anymore, e.g. an uninitialized local variable
+    Unused,
+}
+
+/// the types of the local variables and of the operand stack before an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameState {
+    locals: Vec<Slot>,
+    stack: Vec<Slot>,
+}
+
+/// the frame states of all reachable instructions of a method, which are inferred from the bytecode
+/// in the same way as the type inference of the verifier
+/// see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#9801
+#[derive(Debug)]
+pub struct ReferenceMap {
+    states: Vec<Option<FrameState>>,
+}
+
+impl Slot {
+    /// a conflict between a reference and another slot is kept, so no reference is missed
+    /// after a subroutine returned to a different caller than the one, which called it
+    fn merge(self, other: Slot) -> Slot {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Slot::Value, Slot::Unused) | (Slot::Unused, Slot::Value) => Slot::Unused,
+            _ => Slot::Conflict,
+        }
+    }
+
+    fn for_type(typ: Type) -> Slot { if typ == Type::Reference { Slot::Reference } else { Slot::Value } }
+}
+
+impl FrameState {
+    pub fn locals(&self) -> &Vec<Slot> { &self.locals }
+    pub fn stack(&self) -> &Vec<Slot> { &self.stack }
+
+    /// returns true, if other changed this state
+    fn merge(&mut self, other: &FrameState) -> Result<bool, String> {
+        if self.stack.len() != other.stack.len() {
+            return Err(format!("Stack heights {} and {} differ", self.stack.len(), other.stack.len()));
+        }
+        let mut changed = false;
+        let others = other.locals.iter().chain(&other.stack);
+        for (slot, other) in self.locals.iter_mut().chain(self.stack.iter_mut()).zip(others) {
+            let merged = slot.merge(*other);
+            changed |= merged != *slot;
+            *slot = merged;
+        }
+        Ok(changed)
+    }
+
+    fn push(&mut self, slot: Slot) { self.stack.push(slot); }
+
+    fn push_type(&mut self, typ: Type) {
+        for _ in 0..typ.word_size() {
+            self.push(Slot::for_type(typ));
+        }
+    }
+
+    fn push_values(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push(Slot::Value);
+        }
+    }
+
+    fn pop(&mut self) -> Result<Slot, String> { self.stack.pop().ok_or("Stack underflow".to_owned()) }
+
+    fn pop_words(&mut self, count: usize) -> Result<Vec<Slot>, String> {
+        if count > self.stack.len() {
+            return Err("Stack underflow".to_owned());
+        }
+        let len = self.stack.len() - count;
+        Ok(self.stack.split_off(len))
+    }
+
+    fn store(&mut self, index: usize, slot: Slot) -> Result<(), String> {
+        match self.locals.get_mut(index) {
+            Some(local) => *local = slot,
+            None => return Err(format!("Invalid local variable {}", index)),
+        }
+        Ok(())
+    }
+
+    fn invoke(&mut self, descriptor: &str, has_object: bool) -> Result<(), String> {
+        let descriptor = MethodDescriptor::parse(descriptor).ok_or(format!("Invalid descriptor {}", descriptor))?;
+        self.pop_words(descriptor.words_for_params() + has_object as usize)?;
+        if let Some(ret) = descriptor.ret_type() {
+            self.push_type(ret.simple_typ());
+        }
+        Ok(())
+    }
+
+    /// executes the instruction on the types
+    fn execute(&mut self, instruction: &Instruction) -> Result<(), String> {
+        match *instruction {
+            ALOAD(typ) => {
+                self.pop_words(2)?;
+                self.push_type(typ);
+            }
+            ASTORE(typ) => {
+                self.pop_words(2 + typ.word_size())?;
+            }
+            LOAD(typ, _) => self.push_type(typ),
+            // astore may also store a return address
+            STORE(typ, index) => {
+                let slots = self.pop_words(typ.word_size())?;
+                for (offset, slot) in slots.into_iter().enumerate() {
+                    self.store(index as usize + offset, slot)?;
+                }
+            }
+            ARRAYLENGTH | INSTANCEOF(..) => {
+                self.pop()?;
+                self.push(Slot::Value);
+            }
+            ATHROW | MONITORENTER | MONITOREXIT | POP | IF(..) | IFNULL(..) | TABLESWITCH(..) | LOOKUPSWITCH(..) => {
+                self.pop()?;
+            }
+            CHECKCAST(..) | GOTO(..) | RET(..) | RETURN(..) | NOP => {}
+            ANEWARRAY(..) | NEWARRAY(..) => {
+                self.pop()?;
+                self.push(Slot::Reference);
+            }
+            MULTIANEWARRAY(_, count) => {
+                self.pop_words(count as usize)?;
+                self.push(Slot::Reference);
+            }
+            NEW(..) | ACONST_NULL | LDC_STRING(..) | LDC_CLASS(..) | LDC_METHOD_TYPE(..) | LDC_METHOD_HANDLE(..) => {
+                self.push(Slot::Reference)
+            }
+            CONVERT(from, to) => {
+                self.pop_words(from.word_size())?;
+                self.push_type(to);
+            }
+            ADD(typ) | DIV(typ) | MUL(typ) | REM(typ) | SUB(typ) | AND(typ) | OR(typ) | XOR(typ) => {
+                self.pop_words(2 * typ.word_size())?;
+                self.push_type(typ);
+            }
+            NEG(typ) => {
+                self.pop_words(typ.word_size())?;
+                self.push_type(typ);
+            }
+            // the shift distance is always an int
+            SHL(typ) | SHR(typ) | USHR(typ) => {
+                self.pop_words(1 + typ.word_size())?;
+                self.push_type(typ);
+            }
+            IINC(index, _) => self.store(index as usize, Slot::Value)?,
+            FCONST_0 | FCONST_1 | FCONST_2 | BIPUSH(..) | SIPUSH(..) | LDC_INT(..) | LDC_FLOAT(..) => {
+                self.push(Slot::Value)
+            }
+            DCONST_0 | DCONST_1 | LCONST_0 | LCONST_1 | LDC_DOUBLE(..) | LDC_LONG(..) => self.push_values(2),
+            // the stack instructions work on words like in the vm
+            DUP => {
+                let v1 = self.pop()?;
+                self.stack.extend_from_slice(&[v1, v1]);
+            }
+            DUP_X1 => {
+                let v = self.pop_words(2)?;
+                self.stack.extend_from_slice(&[v[1], v[0], v[1]]);
+            }
+            DUP_X2 => {
+                let v = self.pop_words(3)?;
+                self.stack.extend_from_slice(&[v[2], v[0], v[1], v[2]]);
+            }
+            DUP2 => {
+                let v = self.pop_words(2)?;
+                self.stack.extend_from_slice(&[v[0], v[1], v[0], v[1]]);
+            }
+            DUP2_X1 => {
+                let v = self.pop_words(3)?;
+                self.stack.extend_from_slice(&[v[1], v[2], v[0], v[1], v[2]]);
+            }
+            DUP2_X2 => {
+                let v = self.pop_words(4)?;
+                self.stack.extend_from_slice(&[v[2], v[3], v[0], v[1], v[2], v[3]]);
+            }
+            POP2 => {
+                self.pop_words(2)?;
+            }
+            SWAP => {
+                let v = self.pop_words(2)?;
+                self.stack.extend_from_slice(&[v[1], v[0]]);
+            }
+            GETFIELD(ref field) => {
+                self.pop()?;
+                self.push_type(*field.typ());
+            }
+            GETSTATIC(ref field) => self.push_type(*field.typ()),
+            PUTFIELD(ref field) => {
+                self.pop_words(1 + field.typ().word_size())?;
+            }
+            PUTSTATIC(ref field) => {
+                self.pop_words(field.typ().word_size())?;
+            }
+            DCMPG | DCMPL | LCMP => {
+                self.pop_words(4)?;
+                self.push(Slot::Value);
+            }
+            FCMPG | FCMPL => {
+                self.pop_words(2)?;
+                self.push(Slot::Value);
+            }
+            // the return address
+            JSR(..) => self.push(Slot::Value),
+            IF_ACMP(..) | IF_ICMP(..) => {
+                self.pop_words(2)?;
+            }
+            INVOKEDYNAMIC(ref call_site) => self.invoke(call_site.descriptor(), false)?,
+            INVOKESTATIC(ref method) => self.invoke(method.descriptor(), false)?,
+            INVOKEINTERFACE(ref method, _) |
+            INVOKESPECIAL(ref method) |
+            INVOKEVIRTUAL(ref method) => self.invoke(method.descriptor(), true)?,
+            // the code of the class file is analyzed, which is never quickened
+            GETFIELD_QUICK(..) | PUTFIELD_QUICK(..) | GETSTATIC_QUICK(..) | PUTSTATIC_QUICK(..) |
+            INVOKESPECIAL_QUICK(..) | INVOKESTATIC_QUICK(..) | INVOKEVIRTUAL_QUICK(..) | INVOKEINTERFACE_QUICK(..) => {
+                return Err(format!("Unexpected quickened instruction {:?}", instruction))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReferenceMap {
+    pub fn new(method: &Method) -> Result<ReferenceMap, String> {
+        let code = method.code().ok_or(format!("Method {} has no code", method.name()))?;
+        let instructions = code.code();
+
+        let mut locals = Vec::with_capacity(code.max_locals());
+        if !method.is_static() {
+            locals.push(Slot::Reference);
+        }
+        let descriptor = MethodDescriptor::parse(method.descriptor()).expect("Methods have valid descriptors");
+        for param in descriptor.params() {
+            for _ in 0..param.word_size() {
+                locals.push(Slot::for_type(param.simple_typ()));
+            }
+        }
+        if locals.len() > code.max_locals() {
+            return Err(format!("The parameters of {} don't fit into the local variables", method.name()));
+        }
+        let unused = code.max_locals() - locals.len();
+        locals.extend(iter::repeat(Slot::Unused).take(unused));
+
+        // a ret may return behind any jsr of the method
+        let returns: Vec<usize> = instructions.iter()
+            .enumerate()
+            .filter(|&(_, i)| match *i {
+                JSR(..) => true,
+                _ => false,
+            })
+            .map(|(ip, _)| ip + 1)
+            .collect();
+
+        let mut states = vec![None; instructions.len()];
+        let mut work = vec![0];
+        if !instructions.is_empty() {
+            states[0] = Some(FrameState {
+                locals: locals,
+                stack: Vec::new(),
+            });
+        }
+        while let Some(ip) = work.pop() {
+            let before = states[ip].clone().expect("only instructions with a state are visited");
+            let mut after = before.clone();
+            after.execute(&instructions[ip])?;
+
+            let mut successors: Vec<(usize, FrameState)> = Vec::new();
+            for address in ReferenceMap::successors(ip, &instructions[ip], &returns) {
+                successors.push((address, after.clone()));
+            }
+            for handler in code.exception_table().iter().filter(|h| h.covers(ip as CodeAddress)) {
+                let mut state = before.clone();
+                state.stack = vec![Slot::Reference];
+                successors.push((handler.handler() as usize, state));
+            }
+
+            for (address, state) in successors {
+                if address >= instructions.len() {
+                    return Err(format!("Instruction {} jumps to invalid address {}", ip, address));
+                }
+                let changed = match states[address] {
+                    Some(ref mut old) => old.merge(&state)?,
+                    None => true,
+                };
+                if states[address].is_none() {
+                    states[address] = Some(state);
+                }
+                if changed && !work.contains(&address) {
+                    work.push(address);
+                }
+            }
+        }
+        Ok(ReferenceMap { states: states })
+    }
+
+    /// the instructions, which can be executed after the instruction at ip, if it doesn't throw an exception
+    pub fn successors(ip: usize, instruction: &Instruction, returns: &[usize]) -> Vec<usize> {
+        match *instruction {
+            ATHROW | RETURN(..) => vec![],
+            GOTO(dest) | JSR(dest) => vec![dest as usize],
+            RET(..) => returns.to_vec(),
+            IF_ACMP(_, dest) | IF_ICMP(_, dest) | IF(_, dest) | IFNULL(_, dest) => vec![ip + 1, dest as usize],
+            TABLESWITCH(default, _, _, ref addresses) => {
+                iter::once(default).chain(addresses.iter().cloned()).map(|a| a as usize).collect()
+            }
+            LOOKUPSWITCH(default, _, ref pairs) => {
+                iter::once(default).chain(pairs.iter().map(|&(_, a)| a)).map(|a| a as usize).collect()
+            }
+            _ => vec![ip + 1],
+        }
+    }
+
+    /// the state before the instruction at ip, None if the instruction is unreachable
+    pub fn state(&self, ip: usize) -> Option<&FrameState> { self.states.get(ip).and_then(|s| s.as_ref()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Slot::*;
+    use class::Code;
+    use class_loader::ClassLoader;
+    use classfile_parser::method_info::{STATIC, PUBLIC};
+    use instruction::Comparison;
+
+    fn reference_map(descriptor: &str, max_locals: usize, code: Vec<Instruction>) -> ReferenceMap {
+        let method = Method::new(PUBLIC | STATIC, "test", descriptor, Some(Code::new(10, max_locals, code))).unwrap();
+        ReferenceMap::new(&method).unwrap()
+    }
+
+    #[test]
+    fn locals_and_stack() {
+        let map = reference_map("(IJLjava/lang/Object;)V",
+                                5,
+                                vec![ACONST_NULL, STORE(Type::Reference, 4), LOAD(Type::Long, 1), LCONST_0, POP2,
+                                     LDC_STRING(vec![]), RETURN(None)]);
+        assert_eq!(map.state(0).unwrap().locals(), &vec![Value, Value, Value, Reference, Unused]);
+        assert_eq!(map.state(2).unwrap().locals(), &vec![Value, Value, Value, Reference, Reference]);
+        assert_eq!(map.state(5).unwrap().stack(), &vec![Value, Value]);
+        assert_eq!(map.state(6).unwrap().stack(), &vec![Value, Value, Reference]);
+    }
+
+    #[test]
+    fn branches() {
+        // if (i == 0) { o = null; } else { o = 1; } return;
+        let map = reference_map("(I)V",
+                                2,
+                                vec![LOAD(Type::Int, 0), IF(Comparison::NE, 5), ACONST_NULL, STORE(Type::Reference, 1),
+                                     GOTO(7), BIPUSH(1), STORE(Type::Int, 1), RETURN(None), NOP]);
+        assert_eq!(map.state(7).unwrap().locals(), &vec![Value, Conflict]);
+        // unreachable code has no state
+        assert_eq!(map.state(8), None);
+    }
+
+    #[test]
+    fn class_methods() {
+        // all methods generated by javac can be analyzed
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let class = classloader.load_class("com/mackie/rustyjvm/TestVM").unwrap();
+        for method in class.methods().iter().filter(|m| m.code().is_some()) {
+            let map = ReferenceMap::new(method).unwrap();
+            // the stack only contains the exception, when it is caught
+            for handler in method.code().unwrap().exception_table().iter() {
+                assert_eq!(map.state(handler.handler() as usize).unwrap().stack(), &vec![Reference]);
+            }
+        }
+    }
+
+    #[test]
+    fn subroutines() {
+        // the return address is not a reference and the local variable 1 holds a reference
+        // for the first and an int for the second caller of the subroutine
+        let map = reference_map("()V",
+                                3,
+                                vec![ACONST_NULL, STORE(Type::Reference, 1), JSR(7), BIPUSH(1), STORE(Type::Int, 1),
+                                     JSR(7), RETURN(None), STORE(Type::Reference, 0), RET(0)]);
+        assert_eq!(map.state(8).unwrap().locals(), &vec![Value, Conflict, Unused]);
+        assert_eq!(map.state(3).unwrap().locals(), &vec![Value, Conflict, Unused]);
+        assert_eq!(map.state(6).unwrap().locals(), &vec![Value, Conflict, Unused]);
+    }
+
+    #[test]
+    fn invalid_code() {
+        let method = Method::new(PUBLIC | STATIC, "test", "()V", Some(Code::new(1, 0, vec![POP, RETURN(None)])))
+            .unwrap();
+        assert!(ReferenceMap::new(&method).is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set by the signal handler, the vm prints the thread dump at the next thread switch
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request_dump(_: ::libc::c_int) { DUMP_REQUESTED.store(true, Ordering::SeqCst); }
+
+/// installs a handler for SIGQUIT (kill -3 or ctrl-\), which requests a thread dump instead of quitting
+#[cfg(unix)]
+pub fn handle_sigquit() {
+    let handler = request_dump as extern "C" fn(::libc::c_int);
+    unsafe {
+        ::libc::signal(::libc::SIGQUIT, handler as ::libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn handle_sigquit() {}
+
+/// returns true once after each signal
+pub fn dump_requested() -> bool { DUMP_REQUESTED.swap(false, Ordering::SeqCst) }
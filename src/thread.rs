@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// the state of a java thread, which is scheduled by the vm
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadState {
+    Runnable,
+    // waits to enter the monitor of the object, count is the number of entries, when it gets the monitor
+    Blocked { object: i32, count: u32 },
+    // waits in Object.wait until it is notified or the deadline passes
+    // afterwards it enters the monitor again with count entries
+    Waiting { object: i32, count: u32, deadline: Option<Instant> },
+    Sleeping(Instant),
+    // waits until another thread finished the initialization of the class with the id
+    WaitingForClass(usize),
+    Terminated,
+}
+
+/// chooses the next thread and the number of instructions it executes
+/// without a seed the threads are scheduled round robin, with a seed the order and the quantums are random.
+/// The same seed gives the same schedule, unless threads sleep or wait with a timeout.
+pub struct Scheduler {
+    quantum: usize,
+    // the state of the xorshift generator
+    random: Option<u64>,
+}
+
+/// the owner and the number of entries of a locked monitor
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Monitor {
+    owner: usize,
+    count: u32,
+}
+
+/// the locked monitors of the objects, unlocked monitors aren't stored
+pub struct Monitors {
+    monitors: HashMap<i32, Monitor>,
+}
+
+impl Scheduler {
+    /// each thread executes up to quantum instructions, before the next thread is scheduled
+    pub fn new(quantum: usize, seed: Option<u64>) -> Scheduler {
+        assert!(quantum > 0);
+        Scheduler {
+            quantum: quantum,
+            // the state of xorshift must not be 0
+            random: seed.map(|s| s ^ 0x9e37_79b9_7f4a_7c15).map(|s| if s == 0 { 1 } else { s }),
+        }
+    }
+
+    /// chooses one of the runnable threads, which must not be empty, after the current thread
+    /// returns the thread and the number of instructions it may execute
+    pub fn next(&mut self, current: usize, runnable: &[usize]) -> (usize, usize) {
+        assert!(!runnable.is_empty());
+        match self.random {
+            None => {
+                let next = runnable.iter().find(|&&t| t > current).unwrap_or(&runnable[0]);
+                (*next, self.quantum)
+            }
+            Some(..) => {
+                let thread = runnable[self.next_random() as usize % runnable.len()];
+                let quantum = 1 + self.next_random() as usize % self.quantum;
+                (thread, quantum)
+            }
+        }
+    }
+
+    /// see https://en.wikipedia.org/wiki/Xorshift
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.random.expect("the scheduler has a seed");
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.random = Some(x);
+        x
+    }
+}
+
+impl Monitors {
+    pub fn new() -> Monitors { Monitors { monitors: HashMap::new() } }
+
+    /// enters the monitor count times, returns false, if another thread owns it
+    pub fn enter(&mut self, object: i32, thread: usize, count: u32) -> bool {
+        let monitor = self.monitors.entry(object).or_insert(Monitor { owner: thread, count: 0 });
+        if monitor.owner != thread {
+            return false;
+        }
+        monitor.count += count;
+        true
+    }
+
+    /// returns false, if the thread doesn't own the monitor
+    pub fn exit(&mut self, object: i32, thread: usize) -> bool {
+        let count = match self.monitors.get_mut(&object) {
+            Some(monitor) if monitor.owner == thread => {
+                monitor.count -= 1;
+                monitor.count
+            }
+            _ => return false,
+        };
+        if count == 0 {
+            self.monitors.remove(&object);
+        }
+        true
+    }
+
+    /// unlocks the monitor completely and returns the number of entries
+    /// None, if the thread doesn't own the monitor
+    pub fn release(&mut self, object: i32, thread: usize) -> Option<u32> {
+        match self.monitors.get(&object) {
+            Some(monitor) if monitor.owner == thread => {}
+            _ => return None,
+        }
+        self.monitors.remove(&object).map(|m| m.count)
+    }
+
+    pub fn owns(&self, object: i32, thread: usize) -> bool {
+        self.monitors.get(&object).map_or(false, |m| m.owner == thread)
+    }
+
+    /// the thread, which owns the monitor of the object, None if it is unlocked
+    pub fn owner(&self, object: i32) -> Option<usize> { self.monitors.get(&object).map(|m| m.owner) }
+
+    /// the objects with locked monitors
+    pub fn objects<'a>(&'a self) -> Box<Iterator<Item = i32> + 'a> { Box::new(self.monitors.keys().cloned()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(scheduler: &mut Scheduler) -> Vec<(usize, usize)> {
+        let mut current = 0;
+        let mut schedule = Vec::new();
+        for _ in 0..20 {
+            let next = scheduler.next(current, &[0, 2, 3]);
+            current = next.0;
+            schedule.push(next);
+        }
+        schedule
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut scheduler = Scheduler::new(100, None);
+        assert_eq!(&schedule(&mut scheduler)[..4], &[(2, 100), (3, 100), (0, 100), (2, 100)]);
+        assert_eq!(scheduler.next(1, &[1]), (1, 100));
+    }
+
+    #[test]
+    fn seeds() {
+        let first = schedule(&mut Scheduler::new(10, Some(42)));
+        assert_eq!(first, schedule(&mut Scheduler::new(10, Some(42))));
+        assert!(first != schedule(&mut Scheduler::new(10, Some(43))));
+        assert!(first.iter().all(|&(thread, quantum)| thread != 1 && quantum >= 1 && quantum <= 10));
+        // all threads get scheduled
+        for thread in &[0, 2, 3] {
+            assert!(first.iter().any(|&(t, _)| t == *thread));
+        }
+        schedule(&mut Scheduler::new(1, Some(0)));
+    }
+
+    #[test]
+    fn monitors() {
+        let mut monitors = Monitors::new();
+        assert!(monitors.enter(5, 0, 1));
+        assert!(monitors.enter(5, 0, 1));
+        assert!(!monitors.enter(5, 1, 1));
+        assert!(monitors.owns(5, 0));
+        assert_eq!(monitors.owner(5), Some(0));
+        assert!(!monitors.exit(5, 1));
+        assert!(monitors.exit(5, 0));
+        assert!(monitors.owns(5, 0));
+        assert!(monitors.exit(5, 0));
+        assert!(!monitors.owns(5, 0));
+        assert_eq!(monitors.owner(5), None);
+        assert!(!monitors.exit(5, 0));
+
+        assert!(monitors.enter(6, 1, 3));
+        assert_eq!(monitors.objects().collect::<Vec<_>>(), vec![6]);
+        assert_eq!(monitors.release(6, 0), None);
+        assert_eq!(monitors.release(6, 1), Some(3));
+        assert!(monitors.enter(6, 0, 3));
+    }
+}
@@ -0,0 +1,1339 @@
+use instruction::{Instruction, CodeAddress, Type};
+use instruction::Instruction::*;
+use descriptor::{FieldDescriptor, MethodDescriptor};
+use class::{Class, Code, Method, StackMapFrame, OBJECT_NAME, CLONEABLE_NAME, SERIALIZABLE_NAME};
+use classfile_parser::method_info;
+use class_loader::ClassLoader;
+use errors::ClassLoadingError;
+use reference_map::ReferenceMap;
+use self::VerificationType::*;
+use std::cell::RefCell;
+use std::iter;
+
+const STRING_NAME: &'static str = "java/lang/String";
+const CLASS_NAME: &'static str = "java/lang/Class";
+const THROWABLE_NAME: &'static str = "java/lang/Throwable";
+
+/// the type of a local variable or of a word on the operand stack, as inferred by the verifier
+/// see https://docs.oracle.com/javase/specs/jvms/se6/html/ClassFile.doc.html#9801
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    // an unusable word, e.g. an uninitialized local variable or the second word of a long or double
+    Top,
+    // also booleans, bytes, chars and shorts
+    Int,
+    Float,
+    // longs and doubles are followed by a Top
+    Long,
+    Double,
+    Null,
+    Reference(FieldDescriptor),
+    // the object created by the new instruction at the index, whose constructor wasn't called yet
+    Uninitialized(usize),
+    // this in a constructor before the constructor of the superclass was called
+    UninitializedThis,
+    // the return address of a jsr to the subroutine at the index
+    ReturnAddress(usize),
+}
+
+/// the types of the local variables and of the operand stack before an instruction
+#[derive(Debug, Clone, PartialEq)]
+struct FrameState {
+    locals: Vec<VerificationType>,
+    stack: Vec<VerificationType>,
+    // the constructor of the superclass isn't called on some path to the instruction
+    uninitialized_this: bool,
+    // the local variables, which were stored since the start of the current subroutine
+    changed: Vec<bool>,
+}
+
+/// what the instructions of a method are checked against
+struct Context<'a> {
+    class: &'a Class,
+    descriptor: MethodDescriptor,
+    code: &'a [Instruction],
+    // looks up the superclasses of the referenced classes
+    loader: RefCell<&'a mut ClassLoader>,
+}
+
+impl<'a> Context<'a> {
+    /// the superclass of the class and whether it is an interface, None if the class can't be loaded
+    fn super_class(&self, class: &str) -> Option<(Option<String>, bool)> {
+        if class == self.class.name() {
+            return Some((self.class.super_class().cloned(), self.class.is_interface()));
+        }
+        self.loader.borrow_mut().super_class_of(class).ok()
+    }
+
+    /// the class followed by its superclasses up to Object, empty if one of them can't be loaded
+    fn super_classes(&self, class: &str) -> Vec<String> {
+        let mut classes = vec![class.to_owned()];
+        loop {
+            match self.super_class(&classes[classes.len() - 1]) {
+                // a circular hierarchy is rejected, when the class is linked
+                Some((Some(ref super_class), _)) if classes.contains(super_class) => return classes,
+                Some((Some(super_class), _)) => classes.push(super_class),
+                Some((None, _)) => return classes,
+                None => return Vec::new(),
+            }
+        }
+    }
+}
+
+impl VerificationType {
+    /// the type of a value of the field descriptor
+    fn from_descriptor(descriptor: &FieldDescriptor) -> VerificationType {
+        match descriptor.simple_typ() {
+            Type::Reference => Reference(descriptor.clone()),
+            typ => VerificationType::from_type(typ),
+        }
+    }
+
+    /// the type of a primitive value
+    fn from_type(typ: Type) -> VerificationType {
+        match typ {
+            Type::Long => Long,
+            Type::Float => Float,
+            Type::Double => Double,
+            Type::Reference => panic!("references have no primitive verification type"),
+            _ => Int,
+        }
+    }
+
+    /// the type of a class name of the constant pool, which may also name an array class
+    pub fn from_class(class: &str) -> Result<VerificationType, String> {
+        if class.starts_with('[') {
+            FieldDescriptor::parse(class).map(Reference).ok_or(format!("Invalid array class {}", class))
+        } else {
+            Ok(Reference(FieldDescriptor::from_class(class)))
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        match *self {
+            Null | Reference(..) => true,
+            _ => false,
+        }
+    }
+
+    fn is_assignable_to(&self, typ: Type) -> bool {
+        match typ {
+            Type::Reference => self.is_reference(),
+            Type::Long | Type::Float | Type::Double => *self == VerificationType::from_type(typ),
+            _ => *self == Int,
+        }
+    }
+
+    /// whether a value of this type can be used, where the other type is expected
+    fn is_subtype_of(&self, other: &VerificationType, context: &Context) -> bool {
+        match (self, other) {
+            (a, b) if a == b => true,
+            (_, &Top) | (&Null, &Reference(..)) => true,
+            (&Reference(ref a), &Reference(ref b)) => is_assignable_reference(a, b, context),
+            _ => false,
+        }
+    }
+
+    /// None, if there is no type both can be used as
+    fn merge(&self, other: &VerificationType, context: &Context) -> Option<VerificationType> {
+        match (self, other) {
+            (a, b) if a == b => Some(a.clone()),
+            (&Null, &Reference(ref r)) | (&Reference(ref r), &Null) => Some(Reference(r.clone())),
+            (&Reference(ref a), &Reference(ref b)) => Some(Reference(merge_references(a, b, context))),
+            _ => None,
+        }
+    }
+}
+
+/// the nearest common superclass of classes, interfaces are merged to Object
+fn merge_references(a: &FieldDescriptor, b: &FieldDescriptor, context: &Context) -> FieldDescriptor {
+    if a == b {
+        return a.clone();
+    }
+    if a.is_array() && b.is_array() && a.as_type_without_arrays(1) == Type::Reference &&
+       b.as_type_without_arrays(1) == Type::Reference {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        a.remove_array();
+        b.remove_array();
+        let mut merged = merge_references(&a, &b, context);
+        merged.add_array();
+        return merged;
+    }
+    if let (Some(a), Some(b)) = (a.get_class(), b.get_class()) {
+        let super_classes = context.super_classes(a);
+        if let Some(common) = context.super_classes(b).iter().find(|c| super_classes.contains(c)) {
+            return FieldDescriptor::from_class(common);
+        }
+    }
+    FieldDescriptor::from_class(OBJECT_NAME)
+}
+
+/// arrays are only assignable to arrays with assignable components, Object, Cloneable and Serializable
+/// classes are assignable to their superclasses. Like Object, interfaces accept all references,
+/// invokeinterface checks the class of the object at runtime
+fn is_assignable_reference(from: &FieldDescriptor, to: &FieldDescriptor, context: &Context) -> bool {
+    if from == to || to.get_class() == Some(OBJECT_NAME) {
+        return true;
+    }
+    match (from.is_array(), to.is_array()) {
+        (true, true) => {
+            if from.as_type_without_arrays(1) != Type::Reference || to.as_type_without_arrays(1) != Type::Reference {
+                return false;
+            }
+            let (mut from, mut to) = (from.clone(), to.clone());
+            from.remove_array();
+            to.remove_array();
+            is_assignable_reference(&from, &to, context)
+        }
+        (true, false) => to.get_class() == Some(CLONEABLE_NAME) || to.get_class() == Some(SERIALIZABLE_NAME),
+        (false, true) => false,
+        (false, false) => {
+            match (from.get_class(), to.get_class()) {
+                (Some(from), Some(to)) => {
+                    match context.super_class(to) {
+                        Some((_, true)) => true,
+                        Some((_, false)) => context.super_classes(from).iter().any(|c| c == to),
+                        None => false,
+                    }
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// bytes, chars, shorts and booleans are returned by ireturn
+fn return_category(typ: Type) -> Type {
+    match typ {
+        Type::Boolean | Type::Byte | Type::Char | Type::Short => Type::Int,
+        typ => typ,
+    }
+}
+
+impl FrameState {
+    /// returns true, if other changed this state
+    fn merge(&mut self, other: &FrameState, context: &Context) -> Result<bool, String> {
+        if self.stack.len() != other.stack.len() {
+            return Err(format!("Stack heights {} and {} differ", self.stack.len(), other.stack.len()));
+        }
+        let mut changed = false;
+        for (typ, other) in self.stack.iter_mut().zip(&other.stack) {
+            let merged = typ.merge(other, context)
+                .ok_or(format!("Incompatible types {:?} and {:?} on the stack", typ, other))?;
+            changed |= merged != *typ;
+            *typ = merged;
+        }
+        for (typ, other) in self.locals.iter_mut().zip(&other.locals) {
+            let merged = typ.merge(other, context).unwrap_or(Top);
+            changed |= merged != *typ;
+            *typ = merged;
+        }
+        for (changed_local, &other) in self.changed.iter_mut().zip(&other.changed) {
+            changed |= other && !*changed_local;
+            *changed_local |= other;
+        }
+        changed |= other.uninitialized_this && !self.uninitialized_this;
+        self.uninitialized_this |= other.uninitialized_this;
+        Ok(changed)
+    }
+
+    /// checks, whether the stack map frame can be used for this state
+    fn is_assignable_to_frame(&self, frame: &FrameState, context: &Context) -> Result<(), String> {
+        if self.stack.len() != frame.stack.len() {
+            return Err(format!("Stack height {} differs from {} of the stack map frame",
+                               self.stack.len(),
+                               frame.stack.len()));
+        }
+        for (index, (typ, expected)) in self.locals.iter().zip(&frame.locals).enumerate() {
+            if !typ.is_subtype_of(expected, context) {
+                return Err(format!("{:?} in local variable {} doesn't match {:?} of the stack map frame",
+                                   typ,
+                                   index,
+                                   expected));
+            }
+        }
+        for (typ, expected) in self.stack.iter().zip(&frame.stack) {
+            if !typ.is_subtype_of(expected, context) {
+                return Err(format!("{:?} on the stack doesn't match {:?} of the stack map frame", typ, expected));
+            }
+        }
+        if self.uninitialized_this && !frame.uninitialized_this {
+            return Err("The stack map frame expects an initialized this".to_owned());
+        }
+        Ok(())
+    }
+
+    /// the state behind the jsr of the caller, after the subroutine returned with this state
+    fn return_to(&self, caller: &FrameState) -> FrameState {
+        let locals = self.locals
+            .iter()
+            .zip(&caller.locals)
+            .zip(&self.changed)
+            .map(|((own, callers), &changed)| if changed { own.clone() } else { callers.clone() })
+            .collect();
+        FrameState {
+            locals: locals,
+            stack: self.stack.clone(),
+            uninitialized_this: self.uninitialized_this,
+            changed: self.changed.iter().zip(&caller.changed).map(|(&a, &b)| a || b).collect(),
+        }
+    }
+
+    fn push(&mut self, typ: VerificationType) {
+        let double_sized = typ == Long || typ == Double;
+        self.stack.push(typ);
+        if double_sized {
+            self.stack.push(Top);
+        }
+    }
+
+    /// pops a whole value, i.e. both words of a long or double
+    fn pop(&mut self) -> Result<VerificationType, String> {
+        let typ = self.stack.pop().ok_or("Stack underflow".to_owned())?;
+        if typ != Top {
+            return Ok(typ);
+        }
+        match self.stack.pop() {
+            Some(typ @ Long) | Some(typ @ Double) => Ok(typ),
+            Some(typ) => Err(format!("Expected a long or double below Top, but found {:?}", typ)),
+            None => Err("Stack underflow".to_owned()),
+        }
+    }
+
+    fn pop_type(&mut self, typ: Type) -> Result<VerificationType, String> {
+        let value = self.pop()?;
+        if !value.is_assignable_to(typ) {
+            return Err(format!("Expected {:?} on the stack, but found {:?}", typ, value));
+        }
+        Ok(value)
+    }
+
+    /// pops a value, which can be assigned to a variable of the type
+    fn pop_value(&mut self, typ: &FieldDescriptor, context: &Context) -> Result<VerificationType, String> {
+        if typ.simple_typ() != Type::Reference {
+            return self.pop_type(typ.simple_typ());
+        }
+        let value = self.pop_type(Type::Reference)?;
+        if let Reference(ref found) = value {
+            if !is_assignable_reference(found, typ, context) {
+                return Err(format!("Expected {} on the stack, but found {}",
+                                   typ.to_symbolic_reference(),
+                                   found.to_symbolic_reference()));
+            }
+        }
+        Ok(value)
+    }
+
+    /// returns the type of the array, None for null
+    /// the component type is only checked, if it is given
+    fn pop_array(&mut self, component: Option<Type>) -> Result<Option<FieldDescriptor>, String> {
+        match self.pop()? {
+            Null => Ok(None),
+            Reference(ref array) if array.is_array() => {
+                let matches = match (component, array.as_type_without_arrays(1)) {
+                    (None, _) => true,
+                    // baload and bastore also work on boolean arrays
+                    (Some(Type::Byte), Type::Boolean) => true,
+                    (Some(expected), actual) => expected == actual,
+                };
+                if !matches {
+                    return Err(format!("Expected an array of {:?}, but found {:?}", component.unwrap(), array));
+                }
+                Ok(Some(array.clone()))
+            }
+            other => Err(format!("Expected an array on the stack, but found {:?}", other)),
+        }
+    }
+
+    /// pops the words of the groups, the stack instructions must not split a long or double between them
+    /// the groups are given from the bottom to the top of the stack
+    fn pop_groups(&mut self, groups: &[usize]) -> Result<Vec<VerificationType>, String> {
+        let count: usize = groups.iter().sum();
+        if count > self.stack.len() {
+            return Err("Stack underflow".to_owned());
+        }
+        let len = self.stack.len() - count;
+        let words = self.stack.split_off(len);
+        let mut start = 0;
+        for &size in groups {
+            if words[start] == Top {
+                return Err("Stack instruction splits a long or double".to_owned());
+            }
+            start += size;
+        }
+        Ok(words)
+    }
+
+    fn local(&self, index: usize) -> Result<&VerificationType, String> {
+        self.locals.get(index).ok_or(format!("Invalid local variable {}", index))
+    }
+
+    fn set_local(&mut self, index: usize, typ: VerificationType) -> Result<(), String> {
+        match self.locals.get_mut(index) {
+            Some(local) => *local = typ,
+            None => return Err(format!("Invalid local variable {}", index)),
+        }
+        self.changed[index] = true;
+        Ok(())
+    }
+
+    fn store(&mut self, index: usize, typ: VerificationType) -> Result<(), String> {
+        let double_sized = typ == Long || typ == Double;
+        self.set_local(index, typ)?;
+        if double_sized {
+            self.set_local(index + 1, Top)?;
+        }
+        // the long or double, whose second word was overwritten, can't be loaded anymore
+        if index > 0 && (self.locals[index - 1] == Long || self.locals[index - 1] == Double) {
+            self.set_local(index - 1, Top)?;
+        }
+        Ok(())
+    }
+
+    /// replaces the type everywhere, e.g. after an object was initialized
+    fn replace(&mut self, from: &VerificationType, to: &VerificationType) {
+        for typ in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if typ == from {
+                *typ = to.clone();
+            }
+        }
+    }
+
+    fn pop_params(&mut self, descriptor: &MethodDescriptor, context: &Context) -> Result<(), String> {
+        for param in descriptor.params().iter().rev() {
+            self.pop_value(param, context)?;
+        }
+        Ok(())
+    }
+
+    fn push_return_type(&mut self, descriptor: &MethodDescriptor) {
+        if let Some(ret) = descriptor.ret_type() {
+            self.push(VerificationType::from_descriptor(ret));
+        }
+    }
+
+    /// the object must be an instance of the class, if the method isn't static
+    fn invoke(&mut self,
+              name: &str,
+              descriptor: &str,
+              class: Option<&str>,
+              context: &Context)
+              -> Result<(), String> {
+        if name.starts_with('<') {
+            return Err(format!("Invalid invocation of {}", name));
+        }
+        let descriptor = MethodDescriptor::parse(descriptor).ok_or(format!("Invalid descriptor {}", descriptor))?;
+        self.pop_params(&descriptor, context)?;
+        if let Some(class) = class {
+            match VerificationType::from_class(class)? {
+                Reference(class) => self.pop_value(&class, context)?,
+                _ => unreachable!(),
+            };
+        }
+        self.push_return_type(&descriptor);
+        Ok(())
+    }
+
+    /// invokes the constructor and marks the object as initialized
+    fn initialize(&mut self, class: &str, descriptor: &str, context: &Context) -> Result<(), String> {
+        let descriptor = MethodDescriptor::parse(descriptor).ok_or(format!("Invalid descriptor {}", descriptor))?;
+        if descriptor.ret_type().is_some() {
+            return Err("Constructors must return void".to_owned());
+        }
+        self.pop_params(&descriptor, context)?;
+        let object = self.pop()?;
+        let initialized = match object {
+            Uninitialized(new) => {
+                match context.code[new] {
+                    NEW(ref created) if created == class => Reference(FieldDescriptor::from_class(class)),
+                    _ => return Err(format!("Constructor of {} called on an object of another class", class)),
+                }
+            }
+            UninitializedThis => {
+                let own_class = context.class.name();
+                if class != own_class && Some(class) != context.class.super_class().map(|s| s.as_str()) {
+                    return Err(format!("Constructor of {} called on the uninitialized this", class));
+                }
+                self.uninitialized_this = false;
+                Reference(FieldDescriptor::from_class(own_class))
+            }
+            other => return Err(format!("Expected an uninitialized object, but found {:?}", other)),
+        };
+        self.replace(&object, &initialized);
+        Ok(())
+    }
+
+    /// executes the instruction at ip on the types
+    fn execute(&mut self, ip: usize, context: &Context) -> Result<(), String> {
+        match context.code[ip] {
+            ALOAD(typ) => {
+                self.pop_type(Type::Int)?;
+                match self.pop_array(Some(typ))? {
+                    Some(mut array) => {
+                        array.remove_array();
+                        self.push(VerificationType::from_descriptor(&array));
+                    }
+                    // aaload on null throws a NullPointerException, so any reference will do
+                    None if typ == Type::Reference => self.push(Null),
+                    None => self.push(VerificationType::from_type(typ)),
+                }
+            }
+            ASTORE(typ) => {
+                self.pop_type(typ)?;
+                self.pop_type(Type::Int)?;
+                self.pop_array(Some(typ))?;
+            }
+            LOAD(typ, index) => {
+                let value = self.local(index as usize)?.clone();
+                let valid = match value {
+                    Uninitialized(..) | UninitializedThis => typ == Type::Reference,
+                    ref value => value.is_assignable_to(typ),
+                };
+                if !valid {
+                    return Err(format!("Expected {:?} in local variable {}, but found {:?}", typ, index, value));
+                }
+                self.push(value);
+            }
+            STORE(typ, index) => {
+                let value = self.pop()?;
+                // astore may also store uninitialized objects and return addresses
+                let valid = match value {
+                    Uninitialized(..) | UninitializedThis | ReturnAddress(..) => typ == Type::Reference,
+                    ref value => value.is_assignable_to(typ),
+                };
+                if !valid {
+                    return Err(format!("Expected {:?} on the stack, but found {:?}", typ, value));
+                }
+                self.store(index as usize, value)?;
+            }
+            ARRAYLENGTH => {
+                self.pop_array(None)?;
+                self.push(Int);
+            }
+            ATHROW => {
+                self.pop_value(&FieldDescriptor::from_class(THROWABLE_NAME), context)?;
+            }
+            MONITORENTER | MONITOREXIT | IFNULL(..) => {
+                self.pop_type(Type::Reference)?;
+            }
+            CHECKCAST(ref class) => {
+                self.pop_type(Type::Reference)?;
+                self.push(VerificationType::from_class(class)?);
+            }
+            INSTANCEOF(..) => {
+                self.pop_type(Type::Reference)?;
+                self.push(Int);
+            }
+            ANEWARRAY(ref class) => {
+                self.pop_type(Type::Int)?;
+                let mut array = match VerificationType::from_class(class)? {
+                    Reference(descriptor) => descriptor,
+                    _ => unreachable!(),
+                };
+                array.add_array();
+                self.push(Reference(array));
+            }
+            MULTIANEWARRAY(ref class, dimensions) => {
+                if dimensions == 0 || class.chars().take_while(|&c| c == '[').count() < dimensions as usize {
+                    return Err(format!("{} has less than {} dimensions", class, dimensions));
+                }
+                for _ in 0..dimensions {
+                    self.pop_type(Type::Int)?;
+                }
+                self.push(VerificationType::from_class(class)?);
+            }
+            NEW(ref class) => {
+                if class.starts_with('[') {
+                    return Err(format!("Can't create an array with new {}", class));
+                }
+                let object = Uninitialized(ip);
+                if self.stack.contains(&object) {
+                    return Err("Uninitialized object of the same new on the stack".to_owned());
+                }
+                // the object of an earlier execution of the new in a loop can't be initialized anymore
+                self.replace(&object, &Top);
+                self.push(object);
+            }
+            NEWARRAY(typ) => {
+                self.pop_type(Type::Int)?;
+                let mut array = FieldDescriptor::from_type_without_reference(typ);
+                array.add_array();
+                self.push(Reference(array));
+            }
+            CONVERT(from, to) => {
+                self.pop_type(from)?;
+                self.push(VerificationType::from_type(to));
+            }
+            ADD(typ) | DIV(typ) | MUL(typ) | REM(typ) | SUB(typ) | AND(typ) | OR(typ) | XOR(typ) => {
+                self.pop_type(typ)?;
+                self.pop_type(typ)?;
+                self.push(VerificationType::from_type(typ));
+            }
+            NEG(typ) => {
+                self.pop_type(typ)?;
+                self.push(VerificationType::from_type(typ));
+            }
+            // the shift distance is always an int
+            SHL(typ) | SHR(typ) | USHR(typ) => {
+                self.pop_type(Type::Int)?;
+                self.pop_type(typ)?;
+                self.push(VerificationType::from_type(typ));
+            }
+            IINC(index, _) => {
+                let value = self.local(index as usize)?;
+                if *value != Int {
+                    return Err(format!("Expected Int in local variable {}, but found {:?}", index, value));
+                }
+            }
+            RETURN(typ) => {
+                match (typ, context.descriptor.ret_type()) {
+                    (None, None) => {}
+                    (Some(typ), Some(ret)) if return_category(typ) == return_category(ret.simple_typ()) => {
+                        self.pop_value(ret, context)?;
+                    }
+                    _ => return Err("Return instruction doesn't match the return type".to_owned()),
+                }
+                if self.uninitialized_this {
+                    return Err("Constructor returns without calling the constructor of the superclass".to_owned());
+                }
+            }
+            ACONST_NULL => self.push(Null),
+            FCONST_0 | FCONST_1 | FCONST_2 | LDC_FLOAT(..) => self.push(Float),
+            BIPUSH(..) | SIPUSH(..) | LDC_INT(..) => self.push(Int),
+            DCONST_0 | DCONST_1 | LDC_DOUBLE(..) => self.push(Double),
+            LCONST_0 | LCONST_1 | LDC_LONG(..) => self.push(Long),
+            LDC_STRING(..) => self.push(Reference(FieldDescriptor::from_class(STRING_NAME))),
+            LDC_CLASS(..) => self.push(Reference(FieldDescriptor::from_class(CLASS_NAME))),
+            LDC_METHOD_TYPE(..) | LDC_METHOD_HANDLE(..) => {
+                return Err("Loading method types and method handles with ldc isn't supported".to_owned())
+            }
+            // the stack instructions work on words, but must keep longs and doubles together
+            DUP => {
+                let v = self.pop_groups(&[1])?;
+                self.stack.extend_from_slice(&[v[0].clone(), v[0].clone()]);
+            }
+            DUP_X1 => {
+                let v = self.pop_groups(&[1, 1])?;
+                self.stack.extend_from_slice(&[v[1].clone(), v[0].clone(), v[1].clone()]);
+            }
+            DUP_X2 => {
+                let v = self.pop_groups(&[2, 1])?;
+                self.stack.extend_from_slice(&[v[2].clone(), v[0].clone(), v[1].clone(), v[2].clone()]);
+            }
+            DUP2 => {
+                let v = self.pop_groups(&[2])?;
+                self.stack.extend_from_slice(&[v[0].clone(), v[1].clone(), v[0].clone(), v[1].clone()]);
+            }
+            DUP2_X1 => {
+                let v = self.pop_groups(&[1, 2])?;
+                self.stack.extend_from_slice(&[v[1].clone(), v[2].clone(), v[0].clone(), v[1].clone(), v[2].clone()]);
+            }
+            DUP2_X2 => {
+                let v = self.pop_groups(&[2, 2])?;
+                self.stack.extend_from_slice(&[v[2].clone(), v[3].clone()]);
+                self.stack.extend(v);
+            }
+            POP => {
+                self.pop_groups(&[1])?;
+            }
+            POP2 => {
+                self.pop_groups(&[2])?;
+            }
+            SWAP => {
+                let v = self.pop_groups(&[1, 1])?;
+                self.stack.extend_from_slice(&[v[1].clone(), v[0].clone()]);
+            }
+            GETFIELD(ref field) => {
+                self.pop_value(&FieldDescriptor::from_class(field.class()), context)?;
+                self.push(field_type(field.descriptor())?);
+            }
+            GETSTATIC(ref field) => self.push(field_type(field.descriptor())?),
+            PUTFIELD(ref field) => {
+                self.pop_value(&field_descriptor(field.descriptor())?, context)?;
+                let object = self.pop()?;
+                // the fields of the own class may be assigned before the constructor of the superclass is called
+                let valid = object.is_subtype_of(&Reference(FieldDescriptor::from_class(field.class())), context) ||
+                            (object == UninitializedThis && field.class() == context.class.name());
+                if !valid {
+                    return Err(format!("Expected an object with the field {}, but found {:?}", field.name(), object));
+                }
+            }
+            PUTSTATIC(ref field) => {
+                self.pop_value(&field_descriptor(field.descriptor())?, context)?;
+            }
+            DCMPG | DCMPL => {
+                self.pop_type(Type::Double)?;
+                self.pop_type(Type::Double)?;
+                self.push(Int);
+            }
+            FCMPG | FCMPL => {
+                self.pop_type(Type::Float)?;
+                self.pop_type(Type::Float)?;
+                self.push(Int);
+            }
+            LCMP => {
+                self.pop_type(Type::Long)?;
+                self.pop_type(Type::Long)?;
+                self.push(Int);
+            }
+            GOTO(..) | NOP => {}
+            JSR(dest) => self.push(ReturnAddress(dest as usize)),
+            RET(index) => {
+                match *self.local(index as usize)? {
+                    ReturnAddress(..) => {}
+                    ref other => {
+                        return Err(format!("Expected a return address in local variable {}, but found {:?}",
+                                           index,
+                                           other))
+                    }
+                }
+            }
+            IF_ACMP(..) => {
+                self.pop_type(Type::Reference)?;
+                self.pop_type(Type::Reference)?;
+            }
+            IF_ICMP(..) => {
+                self.pop_type(Type::Int)?;
+                self.pop_type(Type::Int)?;
+            }
+            IF(..) | TABLESWITCH(..) | LOOKUPSWITCH(..) => {
+                self.pop_type(Type::Int)?;
+            }
+            INVOKEDYNAMIC(ref call_site) => self.invoke(call_site.name(), call_site.descriptor(), None, context)?,
+            INVOKESTATIC(ref method) => self.invoke(method.name(), method.descriptor(), None, context)?,
+            INVOKESPECIAL(ref method) if method.name() == "<init>" => {
+                self.initialize(method.class(), method.descriptor(), context)?
+            }
+            INVOKESPECIAL(ref method) |
+            INVOKEVIRTUAL(ref method) => {
+                self.invoke(method.name(), method.descriptor(), Some(method.class()), context)?
+            }
+            INVOKEINTERFACE(ref method, count) => {
+                let descriptor = MethodDescriptor::parse(method.descriptor())
+                    .ok_or(format!("Invalid descriptor {}", method.descriptor()))?;
+                if count as usize != descriptor.words_for_params() + 1 {
+                    return Err(format!("Invalid count {} of invokeinterface", count));
+                }
+                self.invoke(method.name(), method.descriptor(), Some(method.class()), context)?;
+            }
+            // the code of the class file is verified, which is never quickened
+            GETFIELD_QUICK(..) | PUTFIELD_QUICK(..) | GETSTATIC_QUICK(..) | PUTSTATIC_QUICK(..) |
+            INVOKESPECIAL_QUICK(..) | INVOKESTATIC_QUICK(..) | INVOKEVIRTUAL_QUICK(..) | INVOKEINTERFACE_QUICK(..) => {
+                return Err(format!("Unexpected quickened instruction {:?}", context.code[ip]))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field_type(descriptor: &str) -> Result<VerificationType, String> {
+    field_descriptor(descriptor).map(|d| VerificationType::from_descriptor(&d))
+}
+
+fn field_descriptor(descriptor: &str) -> Result<FieldDescriptor, String> {
+    FieldDescriptor::parse(descriptor).ok_or(format!("Invalid field descriptor {}", descriptor))
+}
+
+/// verifies the code of all methods of the class
+/// class files of version 50 and later are type checked against their stack map frames, older ones by type inference
+/// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.10
+/// the loader looks up the superclasses of the classes, whose assignability is checked
+pub fn verify(class: &Class, loader: &mut ClassLoader) -> Result<(), ClassLoadingError> {
+    for method in class.methods() {
+        let result = match class.version() {
+            version if version < 50 => verify_method(class, method, loader),
+            // version 50 falls back to type inference, e.g. for code with subroutines
+            50 => type_check_method(class, method, loader).or_else(|_| verify_method(class, method, loader)),
+            _ => type_check_method(class, method, loader),
+        };
+        result.map_err(|err| verify_error(class, method, err))?;
+    }
+    Ok(())
+}
+
+/// the error of the failing instruction at the index of the method
+fn verify_error(class: &Class, method: &Method, (index, message): (usize, String)) -> ClassLoadingError {
+    let offset = method.code().map_or(index, |code| code.byte_offset(index));
+    ClassLoadingError::VerifyError(format!("{}.{}{} at bytecode index {}: {}",
+                                           class.name(),
+                                           method.name(),
+                                           method.descriptor(),
+                                           offset,
+                                           message))
+}
+
+/// the code of the method, None for abstract and native methods, which must not have code
+fn method_code(method: &Method) -> Result<Option<&Code>, (usize, String)> {
+    let has_no_code = method.is_abstract() || method.access_flags().contains(method_info::NATIVE);
+    match method.code() {
+        Some(_) if has_no_code => Err((0, "Abstract and native methods must not have code".to_owned())),
+        Some(code) if code.code().is_empty() => Err((0, "Method has no code".to_owned())),
+        Some(code) => Ok(Some(code)),
+        None if has_no_code => Ok(None),
+        None => Err((0, "Method has no code".to_owned())),
+    }
+}
+
+/// the types of this and the parameters, longs and doubles take one entry like in the stack map frames
+fn initial_locals(class: &Class, method: &Method, descriptor: &MethodDescriptor) -> Vec<VerificationType> {
+    let mut locals = Vec::new();
+    if !method.is_static() {
+        // java/lang/Object has no superclass, whose constructor must be called
+        if method.name() == "<init>" && class.super_class().is_some() {
+            locals.push(UninitializedThis);
+        } else {
+            locals.push(Reference(FieldDescriptor::from_class(class.name())));
+        }
+    }
+    locals.extend(descriptor.params().iter().map(VerificationType::from_descriptor));
+    locals
+}
+
+/// the words of the types, longs and doubles are followed by Top
+fn words(types: &[VerificationType]) -> Vec<VerificationType> {
+    let mut words = Vec::with_capacity(types.len());
+    for typ in types {
+        words.push(typ.clone());
+        if *typ == Long || *typ == Double {
+            words.push(Top);
+        }
+    }
+    words
+}
+
+/// the state with the locals and the stack, whose longs and doubles take one entry
+/// the locals are filled up with Top to max_locals
+fn frame_state(locals: &[VerificationType], stack: &[VerificationType], code: &Code) -> Result<FrameState, String> {
+    let mut state = FrameState {
+        locals: words(locals),
+        stack: words(stack),
+        uninitialized_this: locals.contains(&UninitializedThis),
+        changed: vec![false; code.max_locals()],
+    };
+    if state.locals.len() > code.max_locals() {
+        return Err(format!("The locals don't fit into {} local variables", code.max_locals()));
+    }
+    let unused = code.max_locals() - state.locals.len();
+    state.locals.extend(iter::repeat(Top).take(unused));
+    if state.stack.len() > code.max_stack() {
+        return Err(format!("Stack overflow, max_stack is {}", code.max_stack()));
+    }
+    Ok(state)
+}
+
+/// the states of the instructions given by the stack map frames of the code, None for the other instructions
+fn stack_map_states(start: &[VerificationType],
+                    code: &Code,
+                    context: &Context)
+                    -> Result<Vec<Option<FrameState>>, (usize, String)> {
+    let mut states = vec![None; code.code().len()];
+    let mut locals = start.to_vec();
+    for frame in code.stack_map().map(|f| &f[..]).unwrap_or(&[]) {
+        let address = frame.address() as usize;
+        let stack = match *frame {
+            StackMapFrame::Same(..) => Vec::new(),
+            StackMapFrame::SameLocals1StackItem(_, ref typ) => vec![typ.clone()],
+            StackMapFrame::Chop(_, count) => {
+                if count > locals.len() {
+                    return Err((address, format!("Stack map frame removes {} of {} locals", count, locals.len())));
+                }
+                let len = locals.len() - count;
+                locals.truncate(len);
+                Vec::new()
+            }
+            StackMapFrame::Append(_, ref appended) => {
+                locals.extend_from_slice(appended);
+                Vec::new()
+            }
+            StackMapFrame::Full(_, ref full_locals, ref stack) => {
+                locals = full_locals.clone();
+                stack.clone()
+            }
+        };
+        let state = frame_state(&locals, &stack, code).map_err(|e| (address, e))?;
+        for typ in state.locals.iter().chain(&state.stack) {
+            if let Uninitialized(new) = *typ {
+                match context.code[new] {
+                    NEW(..) => {}
+                    _ => return Err((address, format!("{:?} doesn't refer to a new instruction", typ))),
+                }
+            }
+        }
+        states[address] = Some(state);
+    }
+    Ok(states)
+}
+
+/// checks the state against the stack map frame at the address, which must exist
+fn check_frame(states: &[Option<FrameState>],
+               address: usize,
+               state: &FrameState,
+               code: &Code,
+               context: &Context)
+               -> Result<(), String> {
+    match states.get(address) {
+        Some(&Some(ref frame)) => state.is_assignable_to_frame(frame, context),
+        Some(&None) => Err(format!("No stack map frame at the branch target {}", code.byte_offset(address))),
+        None => Err(format!("Jump to invalid address {}", address)),
+    }
+}
+
+/// verifies the code in a single pass, the states at branch targets and exception handlers are given by the
+/// stack map frames, see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.10.1
+/// returns the index of the failing instruction and the reason
+fn type_check_method(class: &Class, method: &Method, loader: &mut ClassLoader) -> Result<(), (usize, String)> {
+    let code = match method_code(method)? {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+    let instructions = code.code();
+    let context = Context {
+        class: class,
+        descriptor: MethodDescriptor::parse(method.descriptor()).expect("Methods have valid descriptors"),
+        code: instructions,
+        loader: RefCell::new(loader),
+    };
+    let start = initial_locals(class, method, &context.descriptor);
+    let states = stack_map_states(&start, code, &context)?;
+
+    let mut current = Some(frame_state(&start, &[], code).map_err(|e| (0, e))?);
+    for ip in 0..instructions.len() {
+        if let Some(ref frame) = states[ip] {
+            // the previous instruction continues with the frame
+            if let Some(ref state) = current {
+                state.is_assignable_to_frame(frame, &context).map_err(|e| (ip, e))?;
+            }
+            current = Some(frame.clone());
+        }
+        let before = current.take().ok_or((ip, "No stack map frame behind an unconditional branch".to_owned()))?;
+
+        for handler in code.exception_table().iter().filter(|h| h.covers(ip as CodeAddress)) {
+            let mut state = before.clone();
+            let exception = handler.catch_type().unwrap_or(THROWABLE_NAME);
+            state.stack = vec![Reference(FieldDescriptor::from_class(exception))];
+            check_frame(&states, handler.handler() as usize, &state, code, &context).map_err(|e| (ip, e))?;
+        }
+
+        let mut after = before.clone();
+        after.execute(ip, &context).map_err(|e| (ip, e))?;
+        if after.stack.len() > code.max_stack() {
+            return Err((ip, format!("Stack overflow, max_stack is {}", code.max_stack())));
+        }
+
+        let falls_through = match instructions[ip] {
+            JSR(..) | RET(..) => return Err((ip, "Subroutines can't be type checked".to_owned())),
+            GOTO(..) | ATHROW | RETURN(..) | TABLESWITCH(..) | LOOKUPSWITCH(..) => false,
+            _ => true,
+        };
+        let mut targets = ReferenceMap::successors(ip, &instructions[ip], &[]);
+        if falls_through {
+            targets.remove(0);
+        }
+        for target in targets {
+            check_frame(&states, target, &after, code, &context).map_err(|e| (ip, e))?;
+        }
+        if falls_through {
+            if ip + 1 == instructions.len() {
+                return Err((ip, "Execution falls off the end of the code".to_owned()));
+            }
+            current = Some(after);
+        }
+    }
+    Ok(())
+}
+
+/// infers the states of all instructions by data-flow analysis
+/// returns the index of the failing instruction and the reason
+fn verify_method(class: &Class, method: &Method, loader: &mut ClassLoader) -> Result<(), (usize, String)> {
+    let code = match method_code(method)? {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+    let instructions = code.code();
+    let context = Context {
+        class: class,
+        descriptor: MethodDescriptor::parse(method.descriptor()).expect("Methods have valid descriptors"),
+        code: instructions,
+        loader: RefCell::new(loader),
+    };
+    let start = frame_state(&initial_locals(class, method, &context.descriptor), &[], code).map_err(|e| (0, e))?;
+
+    let rets: Vec<usize> = instructions.iter()
+        .enumerate()
+        .filter(|&(_, i)| match *i {
+            RET(..) => true,
+            _ => false,
+        })
+        .map(|(ip, _)| ip)
+        .collect();
+
+    let mut states = vec![None; instructions.len()];
+    states[0] = Some(start);
+    let mut work = vec![0];
+    while let Some(ip) = work.pop() {
+        let before: FrameState = states[ip].clone().expect("only instructions with a state are visited");
+        let mut after = before.clone();
+        after.execute(ip, &context).map_err(|e| (ip, e))?;
+        if after.stack.len() > code.max_stack() {
+            return Err((ip, format!("Stack overflow, max_stack is {}", code.max_stack())));
+        }
+
+        let mut successors: Vec<(usize, FrameState)> = Vec::new();
+        match instructions[ip] {
+            // a subroutine returns behind all jsrs, which call it
+            RET(index) => {
+                let subroutine = match after.locals[index as usize] {
+                    ReturnAddress(subroutine) => subroutine as CodeAddress,
+                    _ => unreachable!("ret is only executed with a return address"),
+                };
+                for (jsr_ip, instruction) in instructions.iter().enumerate() {
+                    if *instruction == JSR(subroutine) {
+                        if let Some(ref caller) = states[jsr_ip] {
+                            successors.push((jsr_ip + 1, after.return_to(caller)));
+                        }
+                    }
+                }
+            }
+            JSR(dest) => {
+                let mut state = after.clone();
+                state.changed = vec![false; state.locals.len()];
+                successors.push((dest as usize, state));
+                // the subroutine may already have been verified, but must still return behind this jsr
+                for &ret in &rets {
+                    if states[ret].is_some() && !work.contains(&ret) {
+                        work.push(ret);
+                    }
+                }
+            }
+            ref instruction => {
+                for address in ReferenceMap::successors(ip, instruction, &[]) {
+                    successors.push((address, after.clone()));
+                }
+            }
+        }
+        for handler in code.exception_table().iter().filter(|h| h.covers(ip as CodeAddress)) {
+            let mut state = before.clone();
+            let exception = handler.catch_type().unwrap_or(THROWABLE_NAME);
+            state.stack = vec![Reference(FieldDescriptor::from_class(exception))];
+            // the exception may also be thrown after the instruction stored the local variable
+            if let STORE(..) = instructions[ip] {
+                let mut stored = state.clone();
+                stored.locals = after.locals.clone();
+                stored.changed = after.changed.clone();
+                successors.push((handler.handler() as usize, stored));
+            }
+            successors.push((handler.handler() as usize, state));
+        }
+
+        for (address, state) in successors {
+            if address >= instructions.len() {
+                let message = if address == ip + 1 {
+                    "Execution falls off the end of the code".to_owned()
+                } else {
+                    format!("Jump to invalid address {}", address)
+                };
+                return Err((ip, message));
+            }
+            let changed = match states[address] {
+                Some(ref mut old) => old.merge(&state, &context).map_err(|e| (ip, e))?,
+                None => true,
+            };
+            if states[address].is_none() {
+                states[address] = Some(state);
+            }
+            if changed && !work.contains(&address) {
+                work.push(address);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use class::Code;
+    use classfile_parser::method_info::{MethodAccessFlags, STATIC, PUBLIC};
+    use instruction::Comparison;
+    use parsed_class::{FieldRef, MethodRef};
+    use std::fs;
+    use std::path::Path;
+
+    fn verify_method_code(access_flags: MethodAccessFlags,
+                          name: &str,
+                          descriptor: &str,
+                          max_stack: usize,
+                          max_locals: usize,
+                          code: Vec<Instruction>)
+                          -> Result<(), String> {
+        let method = Method::new(access_flags, name, descriptor, Some(Code::new(max_stack, max_locals, code))).unwrap();
+        let class = Class::synthetic("Test", vec![], vec![method], vec![]);
+        let method = &class.methods()[0];
+        let mut loader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        verify_method(&class, method, &mut loader).map_err(|e| verify_error(&class, method, e).to_string())
+    }
+
+    fn type_check_code(descriptor: &str,
+                       max_stack: usize,
+                       max_locals: usize,
+                       code: Vec<Instruction>,
+                       stack_map: Vec<StackMapFrame>)
+                       -> Result<(), String> {
+        let mut code = Code::new(max_stack, max_locals, code);
+        code.set_stack_map(stack_map);
+        let method = Method::new(PUBLIC | STATIC, "test", descriptor, Some(code)).unwrap();
+        let class = Class::synthetic("Test", vec![], vec![method], vec![]);
+        let method = &class.methods()[0];
+        let mut loader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        type_check_method(&class, method, &mut loader).map_err(|e| verify_error(&class, method, e).to_string())
+    }
+
+    fn verify_code(descriptor: &str,
+                   max_stack: usize,
+                   max_locals: usize,
+                   code: Vec<Instruction>)
+                   -> Result<(), String> {
+        verify_method_code(PUBLIC | STATIC, "test", descriptor, max_stack, max_locals, code)
+    }
+
+    fn error(descriptor: &str, index: usize, message: &str) -> Result<(), String> {
+        Err(format!("VerifyError: Test.test{} at bytecode index {}: {}", descriptor, index, message))
+    }
+
+    fn class_names(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = format!("{}{}", prefix, path.file_stem().unwrap().to_str().unwrap());
+            if path.is_dir() {
+                class_names(&path, &format!("{}/", name), names);
+            } else if path.extension().map_or(false, |e| e == "class") {
+                names.push(name);
+            }
+        }
+    }
+
+    #[test]
+    fn class_files() {
+        // all classes generated by javac are valid
+        let mut names = Vec::new();
+        class_names(Path::new(super::super::TEST_CLASSES_DIR), "", &mut names);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        // the classes are verified, when they are loaded
+        for name in &names {
+            assert!(classloader.load_class(name).is_ok());
+        }
+        // the subroutines of the hand written class are valid too
+        assert!(classloader.load_class("TestSubroutine").is_ok());
+    }
+
+    #[test]
+    fn stack() {
+        assert_eq!(verify_code("()V", 1, 0, vec![POP, RETURN(None)]),
+                   error("()V", 0, "Stack underflow"));
+        assert_eq!(verify_code("()V", 1, 0, vec![BIPUSH(1), BIPUSH(2), RETURN(None)]),
+                   error("()V", 1, "Stack overflow, max_stack is 1"));
+        assert_eq!(verify_code("()V", 2, 0, vec![LCONST_0, DUP, RETURN(None)]),
+                   error("()V", 1, "Stack instruction splits a long or double"));
+        assert_eq!(verify_code("(I)V", 1, 1, vec![LOAD(Type::Int, 0), IF(Comparison::EQ, 3), BIPUSH(1), RETURN(None)]),
+                   error("(I)V", 2, "Stack heights 0 and 1 differ"));
+        assert_eq!(verify_code("()V", 6, 0, vec![LCONST_0, LCONST_1, DUP2_X2, POP2, POP2, POP2, RETURN(None)]),
+                   Ok(()));
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(verify_code("()V", 2, 0, vec![FCONST_0, BIPUSH(1), ADD(Type::Int), RETURN(None)]),
+                   error("()V", 2, "Expected Int on the stack, but found Float"));
+        assert_eq!(verify_code("()V", 3, 0, vec![LCONST_0, BIPUSH(1), SHL(Type::Long), POP2, RETURN(None)]),
+                   Ok(()));
+        assert!(verify_code("()V",
+                            2,
+                            0,
+                            vec![BIPUSH(1), NEWARRAY(Type::Float), BIPUSH(0), ALOAD(Type::Int), RETURN(None)])
+            .unwrap_err()
+            .contains("at bytecode index 3: Expected an array of Int"));
+        assert_eq!(verify_code("()I", 1, 0, vec![RETURN(None)]),
+                   error("()I", 0, "Return instruction doesn't match the return type"));
+        assert_eq!(verify_code("()Z", 1, 0, vec![BIPUSH(1), RETURN(Some(Type::Int))]), Ok(()));
+        let class = vec![LDC_CLASS("[I".to_owned()), RETURN(Some(Type::Reference))];
+        assert_eq!(verify_code("()Ljava/lang/Class;", 1, 0, class), Ok(()));
+        assert_eq!(verify_code("()V", 1, 0, vec![LDC_METHOD_TYPE("()V".to_owned()), POP, RETURN(None)]),
+                   error("()V", 0, "Loading method types and method handles with ldc isn't supported"));
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(verify_code("()V", 1, 0, vec![LOAD(Type::Int, 0), RETURN(None)]),
+                   error("()V", 0, "Invalid local variable 0"));
+        assert_eq!(verify_code("()V", 1, 1, vec![LOAD(Type::Int, 0), RETURN(None)]),
+                   error("()V", 0, "Expected Int in local variable 0, but found Top"));
+        // the second word of a long can't be loaded and overwriting it destroys the long
+        assert_eq!(verify_code("(J)V", 1, 2, vec![LOAD(Type::Int, 1), RETURN(None)]),
+                   error("(J)V", 0, "Expected Int in local variable 1, but found Top"));
+        assert_eq!(verify_code("(J)V", 2, 2, vec![BIPUSH(1), STORE(Type::Int, 1), LOAD(Type::Long, 0), RETURN(None)]),
+                   error("(J)V", 2, "Expected Long in local variable 0, but found Top"));
+        assert_eq!(verify_code("()V", 1, 1, vec![NOP]),
+                   error("()V", 0, "Execution falls off the end of the code"));
+    }
+
+    #[test]
+    fn initialization() {
+        let to_string = MethodRef::new("toString", OBJECT_NAME, "()Ljava/lang/String;");
+        let init = MethodRef::new("<init>", OBJECT_NAME, "()V");
+        assert_eq!(verify_code("()V",
+                               2,
+                               0,
+                               vec![NEW(OBJECT_NAME.to_owned()), DUP, INVOKEVIRTUAL(to_string.clone()), RETURN(None)]),
+                   error("()V", 2, "Expected Reference on the stack, but found Uninitialized(0)"));
+        assert_eq!(verify_code("()V",
+                               2,
+                               0,
+                               vec![NEW(OBJECT_NAME.to_owned()), DUP, INVOKESPECIAL(init.clone()),
+                                    INVOKEVIRTUAL(to_string), POP, RETURN(None)]),
+                   Ok(()));
+        assert_eq!(verify_method_code(PUBLIC, "<init>", "()V", 1, 1, vec![RETURN(None)]),
+                   Err("VerifyError: Test.<init>()V at bytecode index 0: Constructor returns without calling the \
+                        constructor of the superclass"
+                       .to_owned()));
+        assert_eq!(verify_method_code(PUBLIC,
+                                      "<init>",
+                                      "()V",
+                                      1,
+                                      1,
+                                      vec![LOAD(Type::Reference, 0), INVOKESPECIAL(init), RETURN(None)]),
+                   Ok(()));
+    }
+
+    #[test]
+    fn subroutines() {
+        // the local variable 1 holds a reference for the first and an int for the second caller of the subroutine,
+        // which doesn't change it
+        assert_eq!(verify_code("()V",
+                               1,
+                               2,
+                               vec![ACONST_NULL, STORE(Type::Reference, 1), JSR(11), LOAD(Type::Reference, 1), POP,
+                                    BIPUSH(1), STORE(Type::Int, 1), JSR(11), LOAD(Type::Int, 1), POP, RETURN(None),
+                                    STORE(Type::Reference, 0), RET(0)]),
+                   Ok(()));
+        // the subroutine overwrites the reference
+        assert_eq!(verify_code("()V",
+                               1,
+                               2,
+                               vec![ACONST_NULL, STORE(Type::Reference, 1), JSR(6), LOAD(Type::Reference, 1), POP,
+                                    RETURN(None), STORE(Type::Reference, 0), BIPUSH(1), STORE(Type::Int, 1), RET(0)]),
+                   error("()V", 3, "Expected Reference in local variable 1, but found Int"));
+    }
+
+    #[test]
+    fn stack_map_frames() {
+        let branch = vec![LOAD(Type::Int, 0), IF(Comparison::EQ, 3), NOP, RETURN(None)];
+        assert_eq!(type_check_code("(I)V", 1, 1, branch.clone(), vec![]),
+                   error("(I)V", 1, "No stack map frame at the branch target 3"));
+        assert_eq!(type_check_code("(I)V", 1, 1, branch.clone(), vec![StackMapFrame::Same(3)]), Ok(()));
+        assert_eq!(type_check_code("(I)V", 1, 1, branch, vec![StackMapFrame::Full(3, vec![Float], vec![])]),
+                   error("(I)V", 1, "Int in local variable 0 doesn't match Float of the stack map frame"));
+        assert_eq!(type_check_code("()V", 1, 0, vec![GOTO(2), NOP, RETURN(None)], vec![StackMapFrame::Same(2)]),
+                   error("()V", 1, "No stack map frame behind an unconditional branch"));
+        // the locals of the frames are relative to the previous frame
+        assert_eq!(type_check_code("()V",
+                                   1,
+                                   2,
+                                   vec![BIPUSH(0), STORE(Type::Int, 0), LOAD(Type::Int, 0), IF(Comparison::EQ, 2),
+                                        RETURN(None)],
+                                   vec![StackMapFrame::Append(2, vec![Int]), StackMapFrame::Chop(4, 1)]),
+                   Ok(()));
+        assert_eq!(type_check_code("()V",
+                                   1,
+                                   1,
+                                   vec![GOTO(1), LOAD(Type::Int, 0), RETURN(None)],
+                                   vec![StackMapFrame::Chop(1, 1)]),
+                   error("()V", 1, "Stack map frame removes 1 of 0 locals"));
+        assert_eq!(type_check_code("()V", 1, 0, vec![JSR(2), RETURN(None), STORE(Type::Reference, 0), RET(0)], vec![]),
+                   error("()V", 0, "Subroutines can't be type checked"));
+    }
+
+    #[test]
+    fn stack_map_uninitialized() {
+        let init = MethodRef::new("<init>", OBJECT_NAME, "()V");
+        let code = vec![NEW(OBJECT_NAME.to_owned()), DUP, GOTO(3), INVOKESPECIAL(init), POP, RETURN(None)];
+        assert_eq!(type_check_code("()V",
+                                   2,
+                                   0,
+                                   code.clone(),
+                                   vec![StackMapFrame::Full(3, vec![], vec![Uninitialized(0), Uninitialized(0)])]),
+                   Ok(()));
+        assert_eq!(type_check_code("()V",
+                                   2,
+                                   0,
+                                   code,
+                                   vec![StackMapFrame::Full(3, vec![], vec![Uninitialized(1), Uninitialized(1)])]),
+                   error("()V", 3, "Uninitialized(1) doesn't refer to a new instruction"));
+    }
+
+    #[test]
+    fn reference_assignability() {
+        let class = Class::synthetic("Test", vec![], vec![], vec![]);
+        let mut loader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let context = Context {
+            class: &class,
+            descriptor: MethodDescriptor::parse("()V").unwrap(),
+            code: &[],
+            loader: RefCell::new(&mut loader),
+        };
+        let typ = |class: &str| VerificationType::from_class(class).unwrap();
+        let object = typ(OBJECT_NAME);
+        let strings = typ("[Ljava/lang/String;");
+        let objects = typ("[Ljava/lang/Object;");
+        let ints = typ("[I");
+        assert!(strings.is_subtype_of(&objects, &context));
+        assert!(ints.is_subtype_of(&object, &context));
+        assert!(ints.is_subtype_of(&typ(CLONEABLE_NAME), &context));
+        assert!(!ints.is_subtype_of(&objects, &context));
+        assert!(!object.is_subtype_of(&ints, &context));
+        assert!(Null.is_subtype_of(&ints, &context));
+        assert!(Long.is_subtype_of(&Top, &context));
+        assert!(!Top.is_subtype_of(&Int, &context));
+
+        let sub = typ("com/mackie/rustyjvm/TestVMStatic");
+        let super_class = typ("com/mackie/rustyjvm/TestVMStaticSuper");
+        let node = typ("com/mackie/rustyjvm/TestVMNode");
+        assert!(sub.is_subtype_of(&super_class, &context));
+        assert!(!super_class.is_subtype_of(&sub, &context));
+        assert!(!typ(STRING_NAME).is_subtype_of(&node, &context));
+        assert!(!typ("com/mackie/rustyjvm/NotExisting").is_subtype_of(&node, &context));
+        // all references are assignable to interfaces
+        assert!(node.is_subtype_of(&typ("java/lang/Runnable"), &context));
+        assert_eq!(sub.merge(&super_class, &context), Some(super_class.clone()));
+        assert_eq!(sub.merge(&node, &context), Some(object.clone()));
+        assert_eq!(typ("[Lcom/mackie/rustyjvm/TestVMStatic;").merge(&typ("[Lcom/mackie/rustyjvm/TestVMStaticSuper;"),
+                                                                   &context),
+                   Some(typ("[Lcom/mackie/rustyjvm/TestVMStaticSuper;")));
+    }
+
+    #[test]
+    fn type_confusion() {
+        let value = FieldRef::new("value", "com/mackie/rustyjvm/TestVMNode", "I").unwrap();
+        let string = || LDC_STRING("node".encode_utf16().collect());
+        assert_eq!(verify_code("()V", 1, 0, vec![string(), GETFIELD(value.clone()), POP, RETURN(None)]),
+                   error("()V", 1, "Expected com/mackie/rustyjvm/TestVMNode on the stack, but found java/lang/String"));
+        assert!(verify_code("()V", 2, 0, vec![string(), BIPUSH(1), PUTFIELD(value.clone()), RETURN(None)])
+            .unwrap_err()
+            .contains("at bytecode index 2: Expected an object with the field value"));
+        let root = FieldRef::new("root", "com/mackie/rustyjvm/TestVMNode", "Lcom/mackie/rustyjvm/TestVMNode;")
+            .unwrap();
+        assert!(verify_code("()V", 1, 0, vec![string(), PUTSTATIC(root), RETURN(None)])
+            .unwrap_err()
+            .contains("at bytecode index 1: Expected com/mackie/rustyjvm/TestVMNode on the stack"));
+        let length = MethodRef::new("length", STRING_NAME, "()I");
+        assert!(verify_code("(Lcom/mackie/rustyjvm/TestVMNode;)V",
+                            1,
+                            1,
+                            vec![LOAD(Type::Reference, 0), INVOKEVIRTUAL(length.clone()), POP, RETURN(None)])
+            .unwrap_err()
+            .contains("at bytecode index 1: Expected java/lang/String on the stack"));
+        assert_eq!(verify_code("()V", 1, 0, vec![string(), INVOKEVIRTUAL(length), POP, RETURN(None)]), Ok(()));
+        assert!(verify_code("()Lcom/mackie/rustyjvm/TestVMNode;", 1, 0, vec![string(), RETURN(Some(Type::Reference))])
+            .unwrap_err()
+            .contains("at bytecode index 1: Expected com/mackie/rustyjvm/TestVMNode on the stack"));
+    }
+}
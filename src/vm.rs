@@ -1,24 +1,104 @@
-use classfile_parser::method_info::{PUBLIC, STATIC, NATIVE};
+use classfile_parser::method_info::{PUBLIC, STATIC, NATIVE, SYNCHRONIZED};
 use class_loader::ClassLoader;
-use instruction::{Instruction, LocalVarRef};
+use instruction::{Instruction, LocalVarRef, CodeAddress, ClassId, MethodId};
 use instruction::Instruction::*;
+use instruction::Type;
 use instruction::Type::*;
-use parsed_class::MethodRef;
+use parsed_class::{MethodRef, FieldRef, InvokeDynamicRef};
 use descriptor::{FieldDescriptor, MethodDescriptor};
 use object::{Object, ArrayObject, InstanceObject};
-use class::Class;
+use class::{Class, Method, ExceptionHandler, ConstantValue, OBJECT_NAME};
+use errors::ClassLoadingError;
+use lambda;
+use gc::Heap;
+use reference_map::{ReferenceMap, Slot};
+use native::{NativeRegistry, NativeMethod, Value};
+use thread::{Scheduler, Monitors, ThreadState};
+use signals;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::io::{stderr, Write};
+use std::iter;
 use std::mem;
-use std::char;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
 use std::ops::{Mul, Add, Div, Sub, Rem, BitAnd, BitOr, BitXor};
 
 // USE WITH CARE
 macro_rules! conv { ($val: expr) => {{unsafe {mem::transmute($val)}}} }
 
+// exceptions, which are thrown by the vm itself
+// see https://docs.oracle.com/javase/specs/jvms/se6/html/Concepts.doc.html#22727
+const NULL_POINTER_EXCEPTION: &'static str = "java/lang/NullPointerException";
+const ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION: &'static str = "java/lang/ArrayIndexOutOfBoundsException";
+const ARITHMETIC_EXCEPTION: &'static str = "java/lang/ArithmeticException";
+const CLASS_CAST_EXCEPTION: &'static str = "java/lang/ClassCastException";
+const NEGATIVE_ARRAY_SIZE_EXCEPTION: &'static str = "java/lang/NegativeArraySizeException";
+const NO_CLASS_DEF_FOUND_ERROR: &'static str = "java/lang/NoClassDefFoundError";
+const EXCEPTION_IN_INITIALIZER_ERROR: &'static str = "java/lang/ExceptionInInitializerError";
+const INCOMPATIBLE_CLASS_CHANGE_ERROR: &'static str = "java/lang/IncompatibleClassChangeError";
+const NO_SUCH_METHOD_ERROR: &'static str = "java/lang/NoSuchMethodError";
+const NO_SUCH_FIELD_ERROR: &'static str = "java/lang/NoSuchFieldError";
+const ABSTRACT_METHOD_ERROR: &'static str = "java/lang/AbstractMethodError";
+const BOOTSTRAP_METHOD_ERROR: &'static str = "java/lang/BootstrapMethodError";
+const OUT_OF_MEMORY_ERROR: &'static str = "java/lang/OutOfMemoryError";
+const UNSATISFIED_LINK_ERROR: &'static str = "java/lang/UnsatisfiedLinkError";
+const ILLEGAL_MONITOR_STATE_EXCEPTION: &'static str = "java/lang/IllegalMonitorStateException";
+const ILLEGAL_ARGUMENT_EXCEPTION: &'static str = "java/lang/IllegalArgumentException";
+const ARRAY_STORE_EXCEPTION: &'static str = "java/lang/ArrayStoreException";
+const ERROR_NAME: &'static str = "java/lang/Error";
+const STRING_NAME: &'static str = "java/lang/String";
+const CLASS_NAME: &'static str = "java/lang/Class";
+const THREAD_NAME: &'static str = "java/lang/Thread";
+const THROWABLE_NAME: &'static str = "java/lang/Throwable";
+const STACK_TRACE_ELEMENT_NAME: &'static str = "java/lang/StackTraceElement";
+const STACK_TRACE_DESCRIPTOR: &'static str = "[Ljava/lang/StackTraceElement;";
+
+// the thread, which executes the main method
+const MAIN_THREAD: usize = 0;
+
+/// the reason, why the vm stopped before the main thread finished normally
+#[derive(Debug, PartialEq)]
+enum Abort {
+    // the exception, which wasn't caught by any frame of the main thread
+    UncaughtException(i32),
+    // all threads, which aren't terminated, wait forever
+    Deadlock,
+}
+
 pub struct VM {
     classloader: ClassLoader,
+    // the runtime states of the classes by their ids
+    classes: Vec<ClassState>,
+    class_ids: HashMap<String, ClassId>,
+    // the methods, which were invoked, by their ids
+    methods: Vec<LinkedMethod>,
+    method_ids: HashMap<MethodRef, MethodId>,
+    // the frames of the current thread, the other threads keep their own frames
     frames: Vec<Frame>,
-    heap: Vec<Option<Object>>,
-    // TODO #[cfg(debug)]
+    threads: Vec<JavaThread>,
+    current_thread: usize,
+    // the number of instructions, which the current thread executes before the next thread is scheduled
+    // 0 starts the scheduling before the next instruction, e.g. after the current thread blocked
+    quantum_left: usize,
+    scheduler: Scheduler,
+    monitors: Monitors,
+    heap: Heap,
+    // the interned strings, string literals with the same characters are the same object
+    strings: HashMap<Vec<u16>, i32>,
+    // the reference maps of the methods, which were on the stack during a garbage collection
+    // None, if the code of the method couldn't be analyzed
+    reference_maps: HashMap<MethodId, Option<ReferenceMap>>,
+    // the instances of java/lang/Class by the descriptor of the type, V for void
+    class_objects: HashMap<String, i32>,
+    // the types, which are represented by the instances of java/lang/Class, except void
+    class_types: HashMap<i32, FieldDescriptor>,
+    natives: NativeRegistry,
+    // the virtual methods and their arguments, which the current native method calls back
+    callbacks: Vec<(MethodRef, Vec<i32>)>,
+    // the calls of the native methods of the tests
+    #[cfg(test)]
     native_calls: Vec<(String, String, Vec<i32>)>,
 }
 
@@ -26,30 +106,111 @@ pub struct Frame {
     // arrays are to big to create them on the stack
     // TODO think about using maybe Box<Frame> with arrays
     //  => benchmark
-    code: Vec<Instruction>,
+    // the code is shared with the method, so invocations don't copy it and it is quickened only once
+    code: Rc<RefCell<Vec<Instruction>>>,
+    exception_table: Rc<[ExceptionHandler]>,
     ip: usize,
     sp: usize,
     local_vars: Vec<i32>,
     stack: Vec<i32>,
     current_class: String,
+    // the executed method, None for frames, which are generated by the vm
+    method: Option<MethodId>,
+    // true, if this frame executes the class initialization method of current_class
+    initializes_class: bool,
+    // the object, whose monitor is held by the synchronized method
+    monitor: Option<i32>,
+    // the quick instruction and its index, which replaces the executed instruction before the next one is fetched
+    quickened: Option<(usize, Instruction)>,
 }
 
-impl VM {
-    pub fn new(loader: ClassLoader) -> VM {
-        let mut heap = Vec::new();
-        // create dummy null object
-        heap.push(None);
+/// a java thread, which is scheduled by the vm
+struct JavaThread {
+    // the instance of java/lang/Thread, 0 until the main thread requests it
+    object: i32,
+    state: ThreadState,
+    // the frames of a thread, which isn't the current thread
+    frames: Vec<Frame>,
+    frame: Option<Frame>,
+    uncaught_exception: Option<i32>,
+}
+
+/// a method, which was invoked before, quickened instructions refer to it by its id
+struct LinkedMethod {
+    // refers to the class, which declares the method
+    reference: MethodRef,
+    descriptor: MethodDescriptor,
+    is_static: bool,
+    is_synchronized: bool,
+    is_native: bool,
+    is_abstract: bool,
+    // None, if there is no rust implementation for the native method
+    native: Option<NativeMethod>,
+    // including the object for non static methods
+    words_for_params: usize,
+    max_stack: usize,
+    max_locals: usize,
+    // a copy of the code of the class file, which gets quickened during the execution
+    code: Rc<RefCell<Vec<Instruction>>>,
+    exception_table: Rc<[ExceptionHandler]>,
+}
 
+/// the part of a class, which changes at runtime
+struct ClassState {
+    name: String,
+    initialization: Initialization,
+    static_fields: Box<[i32]>,
+    // caches the linked methods, which implement the linked interface methods for instances of this class
+    itable: HashMap<MethodId, MethodId>,
+    // the linked methods of the vtable of the class, invokevirtual selects the method by its index
+    vtable: Vec<MethodId>,
+    // the lambda classes, to which the invokedynamic instructions of this class are linked
+    call_sites: HashMap<InvokeDynamicRef, String>,
+}
+
+// see https://docs.oracle.com/javase/specs/jvms/se6/html/Concepts.doc.html#24237
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Initialization {
+    NotInitialized,
+    // the index of the initializing thread
+    BeingInitialized(usize),
+    Initialized,
+    Erroneous,
+}
+
+impl VM {
+    /// the heap of the vm can grow to max_heap_size bytes, the threads are scheduled by the scheduler
+    pub fn new(loader: ClassLoader, max_heap_size: usize, scheduler: Scheduler) -> VM {
         VM {
+            #[cfg(test)]
             native_calls: Vec::new(),
             classloader: loader,
+            classes: Vec::new(),
+            class_ids: HashMap::new(),
+            methods: Vec::new(),
+            method_ids: HashMap::new(),
             frames: Vec::new(),
-            heap: heap,
+            threads: Vec::new(),
+            current_thread: MAIN_THREAD,
+            quantum_left: 0,
+            scheduler: scheduler,
+            monitors: Monitors::new(),
+            heap: Heap::new(max_heap_size),
+            strings: HashMap::new(),
+            reference_maps: HashMap::new(),
+            class_objects: HashMap::new(),
+            class_types: HashMap::new(),
+            natives: NativeRegistry::new(),
+            callbacks: Vec::new(),
         }
     }
 
+    pub fn heap(&mut self) -> &mut Heap { &mut self.heap }
+    pub fn classloader(&mut self) -> &mut ClassLoader { &mut self.classloader }
+
+    /// runs the main method of the class and returns the exit status, which is 1 after an uncaught exception
     // TODO think about using a real error type here
-    pub fn start(&mut self, class: &str, _args: &[&str]) -> Result<(), String> {
+    pub fn start(&mut self, class: &str, args: &[&str]) -> Result<i32, String> {
         let class_name;
         {
             let start_class = self.classloader.load_class(class).map_err(|err| format!("ClassLoadingError: {}", err))?;
@@ -65,95 +226,1190 @@ impl VM {
 
             class_name = start_class.name().to_owned();
         }
-        let mut start_frame = Frame::dummy_frame(1);
-        start_frame.push(0); // TODO push real args on the stack
+        let mut args_array = ArrayObject::new(args.len() as i32, FieldDescriptor::from_class(STRING_NAME));
+        for (index, arg) in args.iter().enumerate() {
+            let string = self.create_string(&arg.encode_utf16().collect::<Vec<u16>>());
+            args_array.set(index as i32, string).expect("index is always in bounds");
+        }
+        let args_array = self.heap.allocate(Object::Array(args_array));
+
+        let start_frame = Frame::bootstrap_frame(MethodRef::new("main", &class_name, "([Ljava/lang/String;)V"),
+                                                 &[args_array]);
+
+        // the uncaught exception and the thread dump of the deadlock were already reported
+        match self.run(start_frame) {
+            Ok(()) => Ok(0),
+            Err(Abort::UncaughtException(..)) => Ok(1),
+            Err(Abort::Deadlock) => Err("Deadlock: all threads wait forever".to_owned()),
+        }
+    }
+
+    /// invokes the method with the arguments on the stack of calling_frame
+    /// returns the name of the exception, which must be thrown, if a native method fails
+    fn invoke_method(&mut self,
+                     class_name: &str,
+                     method: &str,
+                     descriptor: &str,
+                     calling_frame: &mut Frame)
+                     -> Result<(), &'static str> {
+        let method = self.link_method(class_name, method, descriptor);
+        self.invoke_linked_method(method, calling_frame)
+    }
+
+    /// returns the linked method, which is declared by the class, and links it, if this didn't happen yet
+    fn link_method(&mut self, class_name: &str, method: &str, descriptor: &str) -> MethodId {
+        let reference = MethodRef::new(method, class_name, descriptor);
+        if let Some(&id) = self.method_ids.get(&reference) {
+            return id;
+        }
+        let linked = {
+            // these unwraps should be checked in the linking stage
+            let class = self.classloader.load_class(class_name).unwrap();
+            let method = class.method_by_signature(method, descriptor).unwrap();
+            let native = if method.access_flags().contains(NATIVE) {
+                self.natives.get(&reference)
+            } else {
+                None
+            };
+            LinkedMethod::new(reference.clone(), method, native)
+        };
+        self.methods.push(linked);
+        self.method_ids.insert(reference, self.methods.len() - 1);
+        self.methods.len() - 1
+    }
+
+    /// invokes the linked method with the arguments on the stack of calling_frame
+    /// returns the name of the exception, which must be thrown, if a native method fails
+    fn invoke_linked_method(&mut self, id: MethodId, calling_frame: &mut Frame) -> Result<(), &'static str> {
+        if self.methods[id].is_native {
+            return self.invoke_native(id, calling_frame);
+        }
+        let mut new_frame = {
+            let method = &self.methods[id];
+            let args = &calling_frame.stack[calling_frame.sp - method.words_for_params..calling_frame.sp];
+            calling_frame.sp -= method.words_for_params;
 
-        self.invoke_method(&class_name,
-                           "main",
-                           "([Ljava/lang/String;)V",
-                           &mut start_frame);
+            let mut local_vars = Vec::with_capacity(method.max_locals);
+            local_vars.resize(method.max_locals, 0);
+            local_vars[..args.len()].copy_from_slice(args);
+            let mut stack = Vec::with_capacity(method.max_stack);
+            stack.resize(method.max_stack, 0);
+
+            Frame {
+                ip: 0,
+                sp: 0,
+                local_vars: local_vars,
+                stack: stack,
+                code: method.code.clone(),
+                exception_table: method.exception_table.clone(),
+                current_class: method.reference.class().to_owned(),
+                method: Some(id),
+                initializes_class: false,
+                monitor: None,
+                quickened: None,
+            }
+        };
+        mem::swap(&mut new_frame, calling_frame);
+        self.frames.push(new_frame);
 
-        self.run(start_frame);
+        // the monitor is entered before the first instruction, the thread may block until then
+        if self.methods[id].is_synchronized {
+            let object = if self.methods[id].is_static {
+                let class = FieldDescriptor::from_class(&calling_frame.current_class);
+                self.class_object(&class)
+            } else {
+                calling_frame.local_vars[0]
+            };
+            calling_frame.monitor = Some(object);
+            self.enter_monitor(object, 1);
+        }
         Ok(())
     }
 
-    fn invoke_method(&mut self, class_name: &str, method: &str, descriptor: &str, calling_frame: &mut Frame) {
-        // these unwraps should be checked in the linking stage
-        let method = self.classloader.load_class(class_name).unwrap().method_by_signature(method, descriptor).unwrap();
+    /// calls the rust implementation of the native method with the arguments on the stack of calling_frame
+    fn invoke_native(&mut self, id: MethodId, calling_frame: &mut Frame) -> Result<(), &'static str> {
+        let args = {
+            let method = &self.methods[id];
+            calling_frame.sp -= method.words_for_params;
+            let mut args = Vec::with_capacity(method.descriptor.params().len() + 1);
+            let mut index = calling_frame.sp;
+            if !method.is_static {
+                args.push(Value::Reference(calling_frame.stack[index]));
+                index += 1;
+            }
+            for param in method.descriptor.params() {
+                args.push(Value::from_words(param.simple_typ(), &calling_frame.stack[index..]));
+                index += param.word_size();
+            }
+            args
+        };
 
-        let mut local_vars;
-        let code;
+        let native = match self.methods[id].native {
+            Some(ref n) => n.clone(),
+            None => return Err(UNSATISFIED_LINK_ERROR),
+        };
+        // the calling frame is on top of the frames during the call, so the native sees the whole stack
+        let caller = mem::replace(calling_frame, Frame::synthetic_frame(Vec::new(), 0, ""));
+        self.frames.push(caller);
+        let result = native(self, &args);
+        *calling_frame = self.frames.pop().expect("the calling frame was pushed");
+        let callbacks = mem::replace(&mut self.callbacks, Vec::new());
+        if let Some(result) = result? {
+            for word in result.to_words() {
+                calling_frame.push(word);
+            }
+        }
+        // the callbacks are invoked by a frame, which returns to the calling frame
+        if !callbacks.is_empty() {
+            let mut new_frame = Frame::callback_frame(callbacks);
+            mem::swap(&mut new_frame, calling_frame);
+            self.frames.push(new_frame);
+        }
+        Ok(())
+    }
+
+    /// invokes the virtual method with the arguments, which start with the object, after the current native method
+    /// returned. The callbacks are invoked in order and their results are discarded
+    pub fn call_back(&mut self, method: MethodRef, args: Vec<i32>) { self.callbacks.push((method, args)); }
+
+    /// the index of the runtime state of the class, which is created, when the class is used the first time
+    fn class_id(&mut self, class: &str) -> ClassId {
+        if let Some(&id) = self.class_ids.get(class) {
+            return id;
+        }
+        let mut state;
+        let mut strings = Vec::new();
+        let vtable;
         {
-            let args = &calling_frame.stack[calling_frame.sp - method.words_for_params()..calling_frame.sp];
-            calling_frame.sp -= method.words_for_params();
+            // TODO replace unwrap with exception throw
+            let loaded = self.classloader.load_class(class).unwrap();
+            state = ClassState::new(loaded);
+            vtable = loaded.vtable().to_vec();
+            let mut offset = 0;
+            for field in loaded.static_fields() {
+                if let Some(&ConstantValue::String(ref chars)) = field.constant_value() {
+                    strings.push((offset, chars.clone()));
+                }
+                offset += field.size();
+            }
+        }
+        for (offset, chars) in strings {
+            state.static_fields[offset] = self.intern_string(&chars);
+        }
+        state.vtable = vtable.iter().map(|m| self.link_method(m.class(), m.name(), m.descriptor())).collect();
+        self.classes.push(state);
+        self.class_ids.insert(class.to_owned(), self.classes.len() - 1);
+        self.classes.len() - 1
+    }
+
+    fn class_state(&mut self, class: &str) -> &mut ClassState {
+        let id = self.class_id(class);
+        &mut self.classes[id]
+    }
+
+    /// initializes the class and its superclasses, if this didn't happen yet
+    /// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.5
+    /// returns true, if the class can be used right away. Returns false, if frames for the
+    /// initialization methods were pushed. The current instruction is executed again, after they returned.
+    /// The error is the exception, which must be thrown, if the class is erroneous.
+    fn initialize_class(&mut self, class: &str, frame: &mut Frame) -> Result<bool, i32> {
+        // the classes, which must be initialized, starting with class and ending with the topmost superclass
+        let mut uninitialized: Vec<String> = Vec::new();
+        let mut cur_name = Some(class.to_owned());
+        while let Some(name) = cur_name {
+            let initialization = self.class_state(&name).initialization;
+            match initialization {
+                // other threads must wait until the initialization is finished
+                Initialization::BeingInitialized(thread) if thread != self.current_thread => {
+                    self.wait_for_initialization(&name, frame);
+                    return Ok(false);
+                }
+                // the initializing thread can use the class during its own initialization
+                Initialization::BeingInitialized(..) |
+                Initialization::Initialized => break,
+                Initialization::Erroneous => return Err(self.initialization_impossible(&uninitialized, frame)),
+                Initialization::NotInitialized => {}
+            }
+            let is_interface;
+            {
+                let loaded = self.classloader.load_class(&name).unwrap();
+                is_interface = loaded.is_interface();
+                // the superinterfaces of an interface are not initialized
+                cur_name = if is_interface {
+                    None
+                } else {
+                    loaded.super_class().cloned()
+                };
+            }
+            // superinterfaces with default methods are initialized after the superclass, but before the class
+            let interfaces = if is_interface {
+                Vec::new()
+            } else {
+                Class::default_method_interfaces(&name, &mut self.classloader).unwrap()
+            };
+            uninitialized.push(name);
+            for interface in interfaces.into_iter().rev() {
+                let initialization = self.class_state(&interface).initialization;
+                match initialization {
+                    Initialization::NotInitialized if !uninitialized.contains(&interface) => {
+                        uninitialized.push(interface)
+                    }
+                    Initialization::BeingInitialized(thread) if thread != self.current_thread => {
+                        self.wait_for_initialization(&interface, frame);
+                        return Ok(false);
+                    }
+                    Initialization::Erroneous => return Err(self.initialization_impossible(&uninitialized, frame)),
+                    _ => {}
+                }
+            }
+        }
 
-            if method.access_flags().contains(NATIVE) {
-                self.native_calls.push((method.name().to_owned(), method.descriptor().to_owned(), args.to_vec()));
-                // TODO real handling of call
-                return;
+        let mut has_initializer = false;
+        for c in &uninitialized {
+            self.class_state(c).initialization = Initialization::BeingInitialized(self.current_thread);
+            has_initializer |= self.classloader.load_class(c).unwrap().method_by_signature("<clinit>", "()V").is_some();
+        }
+        if !has_initializer {
+            for c in &uninitialized {
+                self.class_state(c).initialization = Initialization::Initialized;
             }
-            // TODO remove specialhandling for dump_char, when there is another way to output
-            if method.name() == "dump_char" && method.descriptor() == "(C)V" {
-                print!("{}", char::from_u32(args[0] as u32).unwrap_or('?'));
-                return;
+            return Ok(true);
+        }
+
+        // the frame of the topmost superclass is pushed last, so it gets executed first
+        for c in &uninitialized {
+            if self.classloader.load_class(c).unwrap().method_by_signature("<clinit>", "()V").is_some() {
+                self.invoke_method(c, "<clinit>", "()V", frame).expect("class initialization methods are not native");
+            } else {
+                // a class without initialization method must still wait for its superclasses
+                let mut new_frame = Frame::synthetic_frame(vec![RETURN(None)], 0, c);
+                mem::swap(&mut new_frame, frame);
+                self.frames.push(new_frame);
             }
+            frame.initializes_class = true;
+        }
+        Ok(false)
+    }
 
-            code = method.code().expect("Method must have code");
-            // println!("Code: {:?}", code);
+    /// blocks the current thread, until another thread finished the initialization of the class
+    /// the instruction of frame, which triggered the initialization, is executed again afterwards
+    fn wait_for_initialization(&mut self, class: &str, frame: &mut Frame) {
+        let id = self.class_id(class);
+        self.threads[self.current_thread].state = ThreadState::WaitingForClass(id);
+        self.quantum_left = 0;
+        frame.ip -= 1;
+    }
 
-            local_vars = Vec::with_capacity(code.max_locals());
-            local_vars.resize(code.max_locals(), 0);
-            local_vars[..args.len()].copy_from_slice(args);
+    /// marks the classes as erroneous, because one of their superclasses or superinterfaces is erroneous
+    /// returns the exception, which gets thrown to the code in frame, which triggered the initialization
+    fn initialization_impossible(&mut self, classes: &[String], frame: &Frame) -> i32 {
+        for c in classes {
+            self.class_state(c).initialization = Initialization::Erroneous;
         }
-        let mut stack = Vec::with_capacity(code.max_stack());
-        stack.resize(code.max_stack(), 0);
+        self.create_exception(NO_CLASS_DEF_FOUND_ERROR, Some(frame))
+    }
 
-        let mut new_frame = Frame {
-            ip: 0,
-            sp: 0,
-            local_vars: local_vars,
-            stack: stack,
-            code: code.code().clone(),
-            current_class: class_name.to_owned(),
+    /// marks the class as erroneous after its initialization method threw the exception
+    /// returns the exception, which gets thrown to the code, which triggered the initialization
+    fn initialization_failed(&mut self, class: &str, exception: i32) -> i32 {
+        self.class_state(class).initialization = Initialization::Erroneous;
+
+        let is_error = {
+            let obj = VM::get_object(&mut self.heap, exception);
+            Class::is_instance_of(obj.typ(),
+                                  FieldDescriptor::from_class(ERROR_NAME),
+                                  &mut self.classloader)
+                .unwrap()
         };
-        mem::swap(&mut new_frame, calling_frame);
-        self.frames.push(new_frame);
+        if is_error {
+            return exception;
+        }
+
+        let error = self.create_exception(EXCEPTION_IN_INITIALIZER_ERROR, None);
+        let field = FieldRef::new("exception", EXCEPTION_IN_INITIALIZER_ERROR, "Ljava/lang/Throwable;").unwrap();
+        VM::get_instance(&mut self.heap, error)
+            .set_field(&field, exception, &mut self.classloader)
+            .expect("ExceptionInInitializerError must have a field for the exception");
+        // the exception is the cause of the error, like in its constructor
+        let cause = FieldRef::new("cause", THROWABLE_NAME, "Ljava/lang/Throwable;").unwrap();
+        VM::get_instance(&mut self.heap, error)
+            .set_field(&cause, exception, &mut self.classloader)
+            .expect("Throwable must have a field for the cause");
+        error
+    }
+
+    /// selects the implementation of the resolved interface method, which invokeinterface calls on an instance of class
+    /// see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc6.html#invokeinterface
+    /// returns the name of the error, which must be thrown, if there is no valid implementation
+    fn select_interface_method(&mut self, class: ClassId, method: MethodId) -> Result<MethodId, &'static str> {
+        if let Some(&selected) = self.classes[class].itable.get(&method) {
+            return Ok(selected);
+        }
+
+        let class_name = self.classes[class].name.clone();
+        let method_ref = self.methods[method].reference.clone();
+        // TODO replace unwraps with throw class loading exception
+        if !Class::has_interface_or_superclass(&class_name, method_ref.class(), &mut self.classloader).unwrap() {
+            return Err(INCOMPATIBLE_CLASS_CHANGE_ERROR);
+        }
+        let dest_class = self.select_method(&class_name, &method_ref)?;
+        let selected = self.link_method(&dest_class, method_ref.name(), method_ref.descriptor());
+
+        self.classes[class].itable.insert(method, selected);
+        Ok(selected)
     }
 
-    fn invoke_method_ref(&mut self, method: &MethodRef, calling_frame: &mut Frame) {
-        self.invoke_method(method.class(),
-                           method.name(),
-                           method.descriptor(),
-                           calling_frame)
+    /// selects the class or interface, whose implementation of the method is called on an instance of class
+    /// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.invokevirtual
+    /// returns the name of the error, which must be thrown, if there is no valid implementation
+    fn select_method(&mut self, class: &str, method: &MethodRef) -> Result<String, &'static str> {
+        let dest_class = match Class::select_method(class, method.name(), method.descriptor(), &mut self.classloader) {
+            Ok(Some(c)) => c,
+            Ok(None) => return Err(ABSTRACT_METHOD_ERROR),
+            // conflicting default methods
+            Err(ClassLoadingError::IncompatibleClassChange) => return Err(INCOMPATIBLE_CLASS_CHANGE_ERROR),
+            // a superclass or superinterface, which is searched for the method, can't be loaded
+            Err(_) => return Err(NO_CLASS_DEF_FOUND_ERROR),
+        };
+        if self.classloader
+            .load_class(&dest_class)
+            .unwrap()
+            .method_by_signature(method.name(), method.descriptor())
+            .unwrap()
+            .is_abstract() {
+            return Err(ABSTRACT_METHOD_ERROR);
+        }
+        Ok(dest_class)
     }
 
-    fn allocate_object(&mut self, object: Object) -> i32 {
-        // TODO think of a better allocation scheeme
+    /// the offset of the instance field in the objects, which have it
+    /// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.getfield
+    fn resolve_field(&mut self, field: &FieldRef) -> Result<usize, &'static str> {
+        match Class::get_field_offset(field, &mut self.classloader) {
+            Ok(offset) => Ok(offset),
+            Err(ClassLoadingError::NoSuchFieldError(..)) => {
+                match Class::find_static_field(field, &mut self.classloader) {
+                    Ok(..) => Err(INCOMPATIBLE_CLASS_CHANGE_ERROR),
+                    Err(..) => Err(NO_SUCH_FIELD_ERROR),
+                }
+            }
+            Err(..) => Err(NO_CLASS_DEF_FOUND_ERROR),
+        }
+    }
 
-        // println!("Allocating object {:?}", object);
-        for i in 1..self.heap.len() {
-            if self.heap[i].is_none() {
-                self.heap[i] = Some(object);
-                return i as i32;
+    /// the class, which declares the static field, and the offset of the field in its static fields
+    /// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.getstatic
+    fn resolve_static_field(&mut self, field: &FieldRef) -> Result<(String, usize), &'static str> {
+        match Class::find_static_field(field, &mut self.classloader) {
+            Ok(f) => Ok(f),
+            Err(ClassLoadingError::NoSuchFieldError(..)) => {
+                match Class::get_field_offset(field, &mut self.classloader) {
+                    Ok(..) => Err(INCOMPATIBLE_CLASS_CHANGE_ERROR),
+                    Err(..) => Err(NO_SUCH_FIELD_ERROR),
+                }
             }
+            Err(..) => Err(NO_CLASS_DEF_FOUND_ERROR),
         }
-        self.heap.push(Some(object));
-        (self.heap.len() - 1) as i32
     }
-    fn get_object(heap: &mut Vec<Option<Object>>, index: i32) -> &mut Object {
-        heap[index as usize].as_mut().expect("Invalid Reference")
+
+    /// the id of the class of the object, the methods of arrays are the methods of Object
+    fn class_id_of(&mut self, object: i32) -> ClassId {
+        let class = match *VM::get_object(&mut self.heap, object) {
+            Object::Instance(ref instance) => {
+                if let Some(&id) = self.class_ids.get(instance.class()) {
+                    return id;
+                }
+                instance.class().to_owned()
+            }
+            Object::Array(..) => OBJECT_NAME.to_owned(),
+        };
+        self.class_id(&class)
     }
 
+    /// links the call site of an invokedynamic instruction in class, if this didn't happen yet
+    /// see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.invokedynamic
+    /// only LambdaMetafactory is supported as bootstrap method. It isn't called, but a class for the lambda
+    /// is synthesized. Instructions with the same constant share the call site.
+    /// returns the name of the lambda class, whose factory method creates the result of the call site
+    fn link_call_site(&mut self, class: &str, call_site: &InvokeDynamicRef) -> Result<String, &'static str> {
+        if let Some(lambda_class) = self.class_state(class).call_sites.get(call_site) {
+            return Ok(lambda_class.clone());
+        }
+
+        let name = format!("{}$$Lambda${}", class, self.class_state(class).call_sites.len() + 1);
+        let lambda_class = {
+            // the index was checked, when the class was loaded
+            let index = call_site.bootstrap_method() as usize;
+            let bootstrap = &self.classloader.load_class(class).unwrap().bootstrap_methods()[index];
+            match lambda::spin_lambda_class(&name, call_site, bootstrap) {
+                Ok(c) => c,
+                Err(_) => return Err(BOOTSTRAP_METHOD_ERROR),
+            }
+        };
+        // e.g. a class of the class path has the name of the lambda class
+        if self.classloader.define_class(lambda_class).is_err() {
+            return Err(BOOTSTRAP_METHOD_ERROR);
+        }
+        self.class_state(class).call_sites.insert(call_site.clone(), name.clone());
+        Ok(name)
+    }
+
+    /// makes sure, that size bytes can be allocated by the instruction, which is executed by frame
+    /// starts a garbage collection, if necessary. Returns the exception, if there is not enough memory left
+    /// or the collection fails. Collections only start here, so all references are in the frames
+    /// and not in local variables of the vm
+    fn reserve(&mut self, size: usize, frame: &Frame) -> Result<(), &'static str> {
+        if self.heap.needs_collection(size) {
+            self.collect_garbage(frame)?;
+        }
+        if self.heap.has_room(size) { Ok(()) } else { Err(OUT_OF_MEMORY_ERROR) }
+    }
+
+    /// frees all objects, which are not referenced by the interned strings, the class objects,
+    /// the static fields, the threads or the frames. frame is the currently executed frame
+    /// fails, if a class of the marked objects can't be loaded, nothing is freed then
+    fn collect_garbage(&mut self, frame: &Frame) -> Result<(), &'static str> {
+        let mut roots: Vec<i32> = self.strings.values().cloned().collect();
+        roots.extend(self.class_objects.values());
+        roots.extend(self.monitors.objects());
+        for thread in &self.threads {
+            roots.push(thread.object);
+            roots.extend(thread.uncaught_exception);
+            match thread.state {
+                ThreadState::Blocked { object, .. } |
+                ThreadState::Waiting { object, .. } => roots.push(object),
+                _ => {}
+            }
+        }
+        for state in &self.classes {
+            let class = self.classloader.load_class(&state.name).expect("the class was loaded before");
+            let mut offset = 0;
+            for field in class.static_fields() {
+                if field.is_reference() {
+                    roots.push(state.static_fields[offset]);
+                }
+                offset += field.size();
+            }
+        }
+        let frames = self.threads.iter().flat_map(|t| t.frames.iter().chain(t.frame.iter()));
+        for frame in self.frames.iter().chain(iter::once(frame)).chain(frames) {
+            roots.extend(frame.monitor);
+            if let Some(id) = frame.method {
+                if !self.reference_maps.contains_key(&id) {
+                    // the map is computed from the code of the class file, because the frame code may be quickened
+                    let map = {
+                        let method = &self.methods[id].reference;
+                        let class = self.classloader.load_class(method.class()).expect("the class was loaded before");
+                        let method = class.method_by_signature(method.name(), method.descriptor())
+                            .expect("the method was invoked before");
+                        ReferenceMap::new(method).ok()
+                    };
+                    self.reference_maps.insert(id, map);
+                }
+            }
+            let map = frame.method.as_ref().and_then(|m| self.reference_maps[m].as_ref());
+            frame.add_roots(map, &self.heap, &mut roots);
+        }
+        self.heap.collect(roots, &mut self.classloader).map_err(|_| NO_CLASS_DEF_FOUND_ERROR)
+    }
+
+    /// returns the interned string with the characters, see
+    /// https://docs.oracle.com/javase/specs/jvms/se6/html/ConstantPool.doc.html#67960
+    pub fn intern_string(&mut self, chars: &[u16]) -> i32 {
+        if let Some(string) = self.strings.get(chars) {
+            return *string;
+        }
+        let string = self.create_string(chars);
+        self.strings.insert(chars.to_vec(), string);
+        string
+    }
+
+    /// returns the unique instance of java/lang/Class, which represents the type, a primitive type too
+    /// the constructor is not run, the natives of the class library look up the type with class_type
+    pub fn class_object(&mut self, typ: &FieldDescriptor) -> i32 {
+        let object = self.class_object_by_descriptor(typ.to_string());
+        self.class_types.entry(object).or_insert_with(|| typ.clone());
+        object
+    }
+
+    /// returns the unique instance of java/lang/Class, which represents void, it has no type
+    pub fn void_class_object(&mut self) -> i32 { self.class_object_by_descriptor("V".to_owned()) }
+
+    fn class_object_by_descriptor(&mut self, descriptor: String) -> i32 {
+        if let Some(object) = self.class_objects.get(&descriptor) {
+            return *object;
+        }
+        let object = match Object::new_instance(CLASS_NAME, &mut self.classloader) {
+            Ok(o) => o,
+            Err(e) => panic!("Error loading class class: {}", e),
+        };
+        let object = self.heap.allocate(object);
+        self.class_objects.insert(descriptor, object);
+        object
+    }
+
+    /// the type, which is represented by the instance of java/lang/Class, None for void
+    pub fn class_type(&self, class_object: i32) -> Option<&FieldDescriptor> { self.class_types.get(&class_object) }
+
+    pub fn is_void_class(&self, class_object: i32) -> bool { self.class_objects.get("V") == Some(&class_object) }
+
+    /// the methods of the frames of the current thread from the innermost to the outermost one
+    /// the frames, which are generated by the vm, are left out
+    pub fn stack_methods(&self) -> Vec<MethodRef> {
+        self.frames.iter().rev().filter_map(|f| f.method.map(|id| self.methods[id].reference.clone())).collect()
+    }
+
+    pub fn get_field(&mut self, object: i32, field: &FieldRef) -> Result<i32, ClassLoadingError> {
+        VM::get_instance(&mut self.heap, object).get_field(field, &mut self.classloader)
+    }
+
+    pub fn set_field(&mut self, object: i32, field: &FieldRef, value: i32) -> Result<(), ClassLoadingError> {
+        VM::get_instance(&mut self.heap, object).set_field(field, value, &mut self.classloader)
+    }
+
+    /// sets a static field without initializing its class, e.g. the final fields of System
+    pub fn set_static_field(&mut self, field: &FieldRef, value: i32) -> Result<(), ClassLoadingError> {
+        let (class, offset) = Class::find_static_field(field, &mut self.classloader)?;
+        self.class_state(&class).static_fields[offset] = value;
+        Ok(())
+    }
+
+    /// the characters of the string object
+    pub fn string_chars(&mut self, string: i32) -> Vec<u16> {
+        let value = self.get_field(string, &FieldRef::new("value", STRING_NAME, "[C").unwrap())
+            .expect("String must have a field value");
+        let offset = self.get_field(string, &FieldRef::new("offset", STRING_NAME, "I").unwrap())
+            .expect("String must have a field offset");
+        let count = self.get_field(string, &FieldRef::new("count", STRING_NAME, "I").unwrap())
+            .expect("String must have a field count");
+        self.get_array(value).data()[offset as usize..(offset + count) as usize].iter().map(|&c| c as u16).collect()
+    }
+
+    /// creates a new string object, which is not interned
+    /// the constructor is not run, the fields are set directly
+    pub fn create_string(&mut self, chars: &[u16]) -> i32 {
+        let mut value = ArrayObject::new(chars.len() as i32, FieldDescriptor::from_type_without_reference(Char));
+        for (index, c) in chars.iter().enumerate() {
+            value.set(index as i32, *c as i32).expect("index is always in bounds");
+        }
+        let value = self.heap.allocate(Object::Array(value));
+
+        let mut string = match Object::new_instance(STRING_NAME, &mut self.classloader) {
+            Ok(s) => s,
+            Err(e) => panic!("Error loading string class: {}", e),
+        };
+        string.as_instance()
+            .set_field(&FieldRef::new("value", STRING_NAME, "[C").unwrap(),
+                       value,
+                       &mut self.classloader)
+            .expect("String must have a field value");
+        string.as_instance()
+            .set_field(&FieldRef::new("count", STRING_NAME, "I").unwrap(),
+                       chars.len() as i32,
+                       &mut self.classloader)
+            .expect("String must have a field count");
+        self.heap.allocate(string)
+    }
+
+    /// creates an exception object, which is thrown by the vm in frame or in the topmost of the frames
+    /// the constructor is not run, so the exception classes must not depend on it. Only the stack trace is set.
+    fn create_exception(&mut self, class: &str, frame: Option<&Frame>) -> i32 {
+        let exception = match Object::new_instance(class, &mut self.classloader) {
+            Ok(e) => e,
+            Err(e) => panic!("Error loading exception class {}: {}", class, e),
+        };
+        let exception = self.heap.allocate(exception);
+        let trace = self.create_stack_trace(frame, exception);
+        self.set_field(exception, &FieldRef::new("stackTrace", THROWABLE_NAME, STACK_TRACE_DESCRIPTOR).unwrap(), trace)
+            .expect("Throwable must have a field stackTrace");
+        exception
+    }
+
+    /// sets the stack trace of the throwable to the frames of the current thread, see Throwable.fillInStackTrace
+    pub fn fill_in_stack_trace(&mut self, throwable: i32) {
+        let trace = self.stack_trace(throwable);
+        self.set_field(throwable, &FieldRef::new("stackTrace", THROWABLE_NAME, STACK_TRACE_DESCRIPTOR).unwrap(), trace)
+            .expect("Throwable must have a field stackTrace");
+    }
+
+    /// creates an array of StackTraceElements for the frames of the current thread
+    pub fn stack_trace(&mut self, throwable: i32) -> i32 { self.create_stack_trace(None, throwable) }
+
+    /// creates an array of StackTraceElements for frame and the frames below it, frame is None during native calls
+    /// the frames of fillInStackTrace and the constructors of the throwable are left out like in HotSpot
+    fn create_stack_trace(&mut self, frame: Option<&Frame>, throwable: i32) -> i32 {
+        // each frame throws or invokes the next frame with its last instruction
+        let locations: Vec<(MethodRef, usize)> = frame.into_iter()
+            .chain(self.frames.iter().rev())
+            .filter_map(|f| f.method.map(|id| (self.methods[id].reference.clone(), f.ip.saturating_sub(1))))
+            .collect();
+        let typ = VM::get_object(&mut self.heap, throwable).typ().clone();
+        let mut skipped = 0;
+        for &(ref method, _) in &locations {
+            let constructs_throwable = method.name() == "<init>" &&
+                                       Class::is_instance_of(&typ,
+                                                             FieldDescriptor::from_class(method.class()),
+                                                             &mut self.classloader)
+                .unwrap_or(false);
+            if method.name() != "fillInStackTrace" && !constructs_throwable {
+                break;
+            }
+            skipped += 1;
+        }
+
+        let mut trace = ArrayObject::new((locations.len() - skipped) as i32,
+                                         FieldDescriptor::from_class(STACK_TRACE_ELEMENT_NAME));
+        for (position, (method, index)) in locations.into_iter().skip(skipped).enumerate() {
+            let (file, line) = {
+                let class = self.classloader.load_class(method.class()).expect("the class was loaded before");
+                let line = class.method_by_signature(method.name(), method.descriptor())
+                    .and_then(|m| m.code())
+                    .and_then(|c| c.line_number(index));
+                (class.source_file().cloned(), line)
+            };
+            let class_name = method.class().replace('/', ".").encode_utf16().collect::<Vec<u16>>();
+            let fields = [("declaringClass", "Ljava/lang/String;", self.intern_string(&class_name)),
+                          ("methodName",
+                           "Ljava/lang/String;",
+                           self.intern_string(&method.name().encode_utf16().collect::<Vec<u16>>())),
+                          ("fileName",
+                           "Ljava/lang/String;",
+                           file.map_or(0, |f| self.intern_string(&f.encode_utf16().collect::<Vec<u16>>()))),
+                          // -1 is an unknown line
+                          ("lineNumber", "I", line.map_or(-1, |l| l as i32))];
+            let mut element = match Object::new_instance(STACK_TRACE_ELEMENT_NAME, &mut self.classloader) {
+                Ok(e) => e,
+                Err(e) => panic!("Error loading stack trace element class: {}", e),
+            };
+            for &(name, descriptor, value) in &fields {
+                element.as_instance()
+                    .set_field(&FieldRef::new(name, STACK_TRACE_ELEMENT_NAME, descriptor).unwrap(),
+                               value,
+                               &mut self.classloader)
+                    .expect("StackTraceElement must have the field");
+            }
+            let element = self.heap.allocate(element);
+            trace.set(position as i32, element).expect("index is always in bounds");
+        }
+        self.heap.allocate(Object::Array(trace))
+    }
+
+    /// describes the exception like HotSpot, with its class, message and stack trace followed by its causes
+    /// the frames of a cause, which are the same as the last frames of the enclosing trace, are elided
+    fn exception_report(&mut self, exception: i32) -> String {
+        let cause_field = FieldRef::new("cause", THROWABLE_NAME, "Ljava/lang/Throwable;").unwrap();
+        let mut report = String::new();
+        let mut enclosing_trace: Vec<String> = Vec::new();
+        // the cause of a throwable without cause is the throwable itself, which ends the chain like null
+        let mut reported = Vec::new();
+        let mut throwable = exception;
+        while throwable != 0 && !reported.contains(&throwable) {
+            if !reported.is_empty() {
+                report.push_str("Caused by: ");
+            }
+            reported.push(throwable);
+            let description = self.describe_throwable(throwable);
+            report.push_str(&description);
+            let trace = self.stack_trace_lines(throwable);
+            let common = trace.iter().rev().zip(enclosing_trace.iter().rev()).take_while(|&(a, b)| a == b).count();
+            for line in &trace[..trace.len() - common] {
+                report.push_str(&format!("\tat {}\n", line));
+            }
+            if common > 0 {
+                report.push_str(&format!("\t... {} more\n", common));
+            }
+            enclosing_trace = trace;
+            throwable = self.get_field(throwable, &cause_field).unwrap_or(0);
+        }
+        report
+    }
+
+    /// the class and the message of the throwable
+    fn describe_throwable(&mut self, throwable: i32) -> String {
+        let class = VM::get_instance(&mut self.heap, throwable).class().replace('/', ".");
+        let message = self.get_field(throwable,
+                                     &FieldRef::new("detailMessage", THROWABLE_NAME, "Ljava/lang/String;").unwrap())
+            .unwrap_or(0);
+        if message == 0 {
+            format!("{}\n", class)
+        } else {
+            format!("{}: {}\n", class, String::from_utf16_lossy(&self.string_chars(message)))
+        }
+    }
+
+    /// the method and the location of each element of the stack trace of the throwable
+    fn stack_trace_lines(&mut self, throwable: i32) -> Vec<String> {
+        let mut trace = self.get_field(throwable,
+                                       &FieldRef::new("stackTrace", THROWABLE_NAME, STACK_TRACE_DESCRIPTOR).unwrap())
+            .unwrap_or(0);
+        // GNU Classpath keeps the stack trace in the VMThrowable, until it is requested
+        if trace == 0 {
+            let state = self.get_field(throwable,
+                                       &FieldRef::new("vmState", THROWABLE_NAME, "Ljava/lang/VMThrowable;").unwrap())
+                .unwrap_or(0);
+            if state != 0 {
+                let field = FieldRef::new("vmdata", "java/lang/VMThrowable", "Ljava/lang/Object;").unwrap();
+                trace = self.get_field(state, &field).unwrap_or(0);
+            }
+        }
+        if trace == 0 {
+            return Vec::new();
+        }
+        let string_field = |vm: &mut VM, element: i32, name: &str| -> Option<String> {
+            let field = FieldRef::new(name, STACK_TRACE_ELEMENT_NAME, "Ljava/lang/String;").unwrap();
+            match vm.get_field(element, &field).expect("StackTraceElement must have the field") {
+                0 => None,
+                string => Some(String::from_utf16_lossy(&vm.string_chars(string))),
+            }
+        };
+        let mut lines = Vec::new();
+        for element in self.get_array(trace).data().to_vec() {
+            let class = string_field(self, element, "declaringClass").unwrap_or_default();
+            let method = string_field(self, element, "methodName").unwrap_or_default();
+            let line = self.get_field(element, &FieldRef::new("lineNumber", STACK_TRACE_ELEMENT_NAME, "I").unwrap())
+                .expect("StackTraceElement must have a field lineNumber");
+            let location = match (string_field(self, element, "fileName"), line) {
+                (_, -2) => "Native Method".to_owned(),
+                (None, _) => "Unknown Source".to_owned(),
+                (Some(file), line) if line < 0 => file,
+                (Some(file), line) => format!("{}:{}", file, line),
+            };
+            lines.push(format!("{}.{}({})", class, method, location));
+        }
+        lines
+    }
+
+    fn get_object(heap: &mut Heap, index: i32) -> &mut Object { heap.get(index) }
+
     fn get_array(&mut self, index: i32) -> &mut ArrayObject { VM::get_object(&mut self.heap, index).as_array() }
 
-    fn get_instance(heap: &mut Vec<Option<Object>>, index: i32) -> &mut InstanceObject {
+    fn get_instance(heap: &mut Heap, index: i32) -> &mut InstanceObject {
         VM::get_object(heap, index).as_instance()
     }
 
-    fn run(&mut self, start_frame: Frame) {
-        self.frames.pop().expect("Expected dummy frame on frame stack");
+    /// searches the exception table of frame for a handler of the exception,
+    /// which was thrown by the last executed instruction
+    fn find_exception_handler(&mut self, frame: &Frame, exception: i32) -> Option<usize> {
+        // the frame didn't start executing yet (e.g. a pending class initialization)
+        if frame.ip == 0 {
+            return None;
+        }
+        let address = (frame.ip - 1) as CodeAddress;
+        for handler in frame.exception_table.iter().filter(|h| h.covers(address)) {
+            let matches = match handler.catch_type() {
+                None => true,
+                Some(catch_type) => {
+                    let obj = VM::get_object(&mut self.heap, exception);
+                    // TODO replace unwrap with exception throw
+                    Class::is_instance_of(obj.typ(),
+                                          FieldDescriptor::from_class(catch_type),
+                                          &mut self.classloader)
+                        .unwrap()
+                }
+            };
+            if matches {
+                return Some(handler.handler() as usize);
+            }
+        }
+        None
+    }
+
+    /// enters the monitor of the object count times or blocks the current thread, until it gets the monitor
+    fn enter_monitor(&mut self, object: i32, count: u32) {
+        if !self.monitors.enter(object, self.current_thread, count) {
+            self.threads[self.current_thread].state = ThreadState::Blocked {
+                object: object,
+                count: count,
+            };
+            self.quantum_left = 0;
+        }
+    }
+
+    /// releases the monitor of the object and lets the current thread wait, until it is notified
+    /// or the timeout in milliseconds passes. A timeout of 0 waits forever.
+    pub fn wait(&mut self, object: i32, timeout: i64) -> Result<(), &'static str> {
+        if timeout < 0 {
+            return Err(ILLEGAL_ARGUMENT_EXCEPTION);
+        }
+        // a thread notifies the threads waiting on its object, when it terminates, even if another thread holds
+        // the monitor. So the waiting thread wakes up spuriously, instead of missing the notification
+        if self.threads.iter().any(|t| t.object == object && t.state == ThreadState::Terminated) {
+            return if self.monitors.owns(object, self.current_thread) {
+                Ok(())
+            } else {
+                Err(ILLEGAL_MONITOR_STATE_EXCEPTION)
+            };
+        }
+        let count = match self.monitors.release(object, self.current_thread) {
+            Some(c) => c,
+            None => return Err(ILLEGAL_MONITOR_STATE_EXCEPTION),
+        };
+        self.threads[self.current_thread].state = ThreadState::Waiting {
+            object: object,
+            count: count,
+            deadline: if timeout == 0 { None } else { Some(Instant::now() + Duration::from_millis(timeout as u64)) },
+        };
+        self.quantum_left = 0;
+        Ok(())
+    }
+
+    /// wakes up one or all threads waiting on the object, whose monitor must be owned by the current thread
+    pub fn notify(&mut self, object: i32, all: bool) -> Result<(), &'static str> {
+        if !self.monitors.owns(object, self.current_thread) {
+            return Err(ILLEGAL_MONITOR_STATE_EXCEPTION);
+        }
+        self.wake_up(object, all);
+        Ok(())
+    }
+
+    fn wake_up(&mut self, object: i32, all: bool) {
+        for thread in &mut self.threads {
+            if let ThreadState::Waiting { object: o, count, .. } = thread.state {
+                if o == object {
+                    thread.state = ThreadState::Blocked {
+                        object: object,
+                        count: count,
+                    };
+                    if !all {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// lets the current thread sleep for the given milliseconds
+    pub fn sleep(&mut self, millis: i64) -> Result<(), &'static str> {
+        if millis < 0 {
+            return Err(ILLEGAL_ARGUMENT_EXCEPTION);
+        }
+        self.threads[self.current_thread].state =
+            ThreadState::Sleeping(Instant::now() + Duration::from_millis(millis as u64));
+        self.quantum_left = 0;
+        Ok(())
+    }
+
+    /// schedules the next thread before the next instruction
+    pub fn yield_thread(&mut self) { self.quantum_left = 0; }
+
+    /// creates a thread, which invokes the method without arguments on the receiver
+    /// object is the instance of java/lang/Thread, which represents the thread
+    pub fn start_thread(&mut self, object: i32, receiver: i32, method: MethodRef) {
+        let mut frame = Frame::synthetic_frame(vec![INVOKEVIRTUAL(method), RETURN(None)], 1, "");
+        frame.push(receiver);
+        self.threads.push(JavaThread::new(object, frame));
+    }
+
+    /// true, if the thread represented by the object was started and isn't terminated
+    pub fn is_alive(&self, object: i32) -> bool {
+        self.threads.iter().any(|t| t.object == object && t.state != ThreadState::Terminated)
+    }
+
+    /// the instance of java/lang/Thread, which represents the current thread
+    /// the object of the main thread is created, when it is requested the first time
+    pub fn current_thread_object(&mut self) -> i32 {
+        if self.threads[self.current_thread].object == 0 {
+            let object = match Object::new_instance(THREAD_NAME, &mut self.classloader) {
+                Ok(o) => o,
+                Err(e) => panic!("Error loading thread class: {}", e),
+            };
+            self.threads[self.current_thread].object = self.heap.allocate(object);
+        }
+        self.threads[self.current_thread].object
+    }
+
+    /// saves the frame of the current thread and returns the frame of the next thread
+    fn switch_thread(&mut self, frame: Frame) -> Result<Frame, Abort> {
+        let current = self.current_thread;
+        self.threads[current].frame = Some(frame);
+        mem::swap(&mut self.threads[current].frames, &mut self.frames);
+        if signals::dump_requested() {
+            let dump = self.thread_dump();
+            write!(&mut stderr(), "{}", dump).expect("stderr writing failed");
+        }
+        self.resume_next_thread()
+    }
+
+    /// terminates the current thread, whose first frame returned or threw the uncaught exception
+    /// returns the frame of the next thread, None if all threads are terminated
+    fn finish_thread(&mut self, exception: Option<i32>) -> Result<Option<Frame>, Abort> {
+        let current = self.current_thread;
+        self.threads[current].state = ThreadState::Terminated;
+        self.threads[current].uncaught_exception = exception;
+        if let Some(exception) = exception {
+            let name = if current == MAIN_THREAD {
+                "main".to_owned()
+            } else {
+                format!("Thread-{}", current - 1)
+            };
+            let report = self.exception_report(exception);
+            write!(&mut stderr(), "Exception in thread \"{}\" {}", name, report).expect("stderr writing failed");
+        }
+        // Thread.join waits on the object of the thread
+        let object = self.threads[current].object;
+        if object != 0 {
+            self.wake_up(object, true);
+        }
+        if self.threads.iter().all(|t| t.state == ThreadState::Terminated) {
+            return Ok(None);
+        }
+        self.resume_next_thread().map(Some)
+    }
+
+    /// makes the next thread, which can run, the current thread and returns its frame
+    /// waits, if all threads sleep, and fails after printing the thread dump, if they wait forever
+    fn resume_next_thread(&mut self) -> Result<Frame, Abort> {
+        let (next, quantum) = loop {
+            let now = Instant::now();
+            let mut runnable = Vec::new();
+            let mut next_deadline: Option<Instant> = None;
+            for (index, thread) in self.threads.iter_mut().enumerate() {
+                thread.state = match thread.state {
+                    ThreadState::Waiting { object, count, deadline: Some(deadline) } if deadline <= now => {
+                        ThreadState::Blocked {
+                            object: object,
+                            count: count,
+                        }
+                    }
+                    ThreadState::Sleeping(deadline) if deadline <= now => ThreadState::Runnable,
+                    state => state,
+                };
+                match thread.state {
+                    ThreadState::Runnable => runnable.push(index),
+                    ThreadState::Blocked { object, count } => {
+                        if self.monitors.enter(object, index, count) {
+                            thread.state = ThreadState::Runnable;
+                            runnable.push(index);
+                        }
+                    }
+                    ThreadState::WaitingForClass(class) => {
+                        match self.classes[class].initialization {
+                            Initialization::BeingInitialized(..) => {}
+                            _ => {
+                                thread.state = ThreadState::Runnable;
+                                runnable.push(index);
+                            }
+                        }
+                    }
+                    ThreadState::Waiting { deadline: Some(deadline), .. } |
+                    ThreadState::Sleeping(deadline) => {
+                        next_deadline = Some(next_deadline.map_or(deadline, |d| min(d, deadline)));
+                    }
+                    ThreadState::Waiting { deadline: None, .. } |
+                    ThreadState::Terminated => {}
+                }
+            }
+            if !runnable.is_empty() {
+                break self.scheduler.next(self.current_thread, &runnable);
+            }
+            match next_deadline {
+                Some(deadline) => ::std::thread::sleep(deadline - now),
+                None => {
+                    let dump = self.thread_dump();
+                    write!(&mut stderr(), "{}", dump).expect("stderr writing failed");
+                    return Err(Abort::Deadlock);
+                }
+            }
+        };
+        self.current_thread = next;
+        self.quantum_left = quantum;
+        mem::swap(&mut self.threads[next].frames, &mut self.frames);
+        Ok(self.threads[next].frame.take().expect("the thread has a frame"))
+    }
+
+    /// describes the state and the frames of all threads, which aren't running, like a thread dump of kill -3
+    /// followed by the deadlocks. The frames are listed from the innermost to the outermost one.
+    fn thread_dump(&mut self) -> String {
+        let mut dump = "Full thread dump\n".to_owned();
+        for index in 0..self.threads.len() {
+            let state = match self.threads[index].state {
+                ThreadState::Runnable => "runnable".to_owned(),
+                ThreadState::Blocked { object, .. } => format!("blocked on {}", self.describe_object(object)),
+                ThreadState::Waiting { object, deadline: None, .. } => {
+                    format!("waiting on {}", self.describe_object(object))
+                }
+                ThreadState::Waiting { object, deadline: Some(..), .. } => {
+                    format!("timed waiting on {}", self.describe_object(object))
+                }
+                ThreadState::Sleeping(..) => "sleeping".to_owned(),
+                ThreadState::WaitingForClass(class) => {
+                    format!("waiting for the initialization of {}", self.classes[class].name.replace('/', "."))
+                }
+                ThreadState::Terminated => "terminated".to_owned(),
+            };
+            let name = if index == MAIN_THREAD { " (main)" } else { "" };
+            dump.push_str(&format!("\nthread {}{}: {}\n", index, name, state));
+            let mut lines = Vec::new();
+            {
+                let thread = &self.threads[index];
+                for (depth, frame) in thread.frame.iter().chain(thread.frames.iter().rev()).enumerate() {
+                    // the innermost frame of a runnable thread didn't execute its next instruction yet,
+                    // neither did the one of a thread waiting for a class initialization, which retries it.
+                    // The other frames blocked or invoked a method with their last instruction
+                    let index = match thread.state {
+                        ThreadState::Runnable | ThreadState::WaitingForClass(..) if depth == 0 => frame.ip,
+                        _ => frame.ip.saturating_sub(1),
+                    };
+                    lines.push((frame.method.map(|id| self.methods[id].reference.clone()), index));
+                }
+            }
+            for (method, index) in lines {
+                let line = match method {
+                    Some(method) => {
+                        let class = self.classloader.load_class(method.class()).expect("the class was loaded before");
+                        let code = class.method_by_signature(method.name(), method.descriptor())
+                            .and_then(|m| m.code());
+                        let offset = code.map_or(index, |c| c.byte_offset(index));
+                        format!("    at {}.{}{} (bytecode index {})\n",
+                                method.class().replace('/', "."),
+                                method.name(),
+                                method.descriptor(),
+                                offset)
+                    }
+                    None => "    at <generated by the vm>\n".to_owned(),
+                };
+                dump.push_str(&line);
+            }
+        }
+        for deadlock in self.find_deadlocks() {
+            dump.push_str(&format!("\nFound a deadlock of {} threads:\n", deadlock.len()));
+            for thread in deadlock {
+                match self.threads[thread].state {
+                    ThreadState::Blocked { object, .. } => {
+                        let owner = self.monitors.owner(object).expect("the monitor is locked");
+                        dump.push_str(&format!("thread {} waits for the monitor of {} held by thread {}\n",
+                                               thread,
+                                               self.describe_object(object),
+                                               owner));
+                    }
+                    ThreadState::WaitingForClass(class) => {
+                        if let Initialization::BeingInitialized(owner) = self.classes[class].initialization {
+                            dump.push_str(&format!("thread {} waits for the initialization of {} by thread {}\n",
+                                                   thread,
+                                                   self.classes[class].name.replace('/', "."),
+                                                   owner));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        dump
+    }
+
+    /// the cycles in the wait-for graph, whose edges lead from the blocked threads to the owners of the monitors
+    /// and from the threads waiting for a class to its initializing thread
+    /// each cycle is listed once, starting with its lowest thread
+    fn find_deadlocks(&self) -> Vec<Vec<usize>> {
+        let waits_for = |thread: usize| match self.threads[thread].state {
+            ThreadState::Blocked { object, .. } => self.monitors.owner(object),
+            ThreadState::WaitingForClass(class) => {
+                match self.classes[class].initialization {
+                    Initialization::BeingInitialized(owner) => Some(owner),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let mut deadlocks = Vec::new();
+        for start in 0..self.threads.len() {
+            // a blocked thread waits for at most one thread, so the cycle is found by following the edges
+            let mut cycle = vec![start];
+            let mut thread = start;
+            while let Some(next) = waits_for(thread) {
+                if next == start {
+                    deadlocks.push(cycle);
+                    break;
+                }
+                // the cycle doesn't contain start or starts with a lower thread
+                if next < start || cycle.contains(&next) {
+                    break;
+                }
+                cycle.push(next);
+                thread = next;
+            }
+        }
+        deadlocks
+    }
+
+    /// the type and the reference of an object, e.g. java.lang.Object@3
+    fn describe_object(&mut self, object: i32) -> String {
+        let typ = VM::get_object(&mut self.heap, object).typ().to_symbolic_reference();
+        format!("{}@{}", typ.replace('/', "."), object)
+    }
+
+    /// executes until all threads are terminated
+    /// returns the exception as error, if it isn't caught by any frame of the main thread
+    fn run(&mut self, start_frame: Frame) -> Result<(), Abort> {
         assert_eq!(self.frames.len(), 0);
-        let mut frame = start_frame;
+        assert!(self.threads.is_empty());
+        self.threads.push(JavaThread::new(0, start_frame));
+        let mut frame = self.resume_next_thread()?;
+
+        // the result of the main thread is returned, after all threads are terminated
+        macro_rules! finish_thread(($exception: expr) => {{
+            match self.finish_thread($exception)? {
+                Some(next_frame) => frame = next_frame,
+                None => {
+                    return match self.threads[MAIN_THREAD].uncaught_exception {
+                        Some(exception) => Err(Abort::UncaughtException(exception)),
+                        None => Ok(()),
+                    }
+                }
+            }
+        }});
+
+        // unwinds the frames until a handler for the exception is found
+        // see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc.html#athrow
+        macro_rules! throw(($exception: expr) => {{
+            let mut exception = $exception;
+            loop {
+                if let Some(handler) = self.find_exception_handler(&frame, exception) {
+                    frame.sp = 0;
+                    frame.push(exception);
+                    frame.ip = handler;
+                    break;
+                }
+                if frame.initializes_class {
+                    exception = self.initialization_failed(&frame.current_class, exception);
+                }
+                if let Some(object) = frame.monitor.take() {
+                    self.monitors.exit(object, self.current_thread);
+                }
+                if self.frames.is_empty() {
+                    finish_thread!(Some(exception));
+                    break;
+                }
+                frame = self.frames.pop().unwrap();
+            }
+        }});
+        macro_rules! throw_new(($class: expr) => {{
+            let exception = self.create_exception($class, Some(&frame));
+            throw!(exception);
+        }});
+        // the exceptions of native methods are thrown in the calling frame
+        macro_rules! invoke(($class: expr, $name: expr, $descriptor: expr) => {{
+            if let Err(error) = self.invoke_method($class, $name, $descriptor, &mut frame) {
+                throw_new!(error);
+            }
+        }});
+        macro_rules! invoke_linked(($method: expr) => {{
+            if let Err(error) = self.invoke_linked_method($method, &mut frame) {
+                throw_new!(error);
+            }
+        }});
+        // must be called before any operands are popped, because the instruction
+        // may be executed again after the initialization
+        macro_rules! initialize(($class: expr) => {{
+            match self.initialize_class($class, &mut frame) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(exception) => {
+                    throw!(exception);
+                    continue;
+                }
+            }
+        }});
 
         macro_rules! arith_int(($typ: ident, $op:ident) => {{
             match $typ {
@@ -193,32 +1449,70 @@ impl VM {
 
 
         loop {
-            match frame.next_instruction() {
+            if self.quantum_left == 0 {
+                frame = self.switch_thread(frame)?;
+            }
+            self.quantum_left -= 1;
+
+            // the instruction is matched in the shared code, which is only changed between instructions
+            if let Some((ip, instruction)) = frame.quickened.take() {
+                frame.code.borrow_mut()[ip] = instruction;
+            }
+            let code = frame.code.clone();
+            let code = code.borrow();
+            frame.ip += 1;
+            match code[frame.ip - 1] {
                 ASTORE(typ) => {
+                    let stored;
                     if typ.is_double_sized() {
                         let val = frame.pop2();
                         let index = frame.pop();
                         let array = frame.pop();
-                        // TODO throw nullpointer exception
-
-                        self.get_array(array).set2(index, val);
+                        stored = if array == 0 { None } else { Some(self.get_array(array).set2(index, val)) };
                     } else {
                         let val = frame.pop();
                         let index = frame.pop();
                         let array = frame.pop();
-                        // TODO throw nullpointer exception
-
-                        self.get_array(array).set(index, val);
+                        // arrays are covariant, so the class of a stored reference is checked against the component
+                        // type, see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.aastore
+                        if typ == Reference && array != 0 && val != 0 && index >= 0 &&
+                           index < self.get_array(array).length() {
+                            let mut component = self.get_array(array).typ().clone();
+                            component.remove_array();
+                            let is_instance = {
+                                let obj = VM::get_object(&mut self.heap, val);
+                                Class::is_instance_of(obj.typ(), component, &mut self.classloader).unwrap()
+                            };
+                            if !is_instance {
+                                throw_new!(ARRAY_STORE_EXCEPTION);
+                                continue;
+                            }
+                        }
+                        stored = if array == 0 { None } else { Some(self.get_array(array).set(index, val)) };
+                    }
+                    match stored {
+                        None => throw_new!(NULL_POINTER_EXCEPTION),
+                        Some(None) => throw_new!(ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION),
+                        Some(Some(())) => {}
                     }
                 }
                 ALOAD(typ) => {
                     let index = frame.pop();
                     let array = frame.pop();
-                    // TODO throw nullpointer exception
-                    if typ.is_double_sized() {
-                        frame.push2(self.get_array(array).get2(index));
+                    if array == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                    } else if typ.is_double_sized() {
+                        let value = self.get_array(array).get2(index);
+                        match value {
+                            Some(v) => frame.push2(v),
+                            None => throw_new!(ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION),
+                        }
                     } else {
-                        frame.push(self.get_array(array).get(index));
+                        let value = self.get_array(array).get(index);
+                        match value {
+                            Some(v) => frame.push(v),
+                            None => throw_new!(ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION),
+                        }
                     }
                 }
 
@@ -244,23 +1538,28 @@ impl VM {
 
                 ARRAYLENGTH => {
                     let array = frame.pop();
-                    frame.push(self.get_array(array).length());
+                    if array == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                    } else {
+                        frame.push(self.get_array(array).length());
+                    }
                 }
 
-                CHECKCAST(dest) => {
+                CHECKCAST(ref dest) => {
                     let objindex = frame.top();
                     // nullpointer is always ok
-                    if objindex != 0 {
+                    let is_instance = objindex == 0 || {
                         let obj = VM::get_object(&mut self.heap, objindex);
-                        if !Class::is_instance_of(obj.typ(),
-                                                  FieldDescriptor::from_symbolic_reference(&dest).unwrap(),
-                                                  &mut self.classloader)
-                            .unwrap() {
-                            panic!("TODO ClassCastException!");
-                        }
+                        Class::is_instance_of(obj.typ(),
+                                              FieldDescriptor::from_symbolic_reference(dest).unwrap(),
+                                              &mut self.classloader)
+                            .unwrap()
+                    };
+                    if !is_instance {
+                        throw_new!(CLASS_CAST_EXCEPTION);
                     }
                 }
-                INSTANCEOF(dest) => {
+                INSTANCEOF(ref dest) => {
                     let objindex = frame.pop();
                     // nullpointer is always false
                     frame.push(if objindex == 0 {
@@ -268,19 +1567,24 @@ impl VM {
                     } else {
                         let obj = VM::get_object(&mut self.heap, objindex);
                         Class::is_instance_of(obj.typ(),
-                                              FieldDescriptor::from_symbolic_reference(&dest).unwrap(),
+                                              FieldDescriptor::from_symbolic_reference(dest).unwrap(),
                                               &mut self.classloader)
                             .unwrap() as i32
                     });
                 }
 
-                ANEWARRAY(class) => {
+                ANEWARRAY(ref class) => {
                     let length = frame.pop();
-                    // TODO throw NegativeArraySizeException exception
-                    frame.push(self.allocate_object(Object::new_array(length,
-                                                           FieldDescriptor::from_symbolic_reference(&class).unwrap())));
+                    if length < 0 {
+                        throw_new!(NEGATIVE_ARRAY_SIZE_EXCEPTION);
+                    } else if let Err(exception) = self.reserve(Heap::array_size(length as usize, 1), &frame) {
+                        throw_new!(exception);
+                    } else {
+                        frame.push(self.heap.allocate(Object::new_array(length,
+                                                           FieldDescriptor::from_symbolic_reference(class).unwrap())));
+                    }
                 }
-                MULTIANEWARRAY(descriptor, count) => {
+                MULTIANEWARRAY(ref descriptor, count) => {
                     fn create_array(depth: usize,
                                     count: usize,
                                     mut desc: FieldDescriptor,
@@ -288,40 +1592,72 @@ impl VM {
                                     vm: &mut VM)
                                     -> i32 {
                         let len = frame.nth_from_top(count - depth);
-                        // TODO throw NegativeArraySizeException exception
 
                         desc.remove_array();
                         let mut array = ArrayObject::new(len, desc.clone());
 
                         if depth < count {
                             for i in 0..len {
-                                array.set(i, create_array(depth + 1, count, desc.clone(), frame, vm));
+                                let created = create_array(depth + 1, count, desc.clone(), frame, vm);
+                                array.set(i, created).expect("index is always in bounds");
                             }
                         }
-                        vm.allocate_object(Object::Array(array))
+                        vm.heap.allocate(Object::Array(array))
+                    }
+
+                    // all dimensions must be checked before anything is allocated
+                    if (0..count as usize).any(|i| frame.nth_from_top(i) < 0) {
+                        frame.sp -= count as usize;
+                        throw_new!(NEGATIVE_ARRAY_SIZE_EXCEPTION);
+                        continue;
+                    }
+
+                    let mut desc = FieldDescriptor::parse(descriptor).unwrap();
+                    let mut size: usize = 0;
+                    let mut arrays: usize = 1;
+                    for depth in 1..count as usize + 1 {
+                        let length = frame.nth_from_top(count as usize - depth) as usize;
+                        desc.remove_array();
+                        size = size.saturating_add(arrays.saturating_mul(Heap::array_size(length, desc.word_size())));
+                        arrays = arrays.saturating_mul(length);
+                    }
+                    if let Err(exception) = self.reserve(size, &frame) {
+                        frame.sp -= count as usize;
+                        throw_new!(exception);
+                        continue;
                     }
 
                     let created = create_array(1,
                                                count as usize,
-                                               FieldDescriptor::parse(&descriptor).unwrap(),
+                                               FieldDescriptor::parse(descriptor).unwrap(),
                                                &mut frame,
                                                self);
                     frame.sp -= count as usize;
                     frame.push(created);
                 }
-                NEW(class) => {
-                    let instance = match Object::new_instance(&class, &mut self.classloader) {
+                NEW(ref class) => {
+                    initialize!(&class);
+                    let instance = match Object::new_instance(class, &mut self.classloader) {
                         Ok(i) => i,
                         // TODO throw class laoding exception
                         Err(e) => panic!("Error loading class {}: {}", class, e),
                     };
-                    frame.push(self.allocate_object(instance));
+                    match self.reserve(Heap::object_size(&instance), &frame) {
+                        Ok(()) => frame.push(self.heap.allocate(instance)),
+                        Err(exception) => throw_new!(exception),
+                    }
                 }
                 NEWARRAY(t) => {
                     let length = frame.pop();
-                    // TODO throw NegativeArraySizeException exception
-                    frame.push(self.allocate_object(Object::new_array(length,
-                                                                     FieldDescriptor::from_type_without_reference(t))));
+                    if length < 0 {
+                        throw_new!(NEGATIVE_ARRAY_SIZE_EXCEPTION);
+                    } else if let Err(exception) =
+                               self.reserve(Heap::array_size(length as usize, t.word_size()), &frame) {
+                        throw_new!(exception);
+                    } else {
+                        frame.push(self.heap.allocate(Object::new_array(length,
+                                                                   FieldDescriptor::from_type_without_reference(t))));
+                    }
                 }
 
                 CONVERT(Int, Byte) => {
@@ -356,10 +1692,21 @@ impl VM {
                 SUB(t) => arith_float!(t, sub),
                 MUL(t @ Int) | MUL(t @ Long) => arith_int!(t, wrapping_mul),
                 MUL(t) => arith_float!(t, mul),
-                // TODO arithmetic exception
-                DIV(t @ Int) | DIV(t @ Long) => arith_int!(t, wrapping_div),
+                DIV(t @ Int) | DIV(t @ Long) => {
+                    if frame.top_is_zero(t) {
+                        throw_new!(ARITHMETIC_EXCEPTION);
+                    } else {
+                        arith_int!(t, wrapping_div)
+                    }
+                }
                 DIV(t) => arith_float!(t, div),
-                REM(t @ Int) | REM(t @ Long) => arith_int!(t, wrapping_rem),
+                REM(t @ Int) | REM(t @ Long) => {
+                    if frame.top_is_zero(t) {
+                        throw_new!(ARITHMETIC_EXCEPTION);
+                    } else {
+                        arith_int!(t, wrapping_rem)
+                    }
+                }
                 REM(t) => arith_float!(t, rem),
                 AND(t) => arith_int!(t, bitand),
                 OR(t) => arith_int!(t, bitor),
@@ -431,11 +1778,26 @@ impl VM {
                     }
                 }
                 RETURN(o) => {
+                    if frame.initializes_class {
+                        self.class_state(&frame.current_class).initialization = Initialization::Initialized;
+                    }
+                    if let Some(object) = frame.monitor.take() {
+                        if !self.monitors.exit(object, self.current_thread) {
+                            throw_new!(ILLEGAL_MONITOR_STATE_EXCEPTION);
+                            continue;
+                        }
+                    }
                     if self.frames.is_empty() {
-                        return;
+                        finish_thread!(None);
+                        continue;
                     }
                     let mut old_frame = frame;
                     frame = self.frames.pop().unwrap();
+                    // the instruction, which triggered the initialization, is executed again
+                    // a frame at ip 0 is the pending initialization of a subclass
+                    if old_frame.initializes_class && frame.ip > 0 {
+                        frame.ip -= 1;
+                    }
 
                     if let Some(typ) = o {
                         if typ.is_double_sized() {
@@ -465,7 +1827,15 @@ impl VM {
                 SIPUSH(i) => frame.push(i as i32),
                 LDC_INT(i) => frame.push(i),
                 LDC_FLOAT(f) => frame.push(conv!(f)),
-                // TODO LDC_STRING(String) => frame.push(),
+                LDC_STRING(ref s) => {
+                    let string = self.intern_string(s);
+                    frame.push(string);
+                }
+                LDC_CLASS(ref name) => {
+                    let class = self.class_object(&FieldDescriptor::from_symbolic_reference(name).unwrap());
+                    frame.push(class);
+                }
+                LDC_METHOD_TYPE(..) | LDC_METHOD_HANDLE(..) => panic!("Operation LDC is rejected by the verifier"),
                 LDC_DOUBLE(f) => frame.push2(conv!(f)),
                 LDC_LONG(i) => frame.push2(conv!(i)),
 
@@ -473,41 +1843,167 @@ impl VM {
                     let val = frame.top();
                     frame.push(val);
                 }
+                // the following instructions only work on words, so long and double values are
+                // handled by the same code as two category 1 values
+                // see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc3.html
+                DUP_X1 => {
+                    let v1 = frame.pop();
+                    let v2 = frame.pop();
+                    frame.push(v1);
+                    frame.push(v2);
+                    frame.push(v1);
+                }
+                DUP_X2 => {
+                    let v1 = frame.pop();
+                    let v2 = frame.pop2();
+                    frame.push(v1);
+                    frame.push2(v2);
+                    frame.push(v1);
+                }
+                DUP2 => {
+                    let v1 = frame.pop2();
+                    frame.push2(v1);
+                    frame.push2(v1);
+                }
+                DUP2_X1 => {
+                    let v1 = frame.pop2();
+                    let v2 = frame.pop();
+                    frame.push2(v1);
+                    frame.push(v2);
+                    frame.push2(v1);
+                }
+                DUP2_X2 => {
+                    let v1 = frame.pop2();
+                    let v2 = frame.pop2();
+                    frame.push2(v1);
+                    frame.push2(v2);
+                    frame.push2(v1);
+                }
                 POP => {
                     frame.pop();
                 }
+                POP2 => {
+                    frame.pop2();
+                }
+                SWAP => {
+                    let v1 = frame.pop();
+                    let v2 = frame.pop();
+                    frame.push(v1);
+                    frame.push(v2);
+                }
 
-                GETFIELD(field) => {
+                // the instructions with symbolic references are quickened and executed again
+                GETFIELD(ref field) => {
+                    match self.resolve_field(field) {
+                        Ok(offset) => {
+                            frame.quicken(GETFIELD_QUICK(offset, field.typ().is_double_sized()));
+                            frame.ip -= 1;
+                        }
+                        Err(exception) => throw_new!(exception),
+                    }
+                }
+                PUTFIELD(ref field) => {
+                    match self.resolve_field(field) {
+                        Ok(offset) => {
+                            frame.quicken(PUTFIELD_QUICK(offset, field.typ().is_double_sized()));
+                            frame.ip -= 1;
+                        }
+                        Err(exception) => throw_new!(exception),
+                    }
+                }
+                GETFIELD_QUICK(offset, double_sized) => {
                     let objindex = frame.pop();
-                    // TODO throw NullPointerException
-                    let obj = VM::get_instance(&mut self.heap, objindex);
-                    if field.typ().is_double_sized() {
-                        // TODO replace unwrap with exception throw
-                        frame.push2(obj.get_field2(&field, &mut self.classloader).unwrap());
+                    if objindex == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    let data = VM::get_instance(&mut self.heap, objindex).data();
+                    if double_sized {
+                        frame.push2([data[offset], data[offset + 1]]);
+                    } else {
+                        frame.push(data[offset]);
+                    }
+                }
+                PUTFIELD_QUICK(offset, double_sized) => {
+                    let value = if double_sized { frame.pop2() } else { [frame.pop(), 0] };
+                    let objindex = frame.pop();
+                    if objindex == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    let data = VM::get_instance(&mut self.heap, objindex).data_mut();
+                    data[offset] = value[0];
+                    if double_sized {
+                        data[offset + 1] = value[1];
+                    }
+                }
+
+                // static fields are quickened after the initialization of their class, because the quick
+                // instructions don't check it
+                GETSTATIC(ref field) => {
+                    let (class, offset) = match self.resolve_static_field(field) {
+                        Ok(f) => f,
+                        Err(exception) => {
+                            throw_new!(exception);
+                            continue;
+                        }
+                    };
+                    initialize!(&class);
+                    let id = self.class_id(&class);
+                    let double_sized = field.typ().is_double_sized();
+                    if self.classes[id].initialization == Initialization::Initialized {
+                        frame.quicken(GETSTATIC_QUICK(id, offset, double_sized));
+                    }
+                    let statics = &self.classes[id].static_fields;
+                    if double_sized {
+                        frame.push2([statics[offset], statics[offset + 1]]);
                     } else {
-                        // TODO replace unwrap with exception throw
-                        frame.push(obj.get_field(&field, &mut self.classloader).unwrap());
+                        frame.push(statics[offset]);
                     }
                 }
-                PUTFIELD(field) => {
-                    if field.typ().is_double_sized() {
+                PUTSTATIC(ref field) => {
+                    let (class, offset) = match self.resolve_static_field(field) {
+                        Ok(f) => f,
+                        Err(exception) => {
+                            throw_new!(exception);
+                            continue;
+                        }
+                    };
+                    initialize!(&class);
+                    let id = self.class_id(&class);
+                    let double_sized = field.typ().is_double_sized();
+                    if self.classes[id].initialization == Initialization::Initialized {
+                        frame.quicken(PUTSTATIC_QUICK(id, offset, double_sized));
+                    }
+                    let statics = &mut self.classes[id].static_fields;
+                    if double_sized {
                         let value = frame.pop2();
-                        let objindex = frame.pop();
-                        // TODO throw NullPointerException
-                        let obj = VM::get_instance(&mut self.heap, objindex);
-                        // TODO replace unwrap with exception throw
-                        obj.set_field2(&field, value, &mut self.classloader).unwrap();
+                        statics[offset] = value[0];
+                        statics[offset + 1] = value[1];
+                    } else {
+                        statics[offset] = frame.pop();
+                    }
+                }
+                GETSTATIC_QUICK(class, offset, double_sized) => {
+                    let statics = &self.classes[class].static_fields;
+                    if double_sized {
+                        frame.push2([statics[offset], statics[offset + 1]]);
                     } else {
-                        let value = frame.pop();
-                        let objindex = frame.pop();
-                        // TODO throw NullPointerException
-                        let obj = VM::get_instance(&mut self.heap, objindex);
-                        // TODO replace unwrap with exception throw
-                        obj.set_field(&field, value, &mut self.classloader).unwrap();
+                        frame.push(statics[offset]);
+                    }
+                }
+                PUTSTATIC_QUICK(class, offset, double_sized) => {
+                    let statics = &mut self.classes[class].static_fields;
+                    if double_sized {
+                        let value = frame.pop2();
+                        statics[offset] = value[0];
+                        statics[offset + 1] = value[1];
+                    } else {
+                        statics[offset] = frame.pop();
                     }
                 }
 
-                i @ DCMPG | i @ DCMPL => {
+                ref i @ DCMPG | ref i @ DCMPL => {
                     let b: f64 = conv!(frame.pop2());
                     let a: f64 = conv!(frame.pop2());
                     frame.push(if a == b {
@@ -518,10 +2014,10 @@ impl VM {
                         1
                     } else {
                         // one is NaN
-                        if i == DCMPG { 1 } else { 0 }
+                        if *i == DCMPG { 1 } else { 0 }
                     });
                 }
-                i @ FCMPG | i @ FCMPL => {
+                ref i @ FCMPG | ref i @ FCMPL => {
                     let b: f32 = conv!(frame.pop());
                     let a: f32 = conv!(frame.pop());
                     frame.push(if a == b {
@@ -532,7 +2028,7 @@ impl VM {
                         1
                     } else {
                         // one is NaN
-                        if i == FCMPG { 1 } else { 0 }
+                        if *i == FCMPG { 1 } else { 0 }
                     });
                 }
                 LCMP => {
@@ -548,6 +2044,14 @@ impl VM {
                 }
 
                 GOTO(dest) => frame.ip = dest as usize,
+                // see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc7.html#jsr
+                JSR(dest) => {
+                    let return_address = frame.ip;
+                    frame.push(return_address as i32);
+                    frame.ip = dest as usize;
+                }
+                // the verifier tracks the return addresses by their type, so the local variable holds one
+                RET(idx) => frame.ip = frame.load(idx) as usize,
                 IF_ACMP(equal, dest) => {
                     let b = frame.pop();
                     let a = frame.pop();
@@ -574,52 +2078,272 @@ impl VM {
                         frame.ip = dest as usize;
                     }
                 }
+                TABLESWITCH(default, low, high, ref addresses) => {
+                    let index = frame.pop();
+                    frame.ip = if index < low || index > high {
+                        default
+                    } else {
+                        addresses[(index as i64 - low as i64) as usize]
+                    } as usize;
+                }
+                LOOKUPSWITCH(default, _, ref pairs) => {
+                    let key = frame.pop();
+                    // the pairs are sorted by the match, see Instruction::decode
+                    frame.ip = match pairs.binary_search_by_key(&key, |&(m, _)| m) {
+                        Ok(i) => pairs[i].1,
+                        Err(_) => default,
+                    } as usize;
+                }
 
-                INVOKESPECIAL(method) => {
+                INVOKESPECIAL(ref method) => {
+                    let object_offset = MethodDescriptor::parse(method.descriptor()).unwrap().words_for_params();
+                    if frame.nth_from_top(object_offset) == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
                     // special lookup procedure for invoke special
-                    // see https://docs.oracle.com/javase/specs/jvms/se6/html/Instructions2.doc6.html
+                    // see https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.invokespecial
+                    // the lookup starts at the direct superclass or at the referenced class or interface,
+                    // which is used for Interface.super.method() calls
                     // TODO replace unwraps with throw class loading exception
-                    if self.classloader.load_class(&frame.current_class).unwrap().has_acc_super_flag() &&
-                       method.name() != "<init>" &&
-                       Class::is_real_super_class(method.class(), &frame.current_class, &mut self.classloader)
+                    let class = if self.classloader.load_class(&frame.current_class).unwrap().has_acc_super_flag() &&
+                                   method.name() != "<init>" &&
+                                   Class::is_real_super_class(method.class(),
+                                                              &frame.current_class,
+                                                              &mut self.classloader)
                         .unwrap() {
-                        let dest_class = Class::find_first_real_super_class_with_method(&frame.current_class,
-                                                                                        method.name(),
-                                                                                        method.descriptor(),
-                                                                                        &mut self.classloader)
-                            .unwrap()
-                            .unwrap();
-                        self.invoke_method(&dest_class, method.name(), method.descriptor(), &mut frame);
+                        self.classloader.load_class(&frame.current_class).unwrap().super_class().unwrap().clone()
                     } else {
-                        self.invoke_method_ref(&method, &mut frame);
+                        method.class().to_owned()
+                    };
+                    // the selected method depends only on the current class, so it is the same for all objects
+                    match self.select_method(&class, method) {
+                        Ok(dest_class) => {
+                            let id = self.link_method(&dest_class, method.name(), method.descriptor());
+                            frame.quicken(INVOKESPECIAL_QUICK(id));
+                            invoke_linked!(id);
+                        }
+                        Err(error) => throw_new!(error),
+                    }
+                }
+                INVOKESPECIAL_QUICK(method) => {
+                    if frame.nth_from_top(self.methods[method].words_for_params - 1) == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    invoke_linked!(method);
+                }
+                INVOKEVIRTUAL(ref method) => {
+                    let object_offset = MethodDescriptor::parse(method.descriptor()).unwrap().words_for_params();
+                    // the resolved method is declared by the referenced class or one of its superclasses,
+                    // its index in the vtable is the same in all subclasses. Arrays have the methods of Object.
+                    // private methods aren't selected, default methods of superinterfaces aren't quickened
+                    let referenced = if method.class().starts_with('[') { OBJECT_NAME } else { method.class() };
+                    // TODO replace unwraps with throw class loading exception
+                    if let Some(class) = Class::find_first_super_class_with_method(referenced,
+                                                                                   method.name(),
+                                                                                   method.descriptor(),
+                                                                                   &mut self.classloader)
+                        .unwrap() {
+                        let (index, is_private) = {
+                            let loaded = self.classloader.load_class(&class).unwrap();
+                            (loaded.vtable_index(&MethodRef::new(method.name(), &class, method.descriptor())),
+                             loaded.method_by_signature(method.name(), method.descriptor()).unwrap().is_private())
+                        };
+                        if let Some(index) = index {
+                            frame.quicken(INVOKEVIRTUAL_QUICK(index, object_offset));
+                            frame.ip -= 1;
+                            continue;
+                        } else if is_private {
+                            let id = self.link_method(&class, method.name(), method.descriptor());
+                            frame.quicken(INVOKESPECIAL_QUICK(id));
+                            frame.ip -= 1;
+                            continue;
+                        }
+                    }
+                    if frame.nth_from_top(object_offset) == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    // the methods of arrays are the methods of Object
+                    let class = match *VM::get_object(&mut self.heap, frame.nth_from_top(object_offset)) {
+                        Object::Instance(ref instance) => instance.class().to_owned(),
+                        Object::Array(..) => OBJECT_NAME.to_owned(),
+                    };
+                    match self.select_method(&class, method) {
+                        Ok(dest_class) => invoke!(&dest_class, method.name(), method.descriptor()),
+                        Err(error) => throw_new!(error),
                     }
                 }
-                INVOKEVIRTUAL(method) => {
-                    let dest_class;
-                    {
-                        let object_offset = MethodDescriptor::parse(method.descriptor()).unwrap().words_for_params();
-                        // TODO throw null pointer exception
-                        let object = VM::get_instance(&mut self.heap, frame.nth_from_top(object_offset));
-                        dest_class = Class::find_first_super_class_with_method(object.class(),
+                INVOKEVIRTUAL_QUICK(index, object_offset) => {
+                    let object = frame.nth_from_top(object_offset);
+                    if object == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    let class = self.class_id_of(object);
+                    let method = self.classes[class].vtable[index];
+                    if self.methods[method].is_abstract {
+                        throw_new!(ABSTRACT_METHOD_ERROR);
+                    } else {
+                        invoke_linked!(method);
+                    }
+                }
+                INVOKEDYNAMIC(ref call_site) => {
+                    match self.link_call_site(&frame.current_class, call_site) {
+                        Ok(lambda_class) => invoke!(&lambda_class, lambda::LAMBDA_FACTORY_NAME, call_site.descriptor()),
+                        Err(error) => throw_new!(error),
+                    }
+                }
+                INVOKEINTERFACE(ref method, _) => {
+                    // the interface method is resolved once, the implementation is selected for each object
+                    // TODO replace unwraps with throw class loading exception
+                    match Class::find_interface_method(method.class(),
+                                                       method.name(),
+                                                       method.descriptor(),
+                                                       &mut self.classloader)
+                        .unwrap() {
+                        Some(interface) => {
+                            let id = self.link_method(&interface, method.name(), method.descriptor());
+                            frame.quicken(INVOKEINTERFACE_QUICK(id));
+                            frame.ip -= 1;
+                        }
+                        None => throw_new!(NO_SUCH_METHOD_ERROR),
+                    }
+                }
+                INVOKEINTERFACE_QUICK(method) => {
+                    let object = frame.nth_from_top(self.methods[method].words_for_params - 1);
+                    if object == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                        continue;
+                    }
+                    let class = self.class_id_of(object);
+                    match self.select_interface_method(class, method) {
+                        Ok(selected) => invoke_linked!(selected),
+                        Err(error) => throw_new!(error),
+                    }
+                }
+                INVOKESTATIC(ref method) => {
+                    // the class or interface, which declares the method, gets initialized
+                    // TODO replace unwraps with throw class loading exception
+                    let dest_class = Class::find_first_super_class_with_method(method.class(),
                                                                                method.name(),
                                                                                method.descriptor(),
                                                                                &mut self.classloader)
-                            .unwrap()
-                            .unwrap();
+                        .unwrap()
+                        .unwrap();
+                    initialize!(&dest_class);
+                    let id = self.link_method(&dest_class, method.name(), method.descriptor());
+                    if self.class_state(&dest_class).initialization == Initialization::Initialized {
+                        frame.quicken(INVOKESTATIC_QUICK(id));
+                    }
+                    invoke_linked!(id);
+                }
+                INVOKESTATIC_QUICK(method) => invoke_linked!(method),
+
+                MONITORENTER => {
+                    let object = frame.pop();
+                    if object == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                    } else {
+                        self.enter_monitor(object, 1);
+                    }
+                }
+                MONITOREXIT => {
+                    let object = frame.pop();
+                    if object == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                    } else if !self.monitors.exit(object, self.current_thread) {
+                        throw_new!(ILLEGAL_MONITOR_STATE_EXCEPTION);
+                    }
+                }
+
+                ATHROW => {
+                    let exception = frame.pop();
+                    if exception == 0 {
+                        throw_new!(NULL_POINTER_EXCEPTION);
+                    } else {
+                        throw!(exception);
                     }
-                    self.invoke_method(&dest_class, method.name(), method.descriptor(), &mut frame);
                 }
-                INVOKESTATIC(method) => {
-                    self.invoke_method_ref(&method, &mut frame);
+                ref c => panic!("Not implemented Instruction {:?}", c),
+            }
+        }
+    }
+}
+
+impl JavaThread {
+    fn new(object: i32, frame: Frame) -> JavaThread {
+        JavaThread {
+            object: object,
+            state: ThreadState::Runnable,
+            frames: Vec::new(),
+            frame: Some(frame),
+            uncaught_exception: None,
+        }
+    }
+}
+
+impl LinkedMethod {
+    fn new(reference: MethodRef, method: &Method, native: Option<NativeMethod>) -> LinkedMethod {
+        let (max_stack, max_locals, code, exception_table) = match method.code() {
+            Some(code) => {
+                (code.max_stack(), code.max_locals(), code.code().to_vec(), code.exception_table().clone())
+            }
+            None => (0, 0, Vec::new(), Vec::new().into()),
+        };
+        LinkedMethod {
+            descriptor: MethodDescriptor::parse(reference.descriptor()).expect("the descriptor was checked"),
+            reference: reference,
+            is_static: method.is_static(),
+            is_synchronized: method.access_flags().contains(SYNCHRONIZED),
+            is_native: method.access_flags().contains(NATIVE),
+            is_abstract: method.is_abstract(),
+            native: native,
+            words_for_params: method.words_for_params(),
+            max_stack: max_stack,
+            max_locals: max_locals,
+            code: Rc::new(RefCell::new(code)),
+            exception_table: exception_table,
+        }
+    }
+}
+
+impl ClassState {
+    /// static fields get their ConstantValue or the default value
+    fn new(class: &Class) -> ClassState {
+        let mut static_fields = Vec::new();
+        for field in class.static_fields() {
+            match field.constant_value() {
+                Some(&ConstantValue::Int(i)) => static_fields.push(i),
+                Some(&ConstantValue::Float(f)) => static_fields.push(conv!(f)),
+                Some(&ConstantValue::Long(l)) => {
+                    let words: [i32; 2] = conv!(l);
+                    static_fields.extend_from_slice(&words);
+                }
+                Some(&ConstantValue::Double(d)) => {
+                    let words: [i32; 2] = conv!(d);
+                    static_fields.extend_from_slice(&words);
                 }
-                c => panic!("Not implemented Instruction {:?}", c),
+                // strings are objects, so they are created by the vm
+                Some(&ConstantValue::String(..)) |
+                None => static_fields.extend(iter::repeat(0).take(field.size())),
             }
         }
+        ClassState {
+            name: class.name().to_owned(),
+            initialization: Initialization::NotInitialized,
+            static_fields: static_fields.into_boxed_slice(),
+            itable: HashMap::new(),
+            vtable: Vec::new(),
+            call_sites: HashMap::new(),
+        }
     }
 }
 
 impl Frame {
-    fn dummy_frame(stack_size: usize) -> Frame {
+    /// creates a frame, which doesn't belong to a method of a class file
+    fn synthetic_frame(code: Vec<Instruction>, stack_size: usize, class: &str) -> Frame {
         let mut stack = Vec::with_capacity(stack_size);
         stack.resize(stack_size, 0);
         Frame {
@@ -627,18 +2351,55 @@ impl Frame {
             sp: 0,
             stack: stack,
             local_vars: Vec::new(),
-            code: Vec::new(),
-            current_class: "".to_owned(),
+            code: Rc::new(RefCell::new(code)),
+            exception_table: Vec::new().into(),
+            current_class: class.to_owned(),
+            method: None,
+            initializes_class: false,
+            monitor: None,
+            quickened: None,
         }
     }
 
-    #[inline(always)]
-    fn next_instruction(&mut self) -> Instruction {
-        let instruction = self.code[self.ip].clone();
-        self.ip += 1;
-        instruction
+    /// creates a frame, which invokes the virtual methods with their arguments one after the other
+    fn callback_frame(callbacks: Vec<(MethodRef, Vec<i32>)>) -> Frame {
+        let mut code = Vec::new();
+        let mut stack_size = 2;
+        for &(ref method, ref args) in &callbacks {
+            code.push(INVOKEVIRTUAL(method.clone()));
+            match MethodDescriptor::parse(method.descriptor()).unwrap().ret_type() {
+                Some(typ) if typ.word_size() == 2 => code.push(POP2),
+                Some(..) => code.push(POP),
+                None => {}
+            }
+            stack_size += args.len();
+        }
+        code.push(RETURN(None));
+        let mut frame = Frame::synthetic_frame(code, stack_size, "");
+        // the arguments of the first callback are on top of the stack
+        for &(_, ref args) in callbacks.iter().rev() {
+            for arg in args {
+                frame.push(*arg);
+            }
+        }
+        frame
+    }
+
+    /// creates the first frame of the vm, which calls the static method with args
+    /// the method is invoked by an instruction, so its class gets initialized
+    fn bootstrap_frame(method: MethodRef, args: &[i32]) -> Frame {
+        let mut frame = Frame::synthetic_frame(vec![INVOKESTATIC(method), RETURN(None)], args.len(), "");
+        for arg in args {
+            frame.push(*arg);
+        }
+        frame
     }
 
+    /// replaces the last executed instruction, which resolved its symbolic reference, by its quick variant
+    /// the code is shared, so the other frames and later invocations of the method execute the quick variant,
+    /// after the frame fetched its next instruction
+    fn quicken(&mut self, instruction: Instruction) { self.quickened = Some((self.ip - 1, instruction)); }
+
     #[inline(always)]
     fn push(&mut self, val: i32) {
         self.stack[self.sp] = val;
@@ -670,8 +2431,20 @@ impl Frame {
     #[inline(always)]
     fn nth_from_top(&self, n: usize) -> i32 { self.stack[self.sp - 1 - n] }
 
+    /// checks, if the value of type typ on top of the stack is zero
+    #[inline(always)]
+    fn top_is_zero(&self, typ: Type) -> bool {
+        if typ.is_double_sized() {
+            self.nth_from_top(0) == 0 && self.nth_from_top(1) == 0
+        } else {
+            self.top() == 0
+        }
+    }
+
     #[inline(always)]
-    fn store(&mut self, index: LocalVarRef, val: i32) { self.local_vars[index as usize] = val; }
+    fn store(&mut self, index: LocalVarRef, val: i32) {
+        self.local_vars[index as usize] = val;
+    }
 
     #[inline(always)]
     fn store2(&mut self, index: LocalVarRef, val: [i32; 2]) {
@@ -684,24 +2457,88 @@ impl Frame {
 
     #[inline(always)]
     fn load2(&mut self, index: LocalVarRef) -> [i32; 2] { [self.load(index), self.load(index + 1)] }
+
+    /// adds the references in the local variables and on the stack to roots
+    /// map gives the types of the slots, all slots are checked at runtime without it
+    fn add_roots(&self, map: Option<&ReferenceMap>, heap: &Heap, roots: &mut Vec<i32>) {
+        // the frame executes the instruction before ip or waits for the method invoked by it
+        let state = map.and_then(|m| m.state(self.ip.saturating_sub(1)));
+        let (locals, stack) = match state {
+            Some(s) => (&s.locals()[..], &s.stack()[..]),
+            None => (&[][..], &[][..]),
+        };
+        let slots = self.local_vars.iter().zip(locals.iter().map(Some).chain(iter::repeat(None)))
+            .chain(self.stack[..self.sp].iter().zip(stack.iter().map(Some).chain(iter::repeat(None))));
+        for (&value, slot) in slots {
+            let is_reference = match slot {
+                Some(&Slot::Reference) => value != 0,
+                Some(&Slot::Value) | Some(&Slot::Unused) => false,
+                Some(&Slot::Conflict) | None => heap.is_object(value),
+            };
+            if is_reference {
+                roots.push(value);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::cmp::max;
+    use thread::Scheduler;
 
     macro_rules! arg1 { ($val: expr) => {{vec![unsafe {mem::transmute::<_, i32>($val)}]}} }
     macro_rules! arg2 { ($val: expr) => {{unsafe {mem::transmute::<_, [i32; 2]>($val)}.to_vec()}} }
 
     const TEST_CLASS: &'static str = "com/mackie/rustyjvm/TestVM";
 
+    /// creates a vm, which records the calls of the native methods of the test classes
+    fn test_vm(max_heap_size: usize) -> VM {
+        let classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
+        let mut vm = VM::new(classloader, max_heap_size, Scheduler::new(super::super::DEFAULT_QUANTUM, None));
+        let natives = [("nativeBoolean", "(Z)V"),
+                       ("nativeChar", "(C)V"),
+                       ("nativeByte", "(B)V"),
+                       ("nativeShort", "(S)V"),
+                       ("nativeInt", "(I)V"),
+                       ("nativeLong", "(J)V"),
+                       ("nativeDouble", "(D)V"),
+                       ("nativeFloat", "(F)V"),
+                       ("nativeString", "(Ljava/lang/String;)V")];
+        // the hand written class files declare their own natives
+        for class in &[TEST_CLASS, "TestSubroutine"] {
+            for &(name, descriptor) in natives.iter() {
+                vm.natives.register(class, name, descriptor, move |vm, args| {
+                    let words = args.iter().flat_map(|a| a.to_words()).collect();
+                    vm.native_calls.push((name.to_owned(), descriptor.to_owned(), words));
+                    Ok(None)
+                });
+            }
+        }
+        vm
+    }
+
     fn run(method: &str, native_calls: Vec<(&str, Vec<i32>)>) {
-        let classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
-        let mut vm = VM::new(classloader);
-        let mut start_frame = Frame::dummy_frame(0);
-        vm.invoke_method(TEST_CLASS, method, "()V", &mut start_frame);
-        vm.run(start_frame);
+        let start_frame = Frame::bootstrap_frame(MethodRef::new(method, TEST_CLASS, "()V"), &[]);
+        run_frame(method, start_frame, native_calls);
+    }
+
+    /// runs instructions, which javac does not generate
+    fn run_code(name: &str, code: Vec<Instruction>, stack_size: usize, native_calls: Vec<(&str, Vec<i32>)>) {
+        run_frame(name, Frame::synthetic_frame(code, stack_size, TEST_CLASS), native_calls);
+    }
+
+    fn run_frame(method: &str, start_frame: Frame, native_calls: Vec<(&str, Vec<i32>)>) {
+        run_frame_with_heap_size(method, start_frame, super::super::DEFAULT_MAX_HEAP_SIZE, native_calls);
+    }
+
+    fn run_frame_with_heap_size(method: &str,
+                                start_frame: Frame,
+                                max_heap_size: usize,
+                                native_calls: Vec<(&str, Vec<i32>)>) {
+        let mut vm = test_vm(max_heap_size);
+        vm.run(start_frame).expect("uncaught exception");
 
         for index in 0..max(native_calls.len(), vm.native_calls.len()) {
             if index >= native_calls.len() {
@@ -723,13 +2560,13 @@ mod tests {
 
     #[test]
     fn print_class() {
-        let mut classloader = ClassLoader::new(super::super::CLASSFILE_DIR);
+        let mut classloader = ClassLoader::new(super::super::TEST_CLASSPATH);
         let class = classloader.load_class(TEST_CLASS).unwrap();
         for method in class.methods() {
             println!("Method {} {}:", method.descriptor(), method.name());
             match method.code() {
                 Some(c) => {
-                    for instr in c.code() {
+                    for instr in c.code().iter() {
                         println!("  {:?}", instr);
                     }
                 }
@@ -808,6 +2645,76 @@ mod tests {
                  ("nativeInt", arg1!(42))]);
     }
 
+    #[test]
+    fn quickening() {
+        run("quickened",
+            vec![("nativeInt", arg1!(200)),
+                 ("nativeLong", arg2!(0i64)),
+                 ("nativeLong", arg2!(100i64)),
+                 ("nativeInt", arg1!(100)),
+                 ("nativeInt", arg1!(200)),
+                 ("nativeLong", arg2!(11i64)),
+                 ("nativeInt", arg1!(300)),
+                 ("nativeLong", arg2!(111i64)),
+                 ("nativeLong", arg2!(111i64)),
+                 ("nativeInt", arg1!(200)),
+                 ("nativeLong", arg2!(2i64)),
+                 ("nativeLong", arg2!(102i64)),
+                 ("nativeLong", arg2!(6i64)),
+                 ("nativeDouble", arg2!(1.5f64)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(1))]);
+
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let method = MethodRef::new("quickened", TEST_CLASS, "()V");
+        vm.run(Frame::bootstrap_frame(method.clone(), &[])).expect("uncaught exception");
+        let code = vm.methods[vm.method_ids[&method]].code.borrow();
+        // all instructions were executed, so none of them refers to a field or method symbolically
+        assert!(code.iter().all(|i| match *i {
+            GETFIELD(..) | PUTFIELD(..) | GETSTATIC(..) | PUTSTATIC(..) | INVOKESPECIAL(..) | INVOKESTATIC(..) |
+            INVOKEVIRTUAL(..) | INVOKEINTERFACE(..) => false,
+            _ => true,
+        }));
+        assert!(code.iter().any(|i| match *i {
+            GETFIELD_QUICK(..) => true,
+            _ => false,
+        }));
+        assert!(code.iter().any(|i| match *i {
+            PUTSTATIC_QUICK(..) => true,
+            _ => false,
+        }));
+        assert!(code.iter().any(|i| match *i {
+            INVOKEVIRTUAL_QUICK(..) => true,
+            _ => false,
+        }));
+        assert!(code.iter().any(|i| match *i {
+            INVOKEINTERFACE_QUICK(..) => true,
+            _ => false,
+        }));
+        // the class code isn't changed
+        let class = vm.classloader.load_class(TEST_CLASS).unwrap();
+        let original = class.method_by_signature("quickened", "()V").unwrap().code().unwrap().code();
+        assert!(original.iter().any(|i| match *i {
+            INVOKEVIRTUAL(..) => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn vtables() {
+        run("vtables",
+            vec![("nativeInt", arg1!(11)),
+                 ("nativeInt", arg1!(12)),
+                 ("nativeInt", arg1!(22)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(23))]);
+    }
+
     #[test]
     fn castinstanceof() {
         run("castinstanceof",
@@ -1212,4 +3119,529 @@ mod tests {
                  ("nativeInt", arg1!(200))]);
     }
 
+    #[test]
+    fn exceptions() {
+        run("exceptions",
+            vec![("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(4)),
+                 ("nativeInt", arg1!(5)),
+                 ("nativeInt", arg1!(6)),
+                 ("nativeInt", arg1!(7)),
+                 ("nativeInt", arg1!(8)),
+                 ("nativeInt", arg1!(9)),
+                 ("nativeInt", arg1!(10)),
+                 ("nativeInt", arg1!(11)),
+                 ("nativeLong", arg2!(12i64))]);
+    }
+
+    #[test]
+    fn vm_exceptions() {
+        run("vmExceptions",
+            vec![("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(4)),
+                 ("nativeInt", arg1!(5)),
+                 ("nativeInt", arg1!(6)),
+                 ("nativeInt", arg1!(7)),
+                 ("nativeInt", arg1!(8)),
+                 ("nativeInt", arg1!(9)),
+                 ("nativeInt", arg1!(10)),
+                 ("nativeInt", arg1!(11)),
+                 ("nativeInt", arg1!(12)),
+                 ("nativeInt", arg1!(13)),
+                 ("nativeInt", arg1!(14)),
+                 ("nativeInt", arg1!(15)),
+                 ("nativeInt", arg1!(16)),
+                 ("nativeLong", arg2!(1i64)),
+                 ("nativeInt", arg1!(17)),
+                 ("nativeInt", arg1!(18)),
+                 ("nativeInt", arg1!(19)),
+                 ("nativeInt", arg1!(20)),
+                 ("nativeInt", arg1!(21)),
+                 ("nativeInt", arg1!(22)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(23))]);
+    }
+
+    #[test]
+    fn statics() {
+        run("statics",
+            vec![("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(10)),
+                 ("nativeLong", arg2!(0x100000001i64)),
+                 ("nativeDouble", arg2!(1.5f64)),
+                 ("nativeDouble", arg2!(3f64)),
+
+                 ("nativeInt", arg1!(20)),
+                 ("nativeInt", arg1!(21)),
+                 ("nativeInt", arg1!(30)),
+                 ("nativeInt", arg1!(31)),
+                 ("nativeInt", arg1!(31)),
+                 ("nativeInt", arg1!(40)),
+                 ("nativeInt", arg1!(40)),
+                 ("nativeInt", arg1!(40)),
+
+                 ("nativeInt", arg1!(50)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(51)),
+                 ("nativeInt", arg1!(52)),
+                 ("nativeInt", arg1!(60)),
+                 ("nativeInt", arg1!(61))]);
+    }
+
+    #[test]
+    fn interfaces() {
+        run("interfaces",
+            vec![("nativeInt", arg1!(3)),
+                 ("nativeLong", arg2!(6i64)),
+                 ("nativeLong", arg2!(10i64)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeLong", arg2!(42i64)),
+                 ("nativeInt", arg1!(1))]);
+    }
+
+    #[test]
+    fn strings() {
+        run("strings",
+            vec![("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(0)),
+                 ("nativeBoolean", arg1!(0)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(0)),
+                 ("nativeInt", arg1!(5)),
+                 ("nativeChar", arg1!('a' as i32)),
+
+                 ("nativeInt", arg1!(6)),
+                 ("nativeChar", arg1!('a' as i32)),
+                 ("nativeChar", arg1!(0)),
+                 ("nativeChar", arg1!(0xD83D)),
+                 ("nativeChar", arg1!(0xDE00)),
+                 ("nativeChar", arg1!(0xD800)),
+                 ("nativeChar", arg1!(0xE4)),
+
+                 ("nativeInt", arg1!(1))]);
+    }
+
+    #[test]
+    fn switches() {
+        run("switches",
+            vec![("nativeInt", arg1!(14)),
+                 ("nativeInt", arg1!(10)),
+                 ("nativeInt", arg1!(11)),
+                 ("nativeInt", arg1!(12)),
+                 ("nativeInt", arg1!(14)),
+                 ("nativeInt", arg1!(13)),
+                 ("nativeInt", arg1!(14)),
+                 ("nativeInt", arg1!(14)),
+                 ("nativeInt", arg1!(14)),
+
+                 ("nativeInt", arg1!(20)),
+                 ("nativeInt", arg1!(21)),
+                 ("nativeInt", arg1!(25)),
+                 ("nativeInt", arg1!(22)),
+                 ("nativeInt", arg1!(23)),
+                 ("nativeInt", arg1!(24)),
+                 ("nativeInt", arg1!(25)),
+
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(5)),
+
+                 ("nativeInt", arg1!(0xFF0000)),
+                 ("nativeInt", arg1!(0x00FF00)),
+                 ("nativeInt", arg1!(0x0000FF)),
+                 ("nativeInt", arg1!(-1))]);
+    }
+
+    #[test]
+    fn stack() {
+        run("stack",
+            vec![("nativeInt", arg1!(3)),
+                 ("nativeInt", arg1!(4)),
+                 ("nativeLong", arg2!(1005i64)),
+                 ("nativeDouble", arg2!(6.5f64)),
+                 ("nativeInt", arg1!(5)),
+                 ("nativeLong", arg2!(7i64)),
+                 ("nativeDouble", arg2!(2.5f64)),
+                 ("nativeLong", arg2!(1008i64)),
+                 ("nativeDouble", arg2!(9.5f64)),
+                 ("nativeLong", arg2!(1010i64)),
+                 ("nativeDouble", arg2!(11.5f64)),
+
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+
+                 ("nativeLong", arg2!(-4i64)),
+                 ("nativeDouble", arg2!(-1.5f64)),
+
+                 ("nativeInt", arg1!(5)),
+                 ("nativeInt", arg1!(6)),
+                 ("nativeLong", arg2!(10i64)),
+                 ("nativeLong", arg2!(11i64)),
+                 ("nativeLong", arg2!(-1i64)),
+                 ("nativeLong", arg2!(0i64)),
+                 ("nativeDouble", arg2!(-0.5f64)),
+                 ("nativeDouble", arg2!(-1.5f64)),
+                 ("nativeLong", arg2!(1000i64))]);
+    }
+
+    #[test]
+    fn swap() {
+        let native_int = MethodRef::new("nativeInt", TEST_CLASS, "(I)V");
+        run_code("swap",
+                 vec![BIPUSH(1), BIPUSH(2), SWAP,
+                      INVOKESTATIC(native_int.clone()), INVOKESTATIC(native_int), RETURN(None)],
+                 2,
+                 vec![("nativeInt", arg1!(1)), ("nativeInt", arg1!(2))]);
+    }
+
+    /// runs instructions, which throw an exception, and returns the class of the exception
+    fn uncaught_code(code: Vec<Instruction>, stack_size: usize) -> String {
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let exception = match vm.run(Frame::synthetic_frame(code, stack_size, TEST_CLASS)) {
+            Err(Abort::UncaughtException(exception)) => exception,
+            result => panic!("Expected an uncaught exception, but got {:?}", result),
+        };
+        vm.heap().get(exception).typ().get_class().unwrap().to_owned()
+    }
+
+    #[test]
+    fn field_resolution() {
+        // javac only references existing fields, but the class may have changed since the compilation
+        let node = "com/mackie/rustyjvm/TestVMNode";
+        let missing = FieldRef::new("missing", node, "I").unwrap();
+        assert_eq!(uncaught_code(vec![ACONST_NULL, GETFIELD(missing.clone()), RETURN(None)], 1),
+                   NO_SUCH_FIELD_ERROR);
+        assert_eq!(uncaught_code(vec![GETSTATIC(missing), RETURN(None)], 1), NO_SUCH_FIELD_ERROR);
+        let value = FieldRef::new("value", node, "I").unwrap();
+        let root = FieldRef::new("root", node, "Lcom/mackie/rustyjvm/TestVMNode;").unwrap();
+        assert_eq!(uncaught_code(vec![ACONST_NULL, ACONST_NULL, PUTFIELD(root), RETURN(None)], 2),
+                   INCOMPATIBLE_CLASS_CHANGE_ERROR);
+        assert_eq!(uncaught_code(vec![BIPUSH(1), PUTSTATIC(value), RETURN(None)], 1), INCOMPATIBLE_CLASS_CHANGE_ERROR);
+    }
+
+    #[test]
+    fn garbage_collection() {
+        // the program allocates more than fits into the heap, so the garbage must be collected
+        run_frame_with_heap_size("garbageCollection",
+                                 Frame::bootstrap_frame(MethodRef::new("garbageCollection", TEST_CLASS, "()V"), &[]),
+                                 1 << 20,
+                                 vec![("nativeInt", arg1!(149500)),
+                                      ("nativeInt", arg1!(1)),
+                                      ("nativeBoolean", arg1!(1))]);
+    }
+
+    #[test]
+    fn out_of_memory() {
+        run_frame_with_heap_size("outOfMemory",
+                                 Frame::bootstrap_frame(MethodRef::new("outOfMemory", TEST_CLASS, "()V"), &[]),
+                                 1 << 20,
+                                 vec![("nativeInt", arg1!(1)),
+                                      ("nativeBoolean", arg1!(1)),
+                                      ("nativeInt", arg1!(1000))]);
+    }
+
+    /// runs the method with the scheduler and returns the calls of the natives
+    fn run_scheduled(method: &str, scheduler: Scheduler) -> Vec<(String, Vec<i32>)> {
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        vm.scheduler = scheduler;
+        vm.run(Frame::bootstrap_frame(MethodRef::new(method, TEST_CLASS, "()V"), &[])).expect("uncaught exception");
+        vm.native_calls.into_iter().map(|(name, _, args)| (name, args)).collect()
+    }
+
+    #[test]
+    fn threads() {
+        let calls = vec![("nativeInt", arg1!(3000)),
+                         ("nativeBoolean", arg1!(0)),
+                         ("nativeInt", arg1!(1)),
+                         ("nativeInt", arg1!(55)),
+                         ("nativeInt", arg1!(2)),
+                         ("nativeBoolean", arg1!(1)),
+                         ("nativeBoolean", arg1!(1))];
+        run("threads", calls.clone());
+        for seed in 0..5 {
+            let scheduled = run_scheduled("threads", Scheduler::new(7, Some(seed)));
+            assert_eq!(scheduled.iter().map(|&(ref name, ref args)| (name.as_str(), args.clone())).collect::<Vec<_>>(),
+                       calls);
+        }
+    }
+
+    #[test]
+    fn blocking_initialization() {
+        let calls = vec![("nativeInt".to_owned(), arg1!(60)), ("nativeInt".to_owned(), arg1!(60))];
+        assert_eq!(run_scheduled("blockingInitialization", Scheduler::new(100000, None)), calls);
+        for seed in 0..5 {
+            assert_eq!(run_scheduled("blockingInitialization", Scheduler::new(3, Some(seed))), calls);
+        }
+    }
+
+    #[test]
+    fn scheduling() {
+        for seed in 0..5 {
+            let calls = run_scheduled("races", Scheduler::new(5, Some(seed)));
+            assert_eq!(calls[0], ("nativeInt".to_owned(), arg1!(200)));
+            assert_eq!(calls, run_scheduled("races", Scheduler::new(5, Some(seed))));
+        }
+        // the threads run one after the other with a long quantum
+        assert_eq!(run_scheduled("races", Scheduler::new(100000, None))[1], ("nativeInt".to_owned(), arg1!(200)));
+    }
+
+    #[test]
+    fn deadlock() {
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let result = vm.run(Frame::bootstrap_frame(MethodRef::new("deadlock", TEST_CLASS, "()V"), &[]));
+        assert_eq!(result, Err(Abort::Deadlock));
+        assert_eq!(vm.find_deadlocks(), vec![vec![0, 1]]);
+        let dump = vm.thread_dump();
+        assert!(dump.starts_with("Full thread dump\n"));
+        assert!(dump.contains("\nthread 0 (main): blocked on java.lang.Object@"));
+        assert!(dump.contains(concat!("    at com.mackie.rustyjvm.TestVM.deadlock()V (bytecode index 58)\n",
+                                      "    at <generated by the vm>\n")));
+        assert!(dump.contains("\nthread 1: blocked on java.lang.Object@"));
+        assert!(dump.contains("    at com.mackie.rustyjvm.TestVMLocker.run()V (bytecode index 18)\n"));
+        assert!(dump.contains("\nFound a deadlock of 2 threads:\n\
+                               thread 0 waits for the monitor of java.lang.Object@"));
+        for seed in 0..5 {
+            let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+            vm.scheduler = Scheduler::new(3, Some(seed));
+            let result = vm.run(Frame::bootstrap_frame(MethodRef::new("deadlock", TEST_CLASS, "()V"), &[]));
+            assert_eq!(result, Err(Abort::Deadlock));
+            assert_eq!(vm.find_deadlocks(), vec![vec![0, 1]]);
+        }
+        // a single thread can wait forever too
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let result = vm.run(Frame::bootstrap_frame(MethodRef::new("waitForever", TEST_CLASS, "()V"), &[]));
+        assert_eq!(result, Err(Abort::Deadlock));
+        assert!(vm.thread_dump().contains("\nthread 0 (main): waiting on java.lang.Object@"));
+    }
+
+    #[test]
+    fn stack_traces() {
+        run("stackTraces",
+            vec![("nativeInt", arg1!(2)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(15))]);
+    }
+
+    #[test]
+    fn uncaught_exceptions() {
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let exception = match vm.run(Frame::bootstrap_frame(MethodRef::new("uncaught", TEST_CLASS, "()V"), &[])) {
+            Err(Abort::UncaughtException(exception)) => exception,
+            result => panic!("Expected an uncaught exception, but got {:?}", result),
+        };
+        let report = vm.exception_report(exception);
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "java.lang.RuntimeException: thrown");
+        assert!(lines[1].starts_with("\tat com.mackie.rustyjvm.TestVM.throwing(TestVM.java:"));
+        assert!(lines[2].starts_with("\tat com.mackie.rustyjvm.TestVM.uncaught(TestVM.java:"));
+        let line = |l: &str| l.trim_right_matches(')').rsplit(':').next().unwrap().parse::<u32>().unwrap();
+        assert_eq!(line(lines[2]) - line(lines[1]), 27);
+
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        let exception = match vm.run(Frame::bootstrap_frame(MethodRef::new("causes", TEST_CLASS, "()V"), &[])) {
+            Err(Abort::UncaughtException(exception)) => exception,
+            result => panic!("Expected an uncaught exception, but got {:?}", result),
+        };
+        let report = vm.exception_report(exception);
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "java.lang.RuntimeException: wrapped");
+        assert!(lines[1].starts_with("\tat com.mackie.rustyjvm.TestVM.wrapping(TestVM.java:"));
+        assert!(lines[2].starts_with("\tat com.mackie.rustyjvm.TestVM.causes(TestVM.java:"));
+        assert_eq!(lines[3], "Caused by: java.lang.RuntimeException: thrown");
+        assert!(lines[4].starts_with("\tat com.mackie.rustyjvm.TestVM.throwing(TestVM.java:"));
+        assert!(lines[5].starts_with("\tat com.mackie.rustyjvm.TestVM.wrapping(TestVM.java:"));
+        assert_eq!(lines[6], "\t... 1 more");
+    }
+
+    #[test]
+    fn natives() {
+        run("natives",
+            vec![("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(0)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(16)),
+                 ("nativeChar", arg1!('.' as i32)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(1235)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeLong", arg2!(8i64)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(1)),
+                 ("nativeInt", arg1!(2)),
+                 ("nativeInt", arg1!(3)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(0x3f800000)),
+                 ("nativeFloat", arg1!(2.0f32)),
+                 ("nativeInt", arg1!(0x7fc00000)),
+                 ("nativeLong", arg2!(-2.0f64)),
+                 ("nativeDouble", arg2!(1.0f64)),
+                 ("nativeDouble", arg2!(4.0f64)),
+                 ("nativeDouble", arg2!(1024.0f64)),
+                 ("nativeDouble", arg2!(1.0f64)),
+                 ("nativeDouble", arg2!(-1.0f64)),
+                 ("nativeDouble", arg2!(::std::f64::consts::PI))]);
+    }
+
+    #[test]
+    fn stack_walker() {
+        // the caller of the method, which calls VMStackWalker.getCallingClass
+        run("stackWalker", vec![("nativeBoolean", arg1!(1))]);
+    }
+
+    #[test]
+    fn callbacks() {
+        run("callbacks",
+            vec![("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeBoolean", arg1!(1)),
+                 ("nativeInt", arg1!(4))]);
+    }
+
+    fn run_java8(method: &str, native_calls: Vec<(&str, Vec<i32>)>) {
+        let start_frame = Frame::bootstrap_frame(MethodRef::new(method, "com/mackie/rustyjvm/TestJava8", "()V"), &[]);
+        run_frame(method, start_frame, native_calls);
+    }
+
+    #[test]
+    fn default_methods() {
+        // the interface with the default method gets initialized together with the class
+        run_java8("defaultMethods",
+                  vec![("nativeInt", arg1!(100)),
+                       ("nativeInt", arg1!(11)),
+                       ("nativeInt", arg1!(11)),
+                       ("nativeInt", arg1!(31)),
+                       ("nativeInt", arg1!(31)),
+                       ("nativeInt", arg1!(40)),
+                       ("nativeInt", arg1!(63))]);
+    }
+
+    #[test]
+    fn lambdas() {
+        run_java8("lambdas",
+                  vec![("nativeInt", arg1!(42)),
+                       ("nativeInt", arg1!(15)),
+                       ("nativeInt", arg1!(1)),
+                       ("nativeInt", arg1!(2)),
+                       ("nativeInt", arg1!(1)),
+                       ("nativeInt", arg1!(7)),
+                       ("nativeInt", arg1!(15)),
+                       ("nativeInt", arg1!(15))]);
+    }
+
+    #[test]
+    fn method_references() {
+        run_java8("methodReferences",
+                  vec![("nativeInt", arg1!(15)),
+                       ("nativeInt", arg1!(8)),
+                       ("nativeInt", arg1!(8)),
+                       ("nativeInt", arg1!(7)),
+                       ("nativeInt", arg1!(100)),
+                       ("nativeInt", arg1!(63)),
+                       ("nativeInt", arg1!(9)),
+                       ("nativeInt", arg1!(-1))]);
+        run_java8("alternativeMetafactory",
+                  vec![("nativeInt", arg1!(1)), ("nativeInt", arg1!(7)), ("nativeInt", arg1!(7))]);
+    }
+
+    #[test]
+    fn boxing() {
+        run_java8("boxing",
+                  vec![("nativeInt", arg1!(7)),
+                       ("nativeInt", arg1!(15)),
+                       ("nativeInt", arg1!(1)),
+                       ("nativeInt", arg1!(120)),
+                       ("nativeInt", arg1!(1))]);
+    }
+
+    #[test]
+    fn static_interface_methods() {
+        run_java8("staticInterfaceMethods",
+                  vec![("nativeInt", arg1!(100)), ("nativeInt", arg1!(47)), ("nativeInt", arg1!(0))]);
+    }
+
+    #[test]
+    fn class_literals() {
+        run_java8("classLiterals",
+                  vec![("nativeBoolean", arg1!(1)),
+                       ("nativeBoolean", arg1!(1)),
+                       ("nativeInt", arg1!(36)),
+                       ("nativeBoolean", arg1!(1)),
+                       ("nativeInt", arg1!(19))]);
+    }
+
+    // java/TestSubroutine.class is assembled by hand, because javac doesn't generate jsr and ret anymore
+    fn run_subroutine(method: &str, native_calls: Vec<(&str, Vec<i32>)>) {
+        let start_frame = Frame::bootstrap_frame(MethodRef::new(method, "TestSubroutine", "()V"), &[]);
+        run_frame(method, start_frame, native_calls);
+    }
+
+    #[test]
+    fn subroutines() {
+        // nativeInt(1); jsr sub; nativeInt(3); return; sub: astore_0; nativeInt(2); ret 0
+        run_subroutine("finallyNormal",
+                       vec![("nativeInt", arg1!(1)), ("nativeInt", arg1!(2)), ("nativeInt", arg1!(3))]);
+        // aconst_null; athrow; any handler: astore_0; jsr sub; nativeInt(4); return
+        // sub: astore_1; nativeInt(2); ret 1
+        run_subroutine("finallyException",
+                       vec![("nativeInt", arg1!(2)), ("nativeInt", arg1!(4))]);
+        // jsr outer; nativeInt(5); jsr inner; nativeInt(9); return
+        // outer: astore_0; nativeInt(6); jsr inner; nativeInt(7); ret 0
+        // inner: astore_1; nativeInt(8); ret 1
+        run_subroutine("nested",
+                       vec![("nativeInt", arg1!(6)),
+                            ("nativeInt", arg1!(8)),
+                            ("nativeInt", arg1!(7)),
+                            ("nativeInt", arg1!(5)),
+                            ("nativeInt", arg1!(8)),
+                            ("nativeInt", arg1!(9))]);
+        // jsr_w sub; nativeInt(11); return; sub: wide astore 256; nativeInt(10); wide ret 256
+        run_subroutine("wide", vec![("nativeInt", arg1!(10)), ("nativeInt", arg1!(11))]);
+        // jsr sub; return; sub: iconst_1; swap; dup; astore_0; pop; nativeInt(1); ret 0
+        run_subroutine("movedReturnAddress", vec![("nativeInt", arg1!(1))]);
+    }
+
+    #[test]
+    fn main_args() {
+        let mut vm = test_vm(super::super::DEFAULT_MAX_HEAP_SIZE);
+        assert_eq!(vm.start("com/mackie/rustyjvm/TestVMArgs", &["ab", "\u{e4}\u{1F600}", ""]), Ok(0));
+        assert_eq!(vm.native_calls
+                       .iter()
+                       .map(|&(ref name, _, ref args)| (name.as_str(), args.clone()))
+                       .collect::<Vec<_>>(),
+                   vec![("nativeInt", arg1!(3)),
+                        ("nativeInt", arg1!(2)),
+                        ("nativeChar", arg1!('a' as i32)),
+                        ("nativeChar", arg1!('b' as i32)),
+                        ("nativeInt", arg1!(3)),
+                        ("nativeChar", arg1!(0xE4)),
+                        ("nativeChar", arg1!(0xD83D)),
+                        ("nativeChar", arg1!(0xDE00)),
+                        ("nativeInt", arg1!(0)),
+                        ("nativeBoolean", arg1!(0))]);
+    }
 }